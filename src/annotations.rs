@@ -0,0 +1,195 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::{Result, bail};
+use stash_core::{
+    annotations,
+    library::{Entry, Library},
+    logging, normalize_url,
+};
+
+#[derive(Clone, Debug, Bpaf)]
+pub enum AnnotationsCommand {
+    /// Import highlights from KOReader (`metadata.epub.lua`) or Kobo (`.annot`)
+    /// files, or directories of them, e.g. a mounted e-reader.
+    #[bpaf(command("import"))]
+    Import {
+        #[bpaf(positional("PATH"), some("At least one path is required"))]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Search highlights and their notes.
+    #[bpaf(command("search"))]
+    Search {
+        #[bpaf(positional("QUERY"))]
+        query: String,
+    },
+
+    /// Export highlights as Markdown, of every annotated article
+    /// or just the one at URL.
+    #[bpaf(command("export"))]
+    Export {
+        /// Write to this file instead of stdout.
+        #[bpaf(short, long, argument("PATH"))]
+        output: Option<PathBuf>,
+
+        #[bpaf(positional("URL"))]
+        url: Option<String>,
+    },
+}
+
+pub fn run(command: AnnotationsCommand) -> Result<ExitCode> {
+    match command {
+        AnnotationsCommand::Import { paths } => import(&paths),
+        AnnotationsCommand::Search { query } => search(&query),
+        AnnotationsCommand::Export { output, url } => export(output, url),
+    }
+}
+
+/// Add the highlights in annotation files to their books' library entries.
+fn import(paths: &[PathBuf]) -> Result<ExitCode> {
+    let mut files = vec![];
+    for path in paths {
+        find(path, &mut files)?;
+    }
+    if files.is_empty() {
+        bail!("No KOReader or Kobo annotation files found.");
+    }
+
+    let mut library = Library::load()?;
+    // Books stashed before identifiers were recorded can still be matched,
+    // as long as they're still around.
+    for entry in &mut library.entries {
+        if entry.identifier.is_none() && entry.path.exists() {
+            entry.identifier = annotations::identifier(&entry.path).ok();
+        }
+    }
+
+    let mut imported = 0;
+    for file in &files {
+        let annotations = match annotations::read(file) {
+            Ok(annotations) => annotations,
+            Err(err) => {
+                logging::warn(format!("{err:#}"));
+                continue;
+            }
+        };
+        if annotations.highlights.is_empty() {
+            continue;
+        }
+        match library.book_of(&annotations) {
+            Some(entry) => {
+                let added = entry.annotate(annotations.highlights);
+                if added > 0 {
+                    eprintln!("{added} new highlight(s) of {}", entry.title);
+                }
+                imported += added;
+            }
+            None => logging::warn(format!(
+                "{} isn't of a stashed book, skipping it",
+                file.display()
+            )),
+        }
+    }
+    library.save()?;
+    eprintln!("Imported {imported} highlight(s).");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Collect the annotation files at a path, searching directories recursively.
+fn find(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = fs_err::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            find(&entry, files)?;
+        }
+    } else if annotations::is_annotations(path) {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn search(query: &str) -> Result<ExitCode> {
+    let query = query.to_lowercase();
+    let library = Library::load()?;
+    let mut found = false;
+    for entry in annotated(&library) {
+        for highlight in &entry.highlights {
+            if highlight.text.to_lowercase().contains(&query)
+                || highlight.note.to_lowercase().contains(&query)
+            {
+                found = true;
+                println!("{} ({})", entry.title, entry.url);
+                println!("  > {}", highlight.text);
+                if !highlight.note.is_empty() {
+                    println!("  {}", highlight.note);
+                }
+            }
+        }
+    }
+    Ok(if found {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn export(output: Option<PathBuf>, url: Option<String>) -> Result<ExitCode> {
+    let library = Library::load()?;
+    let url = url.as_deref().map(normalize_url);
+    let entries: Vec<_> = annotated(&library)
+        .filter(|entry| url.as_ref().is_none_or(|url| entry.url == *url))
+        .collect();
+    if entries.is_empty() {
+        bail!("No highlights to export.");
+    }
+    let markdown = entries
+        .into_iter()
+        .map(markdown)
+        .collect::<Vec<_>>()
+        .join("\n");
+    match output {
+        Some(path) => fs_err::write(path, markdown)?,
+        None => print!("{markdown}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Library entries with highlights, most recently stashed first.
+fn annotated(library: &Library) -> impl Iterator<Item = &Entry> {
+    library
+        .entries
+        .iter()
+        .rev()
+        .filter(|entry| !entry.highlights.is_empty())
+}
+
+fn markdown(entry: &Entry) -> String {
+    let mut markdown = format!("# {}\n\n", entry.title);
+    if entry.authors.is_empty() {
+        markdown.push_str(&format!("<{}>\n\n", entry.url));
+    } else {
+        markdown.push_str(&format!("{}, <{}>\n\n", entry.authors, entry.url));
+    }
+    let mut chapter = "";
+    for highlight in &entry.highlights {
+        if !highlight.chapter.is_empty() && highlight.chapter != chapter {
+            chapter = &highlight.chapter;
+            markdown.push_str(&format!("## {chapter}\n\n"));
+        }
+        for line in highlight.text.lines() {
+            markdown.push_str(&format!("> {line}\n"));
+        }
+        markdown.push('\n');
+        if !highlight.note.is_empty() {
+            markdown.push_str(&format!("{}\n\n", highlight.note));
+        }
+    }
+    markdown
+}
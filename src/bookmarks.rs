@@ -0,0 +1,201 @@
+use std::{process::ExitCode, time::Duration};
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use serde_json::{Value, json};
+use stash_core::{
+    Extractor,
+    config::{BookmarkService, Bookmarks, Config},
+    failure::Failure,
+    progress::Progress,
+};
+
+/// Stash the bookmarks with the `[bookmarks]` tag, without previews.
+/// Those that work (or were already stashed) are re-tagged as processed;
+/// those that fail keep their tag, to be tried again next time.
+pub fn run(extractor: &Extractor, config: &Config, progress: &Progress) -> Result<ExitCode> {
+    let Some(settings) = &config.bookmarks else {
+        bail!("No `[bookmarks]` section in config.toml.");
+    };
+    let service = Service::login(settings)?;
+    let bookmarks = service.tagged()?;
+    if bookmarks.is_empty() {
+        eprintln!("No bookmarks tagged `{}`.", settings.tag);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let batch = progress.batch(bookmarks.len());
+    let mut failures = 0;
+    for bookmark in &bookmarks {
+        let url = bookmark["url"].as_str().unwrap_or_default();
+        batch.set_message(url.to_string());
+        let stashed = extractor
+            .fetch_article(url, progress)
+            .and_then(|article| crate::save(article, config, progress))
+            .inspect_err(|err| crate::failed(config, url, err));
+        match stashed {
+            Ok(Some(path)) => progress.status(format!("[ok] {url} -> {}", path.display())),
+            Ok(None) => progress.status(format!("[skipped] {url}: already exists")),
+            Err(err) => {
+                progress.status(format!("[failed] {url}: {err:#}"));
+                failures += 1;
+                batch.inc(1);
+                continue;
+            }
+        }
+        if let Err(err) = service.mark_processed(bookmark) {
+            progress.status(format!("[failed] {url}: Couldn't re-tag it: {err:#}"));
+            failures += 1;
+        }
+        batch.inc(1);
+    }
+    batch.finish_and_clear();
+
+    if failures == 0 {
+        return Ok(ExitCode::SUCCESS);
+    }
+    eprintln!("{failures} of {} bookmarks failed.", bookmarks.len());
+    Ok(ExitCode::from(Failure::GENERIC_CODE))
+}
+
+struct Service<'a> {
+    settings: &'a Bookmarks,
+    base: &'a str,
+    headers: Vec<(&'static str, String)>,
+}
+impl<'a> Service<'a> {
+    fn login(settings: &'a Bookmarks) -> Result<Self> {
+        let base = settings.url.trim_end_matches('/');
+        let headers = match settings.service {
+            BookmarkService::Linkding => {
+                let Some(token) = &settings.token else {
+                    bail!("linkding needs a `token` under `[bookmarks]`.");
+                };
+                vec![("Authorization", format!("Token {token}"))]
+            }
+            BookmarkService::Shiori => {
+                let (Some(username), Some(password)) = (&settings.username, &settings.password)
+                else {
+                    bail!("Shiori needs a `username` and `password` under `[bookmarks]`.");
+                };
+                let resp: Value = ureq::post(&format!("{base}/api/v1/auth/login"))
+                    .timeout(Duration::from_secs(30))
+                    .send_json(json!({
+                        "username": username,
+                        "password": password,
+                        "remember_me": false,
+                    }))
+                    .wrap_err("Couldn't log in to Shiori")
+                    .wrap_err(Failure::Network)?
+                    .into_json()?;
+                let login = &resp["message"];
+                // Older versions of the bookmarks API want the session,
+                // newer ones the token.
+                vec![
+                    ("Authorization", format!("Bearer {}", str(&login["token"]))),
+                    ("X-Session-Id", str(&login["session"]).to_string()),
+                ]
+            }
+        };
+        Ok(Self {
+            settings,
+            base,
+            headers,
+        })
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        self.headers.iter().fold(
+            ureq::request(method, url).timeout(Duration::from_secs(30)),
+            |request, (name, value)| request.set(name, value),
+        )
+    }
+
+    /// All the bookmarks with the tag, as the service returns them.
+    fn tagged(&self) -> Result<Vec<Value>> {
+        let tag = &self.settings.tag;
+        let mut bookmarks = vec![];
+        match self.settings.service {
+            BookmarkService::Linkding => {
+                let mut next = Some(
+                    self.request("GET", &format!("{}/api/bookmarks/", self.base))
+                        .query("q", &format!("#{tag}"))
+                        .query("limit", "100"),
+                );
+                while let Some(request) = next {
+                    let resp: Value = request
+                        .call()
+                        .wrap_err("Couldn't list bookmarks")
+                        .wrap_err(Failure::Network)?
+                        .into_json()?;
+                    bookmarks.extend(resp["results"].as_array().into_iter().flatten().cloned());
+                    // The next page's URL has the query already.
+                    next = resp["next"].as_str().map(|url| self.request("GET", url));
+                }
+            }
+            BookmarkService::Shiori => {
+                let mut page = 1;
+                loop {
+                    let resp: Value = self
+                        .request("GET", &format!("{}/api/bookmarks", self.base))
+                        .query("tags", tag)
+                        .query("page", &page.to_string())
+                        .call()
+                        .wrap_err("Couldn't list bookmarks")
+                        .wrap_err(Failure::Network)?
+                        .into_json()?;
+                    bookmarks.extend(resp["bookmarks"].as_array().into_iter().flatten().cloned());
+                    if page >= resp["maxPage"].as_u64().unwrap_or_default() {
+                        break;
+                    }
+                    page += 1;
+                }
+            }
+        }
+        // Searches can be fuzzier than the tag itself.
+        bookmarks.retain(|bookmark| self.tags(bookmark).any(|name| name == tag));
+        Ok(bookmarks)
+    }
+
+    fn tags<'b>(&self, bookmark: &'b Value) -> impl Iterator<Item = &'b str> {
+        let (field, name) = match self.settings.service {
+            BookmarkService::Linkding => ("tag_names", None),
+            BookmarkService::Shiori => ("tags", Some("name")),
+        };
+        bookmark[field]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(move |tag| name.map_or(tag, |name| &tag[name]))
+            .filter_map(Value::as_str)
+    }
+
+    /// Replace the bookmark's tag with the processed one.
+    fn mark_processed(&self, bookmark: &Value) -> Result<()> {
+        let tags: Vec<&str> = self
+            .tags(bookmark)
+            .filter(|&name| name != self.settings.tag && name != self.settings.processed_tag)
+            .chain([self.settings.processed_tag.as_str()])
+            .collect();
+        match self.settings.service {
+            BookmarkService::Linkding => {
+                let id = bookmark["id"]
+                    .as_u64()
+                    .ok_or_else(|| eyre!("Bookmark has no ID"))?;
+                self.request("PATCH", &format!("{}/api/bookmarks/{id}/", self.base))
+                    .send_json(json!({ "tag_names": tags }))?;
+            }
+            BookmarkService::Shiori => {
+                // Edits replace the whole bookmark.
+                let mut bookmark = bookmark.clone();
+                bookmark["tags"] = tags.iter().map(|name| json!({ "name": name })).collect();
+                self.request("PUT", &format!("{}/api/bookmarks", self.base))
+                    .send_json(bookmark)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn str(value: &Value) -> &str {
+    value.as_str().unwrap_or_default()
+}
@@ -0,0 +1,56 @@
+mod matrix;
+mod telegram;
+
+use std::{path::PathBuf, process::ExitCode};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::Result;
+use stash_core::{Extractor, config::Config, progress::Progress};
+
+#[derive(Clone, Debug, Bpaf)]
+pub enum BotCommand {
+    /// Run the `[telegram]` bot: send it links in a chat,
+    /// and it replies with their books.
+    #[bpaf(command("telegram"))]
+    Telegram,
+
+    /// Watch the `[matrix]` rooms for links,
+    /// and reply with their books.
+    #[bpaf(command("matrix"))]
+    Matrix,
+}
+
+pub fn run(
+    command: BotCommand,
+    extractor: &Extractor,
+    config: Config,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    match command {
+        BotCommand::Telegram => telegram::run(extractor, &config, progress),
+        BotCommand::Matrix => matrix::run(extractor, config, progress),
+    }
+}
+
+/// Stash a link sent to a bot, adding `tags` to the book's subjects.
+fn stash(
+    url: &str,
+    tags: &[String],
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<Option<PathBuf>> {
+    let stashed = extractor
+        .fetch_article(url, progress)
+        .and_then(|mut article| {
+            article.keywords.extend(tags.iter().cloned());
+            crate::save(article, config, progress)
+        })
+        .inspect_err(|err| crate::failed(config, url, err));
+    match &stashed {
+        Ok(Some(path)) => progress.status(format!("[ok] {url} -> {}", path.display())),
+        Ok(None) => progress.status(format!("[skipped] {url}: already exists")),
+        Err(err) => progress.status(format!("[failed] {url}: {err:#}")),
+    }
+    stashed
+}
@@ -0,0 +1,266 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use stash_core::{
+    Extractor,
+    config::{Config, Matrix},
+    failure::Failure,
+    logging,
+    progress::Progress,
+};
+use url::Url;
+
+/// How long to wait for new events with each sync, in milliseconds.
+const SYNC_TIMEOUT: u64 = 30_000;
+
+#[derive(Deserialize)]
+struct Sync {
+    next_batch: String,
+    #[serde(default)]
+    rooms: Rooms,
+}
+
+#[derive(Deserialize, Default)]
+struct Rooms {
+    #[serde(default)]
+    join: HashMap<String, JoinedRoom>,
+}
+
+#[derive(Deserialize)]
+struct JoinedRoom {
+    timeline: Timeline,
+}
+
+#[derive(Deserialize)]
+struct Timeline {
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    event_id: String,
+    sender: String,
+    #[serde(default)]
+    content: Value,
+}
+
+/// Stash the links posted in the configured rooms, replying to each
+/// message with the books (or why they couldn't be made). Only messages
+/// sent once the bot's running count, not the rooms' history.
+pub fn run(extractor: &Extractor, mut config: Config, progress: &Progress) -> Result<ExitCode> {
+    let Some(matrix) = config.matrix.take() else {
+        bail!("No `[matrix]` section in config.toml.");
+    };
+    if matrix.rooms.is_empty() {
+        bail!("No rooms to watch under `[matrix.rooms]`.");
+    }
+    let client = Client::new(&matrix)?;
+    let me = client.whoami()?;
+    let epub_version = config.epub_version;
+
+    let mut since = client.sync(None)?.next_batch;
+    eprintln!("Watching {} room(s) as {me}...", matrix.rooms.len());
+    loop {
+        let sync = match client.sync(Some(&since)) {
+            Ok(sync) => sync,
+            Err(err) => {
+                logging::warn(format!("Couldn't sync: {err:#}"));
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+        since = sync.next_batch;
+        for (room_id, room) in sync.rooms.join {
+            let Some(settings) = matrix.rooms.get(&room_id) else {
+                continue;
+            };
+            config.epub_version = settings.epub_version.unwrap_or(epub_version);
+            for event in room.timeline.events {
+                // Replies are notices, but skip the bot's own messages regardless.
+                if event.kind != "m.room.message"
+                    || event.sender == me
+                    || event.content["msgtype"] != "m.text"
+                {
+                    continue;
+                }
+                let text = event.content["body"].as_str().unwrap_or_default();
+                for url in crate::mail::links(text) {
+                    let stashed = super::stash(&url, &settings.tags, extractor, &config, progress);
+                    client.respond(&room_id, &event.event_id, &url, stashed);
+                }
+            }
+        }
+    }
+}
+
+struct Client<'a> {
+    config: &'a Matrix,
+    homeserver: Url,
+
+    /// For making each message's transaction ID unique.
+    sent: Cell<u64>,
+    started: u128,
+}
+impl<'a> Client<'a> {
+    fn new(config: &'a Matrix) -> Result<Self> {
+        let homeserver = Url::parse(&config.homeserver).wrap_err("Invalid Matrix homeserver")?;
+        if homeserver.cannot_be_a_base() {
+            bail!("Invalid Matrix homeserver: {}", config.homeserver);
+        }
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        Ok(Self {
+            config,
+            homeserver,
+            sent: Cell::new(0),
+            started,
+        })
+    }
+
+    fn endpoint(&self, path: &[&str]) -> Url {
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .expect("URL can be a base")
+            .pop_if_empty()
+            .push("_matrix")
+            .extend(path);
+        url
+    }
+
+    fn request(&self, method: &str, path: &[&str]) -> ureq::Request {
+        ureq::request_url(method, &self.endpoint(path))
+            .timeout(Duration::from_secs(60))
+            .set(
+                "Authorization",
+                &format!("Bearer {}", self.config.access_token),
+            )
+    }
+
+    fn whoami(&self) -> Result<String> {
+        let resp: Value = self
+            .request("GET", &["client", "v3", "account", "whoami"])
+            .call()
+            .wrap_err("Couldn't log in to Matrix")
+            .wrap_err(Failure::Network)?
+            .into_json()?;
+        Ok(resp["user_id"].as_str().unwrap_or_default().to_string())
+    }
+
+    fn sync(&self, since: Option<&str>) -> Result<Sync> {
+        let rooms: Vec<_> = self.config.rooms.keys().collect();
+        let filter = json!({
+            "presence": {"types": []},
+            "account_data": {"types": []},
+            "room": {
+                "rooms": rooms,
+                "timeline": {"types": ["m.room.message"]},
+                "state": {"types": []},
+                "ephemeral": {"types": []},
+                "account_data": {"types": []},
+            },
+        });
+        let mut request = self
+            .request("GET", &["client", "v3", "sync"])
+            .timeout(Duration::from_millis(SYNC_TIMEOUT + 30_000))
+            .query("filter", &filter.to_string());
+        if let Some(since) = since {
+            request = request
+                .query("since", since)
+                .query("timeout", &SYNC_TIMEOUT.to_string());
+        }
+        Ok(request.call().wrap_err(Failure::Network)?.into_json()?)
+    }
+
+    /// Reply with the book, or a notice of what happened.
+    /// This is best-effort; failures are only warned about.
+    fn respond(&self, room_id: &str, event_id: &str, url: &str, stashed: Result<Option<PathBuf>>) {
+        let notice = |body: String| json!({"msgtype": "m.notice", "body": body});
+        let result = match stashed {
+            // Without a local copy (after uploading), there's nothing to send.
+            Ok(Some(path)) if !path.exists() => {
+                self.send(room_id, event_id, notice(format!("Stashed {url}")))
+            }
+            Ok(Some(path)) => self.send_file(room_id, event_id, &path).or_else(|err| {
+                logging::warn(format!("Couldn't send {}: {err:#}", path.display()));
+                self.send(
+                    room_id,
+                    event_id,
+                    notice(format!("Stashed {url}, but couldn't send it.")),
+                )
+            }),
+            Ok(None) => self.send(room_id, event_id, notice(format!("Already stashed {url}"))),
+            Err(err) => self.send(
+                room_id,
+                event_id,
+                notice(format!("Couldn't stash {url}: {err:#}")),
+            ),
+        };
+        if let Err(err) = result {
+            logging::warn(format!("Couldn't reply: {err:#}"));
+        }
+    }
+
+    fn send(&self, room_id: &str, event_id: &str, mut content: Value) -> Result<()> {
+        content["m.relates_to"] = json!({"m.in_reply_to": {"event_id": event_id}});
+        let sent = self.sent.get() + 1;
+        self.sent.set(sent);
+        let txn_id = format!("stash-{}-{sent}", self.started);
+        self.request(
+            "PUT",
+            &[
+                "client",
+                "v3",
+                "rooms",
+                room_id,
+                "send",
+                "m.room.message",
+                &txn_id,
+            ],
+        )
+        .send_json(content)?;
+        Ok(())
+    }
+
+    fn send_file(&self, room_id: &str, event_id: &str, path: &Path) -> Result<()> {
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let data = fs_err::read(path)?;
+        let resp: Value = self
+            .request("POST", &["media", "v3", "upload"])
+            .timeout(Duration::from_secs(300))
+            .query("filename", &filename)
+            .set("Content-Type", "application/epub+zip")
+            .send_bytes(&data)?
+            .into_json()?;
+        let Some(uri) = resp["content_uri"].as_str() else {
+            bail!("No content URI in the upload's response");
+        };
+        self.send(
+            room_id,
+            event_id,
+            json!({
+                "msgtype": "m.file",
+                "body": filename,
+                "filename": filename,
+                "url": uri,
+                "info": {"mimetype": "application/epub+zip", "size": data.len()},
+            }),
+        )
+    }
+}
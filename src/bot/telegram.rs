@@ -0,0 +1,218 @@
+use std::{
+    path::Path,
+    process::ExitCode,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use serde::Deserialize;
+use serde_json::json;
+use stash_core::{
+    Extractor,
+    config::{Config, Telegram},
+    failure::Failure,
+    logging,
+    progress::Progress,
+};
+
+/// How long to wait for new messages with each request.
+const POLL_TIMEOUT: u64 = 50;
+
+#[derive(Deserialize)]
+struct Response<T> {
+    ok: bool,
+    description: Option<String>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    message_id: i64,
+    chat: Chat,
+    from: Option<User>,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: i64,
+}
+
+/// Stash the links sent to the bot, replying to each message
+/// with the books (or why they couldn't be made).
+pub fn run(extractor: &Extractor, config: &Config, progress: &Progress) -> Result<ExitCode> {
+    let Some(telegram) = &config.telegram else {
+        bail!("No `[telegram]` section in config.toml.");
+    };
+    let bot = Bot(telegram);
+    eprintln!("Waiting for messages...");
+
+    let mut offset = 0;
+    loop {
+        let updates = match bot.updates(offset) {
+            Ok(updates) => updates,
+            Err(err) => {
+                logging::warn(format!("Couldn't check for messages: {err:#}"));
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+        for update in updates {
+            offset = update.update_id + 1;
+            if let Some(message) = update.message {
+                handle(&bot, &message, extractor, config, progress);
+            }
+        }
+    }
+}
+
+fn handle(
+    bot: &Bot,
+    message: &Message,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) {
+    let user = message.from.as_ref().map(|user| user.id);
+    if !bot.0.allowed_users.is_empty()
+        && !user.is_some_and(|user| bot.0.allowed_users.contains(&user))
+    {
+        bot.reply(
+            message,
+            &format!(
+                "Sorry, you're not allowed to use this bot. Your user ID is {}.",
+                user.unwrap_or_default()
+            ),
+        );
+        return;
+    }
+
+    let links = crate::mail::links(message.text.as_deref().unwrap_or_default());
+    if links.is_empty() {
+        bot.reply(
+            message,
+            "Send me links to articles and I'll reply with EPUBs of them.",
+        );
+        return;
+    }
+    for url in links {
+        match super::stash(&url, &[], extractor, config, progress) {
+            // Without a local copy (after uploading), there's nothing to send.
+            Ok(Some(path)) if !path.exists() => bot.reply(message, &format!("Stashed {url}")),
+            Ok(Some(path)) => {
+                if let Err(err) = bot.send_document(message, &path) {
+                    logging::warn(format!("Couldn't send {}: {err:#}", path.display()));
+                    bot.reply(message, &format!("Stashed {url}, but couldn't send it."));
+                }
+            }
+            Ok(None) => bot.reply(message, &format!("Already stashed {url}")),
+            Err(err) => bot.reply(message, &format!("Couldn't stash {url}: {err:#}")),
+        }
+    }
+}
+
+struct Bot<'a>(&'a Telegram);
+impl Bot<'_> {
+    fn endpoint(&self, method: &str) -> String {
+        format!(
+            "{}/bot{}/{method}",
+            self.0.api_url.trim_end_matches('/'),
+            self.0.token
+        )
+    }
+
+    fn updates(&self, offset: i64) -> Result<Vec<Update>> {
+        let resp = ureq::get(&self.endpoint("getUpdates"))
+            .query("offset", &offset.to_string())
+            .query("timeout", &POLL_TIMEOUT.to_string())
+            .query("allowed_updates", r#"["message"]"#)
+            .timeout(Duration::from_secs(POLL_TIMEOUT + 10))
+            .call()
+            .wrap_err(Failure::Network)?;
+        result(resp.into_json()?)
+    }
+
+    /// Replies are best-effort; failures are only warned about.
+    fn reply(&self, message: &Message, text: &str) {
+        let sent = ureq::post(&self.endpoint("sendMessage"))
+            .timeout(Duration::from_secs(30))
+            .send_json(json!({
+                "chat_id": message.chat.id,
+                "text": text,
+                "reply_parameters": {"message_id": message.message_id},
+            }))
+            .map_err(Into::into)
+            .and_then(|resp| result::<serde_json::Value>(resp.into_json()?));
+        if let Err(err) = sent {
+            logging::warn(format!("Couldn't reply: {err:#}"));
+        }
+    }
+
+    fn send_document(&self, message: &Message, path: &Path) -> Result<()> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let boundary = format!("stash-boundary-{nanos:x}");
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().replace('"', "'"))
+            .unwrap_or_default();
+        let mut body = vec![];
+        for (name, value) in [
+            ("chat_id", message.chat.id.to_string()),
+            (
+                "reply_parameters",
+                json!({"message_id": message.message_id}).to_string(),
+            ),
+        ] {
+            body.extend(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+        body.extend(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"document\"; \
+                 filename=\"{filename}\"\r\nContent-Type: application/epub+zip\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend(fs_err::read(path)?);
+        body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let resp = ureq::post(&self.endpoint("sendDocument"))
+            .timeout(Duration::from_secs(300))
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={boundary}"),
+            )
+            .send_bytes(&body)?;
+        result::<serde_json::Value>(resp.into_json()?)?;
+        Ok(())
+    }
+}
+
+fn result<T>(resp: Response<T>) -> Result<T> {
+    match resp.result {
+        Some(result) if resp.ok => Ok(result),
+        _ => bail!(
+            "Telegram says: {}",
+            resp.description.as_deref().unwrap_or("unknown error")
+        ),
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Result, bail};
+use toml_edit::{ImDocument, Item, TableLike};
+use url::Url;
+
+use stash_core::{
+    ExtractionMethod,
+    config::{self, Config},
+    script, selector,
+};
+
+/// A problem found in one of the config files.
+struct Problem {
+    path: PathBuf,
+    line: Option<usize>,
+    message: String,
+}
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// Keys any site entry can have, whatever its extraction.
+const SITE_SETTINGS: &[&str] = &["images", "tests", "comments", "byline", "profile"];
+
+/// Collects problems for a single file,
+/// translating byte spans into line numbers.
+struct Report<'a> {
+    path: &'a Path,
+    src: &'a str,
+    problems: &'a mut Vec<Problem>,
+}
+impl Report<'_> {
+    fn add(&mut self, span: Option<Range<usize>>, message: impl Into<String>) {
+        let line = span.map(|span| line_of(self.src, span.start));
+        self.problems.push(Problem {
+            path: self.path.to_path_buf(),
+            line,
+            message: message.into(),
+        });
+    }
+}
+
+fn line_of(src: &str, offset: usize) -> usize {
+    src[..offset].matches('\n').count() + 1
+}
+
+/// Validate `config.toml` and `sites.toml` in the config dir,
+/// printing any problems found.
+pub fn check(config_dir: &Path) -> Result<()> {
+    let mut problems = vec![];
+    check_config(&config_dir.join("config.toml"), &mut problems);
+    // For sites' profiles, which have to be in config.toml.
+    let profiles: Vec<String> = fs_err::read_to_string(config_dir.join("config.toml"))
+        .ok()
+        .and_then(|src| toml::from_str::<toml::Table>(&src).ok())
+        .and_then(|mut table| match table.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => Some(profiles.keys().cloned().collect()),
+            _ => None,
+        })
+        .unwrap_or_default();
+    check_sites(&config_dir.join("sites.toml"), &profiles, &mut problems);
+
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{problem}");
+    }
+    bail!("Found {} problem(s).", problems.len());
+}
+
+fn check_config(path: &Path, problems: &mut Vec<Problem>) {
+    let src = match fs_err::read_to_string(path) {
+        Ok(src) => src,
+        Err(err) => {
+            problems.push(Problem {
+                path: path.to_path_buf(),
+                line: None,
+                message: err.to_string(),
+            });
+            return;
+        }
+    };
+    let mut report = Report {
+        path,
+        src: &src,
+        problems,
+    };
+    let doc = match ImDocument::parse(src.as_str()) {
+        Ok(doc) => doc,
+        Err(err) => {
+            report.add(err.span(), err.message());
+            return;
+        }
+    };
+
+    let mut unknown = vec![];
+    let result: Result<Config, _> =
+        serde_ignored::deserialize(toml::Deserializer::new(&src), |path| {
+            unknown.push(path.to_string())
+        });
+    if let Err(err) = result {
+        report.add(err.span(), err.message());
+    }
+    for key in unknown {
+        let span = key
+            .split('.')
+            .try_fold(doc.as_item(), |item, key| item.get(key))
+            .and_then(Item::span);
+        report.add(span, format!("Unknown key `{key}`."));
+    }
+    check_profiles(&mut report, &src, &doc);
+}
+
+/// Check the config as each profile would make it.
+fn check_profiles(report: &mut Report, src: &str, doc: &ImDocument<&str>) {
+    let Ok(table) = toml::from_str::<toml::Table>(src) else {
+        return;
+    };
+    let Some(profiles) = doc.get("profiles").and_then(Item::as_table_like) else {
+        return;
+    };
+    for (name, profile) in profiles.iter() {
+        let mut table = table.clone();
+        if let Err(err) = config::apply_profile(&mut table, name) {
+            report.add(profile.span(), format!("{err}"));
+            continue;
+        }
+        let mut unknown = vec![];
+        let result: Result<Config, _> =
+            serde_ignored::deserialize(toml::Value::Table(table), |path| {
+                unknown.push(path.to_string())
+            });
+        if let Err(err) = result {
+            report.add(
+                profile.span(),
+                format!("With profile `{name}`: {}", err.message()),
+            );
+        }
+        // The rest are reported already.
+        for key in unknown {
+            if let Some(item) = key.split('.').try_fold(profile, |item, key| item.get(key)) {
+                report.add(item.span(), format!("Unknown key `profiles.{name}.{key}`."));
+            }
+        }
+    }
+}
+
+fn check_sites(path: &Path, profiles: &[String], problems: &mut Vec<Problem>) {
+    let src = match fs_err::read_to_string(path) {
+        Ok(src) => src,
+        // It's optional.
+        Err(err) if err.kind() == ErrorKind::NotFound => return,
+        Err(err) => {
+            problems.push(Problem {
+                path: path.to_path_buf(),
+                line: None,
+                message: err.to_string(),
+            });
+            return;
+        }
+    };
+    let mut report = Report {
+        path,
+        src: &src,
+        problems,
+    };
+    let doc = match ImDocument::parse(src.as_str()) {
+        Ok(doc) => doc,
+        Err(err) => {
+            report.add(err.span(), err.message());
+            return;
+        }
+    };
+
+    // Domain -> line for detecting duplicates.
+    let mut seen: HashMap<String, Option<usize>> = HashMap::new();
+    for (key, item) in doc.iter() {
+        let (key_repr, _) = doc.get_key_value(key).expect("Key exists");
+        let span = key_repr.span().or_else(|| item.span());
+        let line = span.clone().map(|span| line_of(&src, span.start));
+
+        // Lookups use the domain as parsed from the URL,
+        // so the key has to match that exactly. Keys given as a
+        // full URL are parsed as-is so the suggestion is sensible.
+        match Url::parse(key)
+            .ok()
+            .filter(|url| url.has_host())
+            .or_else(|| Url::parse(&format!("http://{key}/")).ok())
+            .and_then(|url| url.host_str().map(|domain| domain.to_string()))
+        {
+            None => report.add(span.clone(), format!("`{key}` is not a valid domain.")),
+            Some(domain) => {
+                if domain != key {
+                    report.add(
+                        span.clone(),
+                        format!("`{key}` will never match; use `{domain}` instead."),
+                    );
+                }
+                if let Some(other) = seen.insert(domain.clone(), line) {
+                    let message = match other {
+                        Some(other) => {
+                            format!("Duplicate domain `{domain}` (also on line {other}).")
+                        }
+                        None => format!("Duplicate domain `{domain}`."),
+                    };
+                    report.add(span.clone(), message);
+                }
+            }
+        }
+
+        let Some(table) = item.as_table_like() else {
+            report.add(span, format!("Entry for `{key}` should be a table."));
+            continue;
+        };
+        if let Some(images) = table.get("images") {
+            check_images(&mut report, key, images);
+        }
+        if let Some(tests) = table.get("tests") {
+            check_tests(&mut report, key, tests, path);
+        }
+        if let Some(comments) = table.get("comments") {
+            check_comments(&mut report, key, comments);
+        }
+        if let Some(byline) = table.get("byline") {
+            check_byline(&mut report, key, byline);
+        }
+        if let Some(profile) = table.get("profile") {
+            match profile.as_str() {
+                None => report.add(
+                    profile.span(),
+                    format!("`{key}.profile` should be a string."),
+                ),
+                Some(name) if !profiles.iter().any(|profile| profile == name) => report.add(
+                    profile.span(),
+                    format!("There's no profile `{name}` under `[profiles]` in config.toml."),
+                ),
+                Some(_) => {}
+            }
+        }
+        // Besides the settings and tests, which any entry can have.
+        let keys = table
+            .iter()
+            .filter(|(field, _)| !SITE_SETTINGS.contains(field))
+            .count();
+        if let Some(script) = table.get("script") {
+            let span = script.span();
+            if keys > 1 {
+                report.add(
+                    span.clone(),
+                    format!("`{key}` has a script and other keys."),
+                );
+            }
+            match script.as_str() {
+                None => report.add(span, format!("`{key}.script` should be a string.")),
+                Some(script) => {
+                    let dir = path.parent().unwrap_or(Path::new(""));
+                    if let Err(err) = script::check(&script::resolve(dir, script)) {
+                        report.add(span, format!("{err:#}"));
+                    }
+                }
+            }
+            continue;
+        }
+        // Extracted automatically.
+        if keys == 0 {
+            continue;
+        }
+        for field in ExtractionMethod::MANUAL_FIELDS {
+            if !table.contains_key(field) {
+                report.add(span.clone(), format!("`{key}` is missing `{field}`."));
+            }
+        }
+        for (field, value) in table
+            .iter()
+            .filter(|(field, _)| !SITE_SETTINGS.contains(field))
+        {
+            let span = value.span();
+            if !ExtractionMethod::MANUAL_FIELDS.contains(&field) {
+                report.add(span, format!("Unknown key `{field}` for `{key}`."));
+                continue;
+            }
+            match value.as_str() {
+                None => report.add(span, format!("`{key}.{field}` should be a string.")),
+                Some("") if field == "authors" || field == "date" => {}
+                Some(sel) => {
+                    if let Err(err) = selector(sel) {
+                        report.add(span, format!("Invalid selector for `{key}.{field}`: {err}"));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Check a site's `tests`: each needs a fixture that exists,
+/// and its expectations should be strings.
+fn check_tests(report: &mut Report, key: &str, tests: &Item, path: &Path) {
+    let tables: Vec<_> = match tests {
+        Item::ArrayOfTables(tables) => tables.iter().map(|table| table as &dyn TableLike).collect(),
+        Item::Value(toml_edit::Value::Array(array)) => {
+            let tables: Option<Vec<_>> = array
+                .iter()
+                .map(|value| value.as_inline_table().map(|table| table as &dyn TableLike))
+                .collect();
+            match tables {
+                Some(tables) => tables,
+                None => {
+                    report.add(tests.span(), format!("`{key}.tests` should be tables."));
+                    return;
+                }
+            }
+        }
+        _ => {
+            report.add(tests.span(), format!("`{key}.tests` should be tables."));
+            return;
+        }
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for test in tables {
+        match test
+            .get("fixture")
+            .map(|fixture| (fixture.as_str(), fixture.span()))
+        {
+            None => report.add(
+                tests.span(),
+                format!("A test of `{key}` is missing `fixture`."),
+            ),
+            Some((None, span)) => {
+                report.add(span, format!("`{key}.tests.fixture` should be a string."))
+            }
+            Some((Some(fixture), span)) => {
+                if !dir.join(fixture).is_file() {
+                    report.add(span, format!("There's no fixture at `{fixture}`."));
+                }
+            }
+        }
+        for (field, value) in test.iter().filter(|(field, _)| *field != "fixture") {
+            let span = value.span();
+            if !["url", "title", "authors", "date", "body_hash"].contains(&field) {
+                report.add(span, format!("Unknown key `{field}` for `{key}.tests`."));
+            } else if value.as_str().is_none() {
+                report.add(span, format!("`{key}.tests.{field}` should be a string."));
+            }
+        }
+    }
+}
+
+/// Check a site's `comments` settings: selectors, or a known platform.
+fn check_comments(report: &mut Report, key: &str, item: &Item) {
+    let Some(comments) = item.as_table_like() else {
+        report.add(item.span(), format!("`{key}.comments` should be a table."));
+        return;
+    };
+    if !comments.contains_key("selector") && !comments.contains_key("platform") {
+        report.add(
+            item.span(),
+            format!("`{key}.comments` needs a `selector` or a `platform`."),
+        );
+    }
+    for (field, value) in comments.iter() {
+        let span = value.span();
+        match (field, value.as_str()) {
+            ("selector" | "author" | "body", None) => {
+                report.add(
+                    span,
+                    format!("`{key}.comments.{field}` should be a string."),
+                );
+            }
+            ("selector" | "author" | "body", Some(sel)) => {
+                if let Err(err) = selector(sel) {
+                    report.add(
+                        span,
+                        format!("Invalid selector for `{key}.comments.{field}`: {err}"),
+                    );
+                }
+            }
+            ("platform", platform) => {
+                if platform != Some("disqus") {
+                    report.add(
+                        span,
+                        format!("`{key}.comments.platform` should be \"disqus\"."),
+                    );
+                }
+            }
+            ("limit", _) => {
+                if value.as_integer().is_none_or(|n| n < 0) {
+                    report.add(
+                        span,
+                        format!("`{key}.comments.limit` should be a number of comments."),
+                    );
+                }
+            }
+            _ => report.add(span, format!("Unknown key `{field}` for `{key}.comments`.")),
+        }
+    }
+}
+
+/// Check a site's `byline` settings, which override `[byline]`.
+fn check_byline(report: &mut Report, key: &str, item: &Item) {
+    let Some(byline) = item.as_table_like() else {
+        report.add(item.span(), format!("`{key}.byline` should be a table."));
+        return;
+    };
+    for (field, value) in byline.iter() {
+        let span = value.span();
+        match (field, value.as_str()) {
+            ("authors" | "date", None) => {
+                report.add(span, format!("`{key}.byline.{field}` should be a string."));
+            }
+            ("authors" | "date", Some(sel)) => {
+                if let Err(err) = selector(sel) {
+                    report.add(
+                        span,
+                        format!("Invalid selector for `{key}.byline.{field}`: {err}"),
+                    );
+                }
+            }
+            ("prefer_rel_author" | "header_only", _) => {
+                if value.as_bool().is_none() {
+                    report.add(
+                        span,
+                        format!("`{key}.byline.{field}` should be `true` or `false`."),
+                    );
+                }
+            }
+            _ => report.add(span, format!("Unknown key `{field}` for `{key}.byline`.")),
+        }
+    }
+}
+
+/// Check a site's `images` settings, which override `[images]`.
+fn check_images(report: &mut Report, key: &str, images: &Item) {
+    let Some(images) = images.as_table_like() else {
+        report.add(images.span(), format!("`{key}.images` should be a table."));
+        return;
+    };
+    for (field, value) in images.iter() {
+        let span = value.span();
+        let valid = match field {
+            "max_dimension" => value.as_integer().is_some_and(|n| n > 0),
+            "jpeg_quality" => value.as_integer().is_some_and(|n| (1..=100).contains(&n)),
+            "enabled" | "eink" => value.as_bool().is_some(),
+            _ => {
+                report.add(span, format!("Unknown key `{field}` for `{key}.images`."));
+                continue;
+            }
+        };
+        if !valid {
+            let expected = match field {
+                "max_dimension" => "a positive number of pixels",
+                "jpeg_quality" => "a quality from 1 to 100",
+                _ => "`true` or `false`",
+            };
+            report.add(
+                span,
+                format!("`{key}.images.{field}` should be {expected}."),
+            );
+        }
+    }
+}
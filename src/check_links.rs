@@ -0,0 +1,143 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::Result;
+use stash_core::{
+    Extractor, blocklist,
+    config::Config,
+    crypt,
+    library::{Entry, Library},
+    links::{self, LinkStatus},
+    logging,
+    progress::Progress,
+};
+use url::Url;
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct CheckLinksArgs {
+    /// Wait this many seconds between requests to the same site;
+    /// defaults to 1.
+    #[bpaf(argument("SECS"), fallback(1.0))]
+    delay: f64,
+
+    /// Stash the articles whose pages changed since they were last stashed again.
+    restash: bool,
+}
+
+/// Check whether the stashed articles' pages are still there, one URL
+/// at a time, and record what was found in the library. Dead and
+/// redirected links are listed.
+pub fn run(
+    args: CheckLinksArgs,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let library = Library::load()?;
+    // Each URL once, with its latest capture.
+    let mut latest: BTreeMap<&str, &Entry> = BTreeMap::new();
+    for entry in &library.entries {
+        if !(entry.url.starts_with("http://") || entry.url.starts_with("https://")) {
+            continue;
+        }
+        let latest = latest.entry(&entry.url).or_insert(entry);
+        if entry.stashed_at > latest.stashed_at {
+            *latest = entry;
+        }
+    }
+    if latest.is_empty() {
+        eprintln!("There are no links to check.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let delay = Duration::from_secs_f64(args.delay.max(0.0));
+    let mut last_request: HashMap<String, Instant> = HashMap::new();
+    let mut wait = |url: &str| {
+        let domain = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        if let Some(last) = last_request.get(&domain) {
+            thread::sleep(delay.saturating_sub(last.elapsed()));
+        }
+        last_request.insert(domain, Instant::now());
+    };
+
+    let batch = progress.batch(latest.len());
+    let (mut dead, mut redirected) = (0, 0);
+    let mut checks = vec![];
+    for (url, entry) in latest {
+        batch.set_message(url.to_string());
+        if let Some(domain) = blocklist::blocked(url) {
+            progress.status(format!("[skipped] {url}: `{domain}` is on the blocklist"));
+            batch.inc(1);
+            continue;
+        }
+        wait(url);
+        let check = links::check(url)?;
+        match &check.status {
+            LinkStatus::Ok => {}
+            LinkStatus::Redirected { to } => {
+                progress.status(format!("[redirected] {url} -> {to}"));
+                redirected += 1;
+            }
+            LinkStatus::Dead { error } => {
+                progress.status(format!("[dead] {url}: {error}"));
+                dead += 1;
+            }
+        }
+        if args.restash && !matches!(check.status, LinkStatus::Dead { .. }) {
+            wait(url);
+            restash(entry, extractor, config, progress);
+        }
+        checks.push((url.to_string(), check));
+        batch.inc(1);
+    }
+    batch.finish_and_clear();
+    Library::record_links(&checks)?;
+    eprintln!(
+        "Checked {} link(s): {dead} dead, {redirected} redirected.",
+        checks.len()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Stash an article again if its text has changed since it was, going
+/// by the capture's text (see `Entry::text_path`), or for captures from
+/// before that was kept, `Article::content_hash`. Failures are only
+/// warned about, as the old capture is still there.
+fn restash(entry: &Entry, extractor: &Extractor, config: &Config, progress: &Progress) {
+    let url = &entry.url;
+    let article = match extractor.fetch_article(url, progress) {
+        Ok(article) => article,
+        Err(err) => {
+            logging::warn(format!("Couldn't fetch {url} again: {err:#}"));
+            return;
+        }
+    };
+    let changed = match crypt::read_to_string(&entry.text_path()) {
+        Ok(text) => text != article.plain_text(),
+        // Short articles have no hash, so they're stashed again to be sure.
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            entry.content_hash.is_none() || article.content_hash() != entry.content_hash
+        }
+        Err(err) => {
+            logging::warn(format!("Couldn't read what was stashed of {url}: {err}"));
+            return;
+        }
+    };
+    if !changed {
+        return;
+    }
+    match crate::save(article, config, progress) {
+        Ok(Some(path)) => progress.status(format!("[restashed] {url} -> {}", path.display())),
+        Ok(None) => {}
+        Err(err) => logging::warn(format!("Couldn't stash {url} again: {err:#}")),
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::{HashMap, HashSet, hash_map::RandomState},
+    hash::{BuildHasher, Hasher},
+    io::ErrorKind,
+    path::PathBuf,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use serde::{Deserialize, Serialize};
+use stash_core::{
+    Extractor,
+    config::{self, Config, FeedJob},
+    crypt, feed, logging,
+    progress::Progress,
+    retry::RetryQueue,
+};
+
+use crate::{digest::DigestArgs, mail::MailArgs, reload::Reloader, retry::RetryArgs};
+
+/// How often to check whether the config files changed, while waiting.
+const RELOAD_CHECK: Duration = Duration::from_secs(5);
+
+#[derive(PartialEq)]
+enum Task {
+    /// The feed at this URL.
+    Feed(String),
+    Bookmarks,
+    Mail,
+    Retry,
+}
+
+struct Job {
+    task: Task,
+    every: Duration,
+    next: Instant,
+}
+
+/// Run the `[daemon]` jobs on their schedules, forever. Each job runs once
+/// at startup and then every interval after it last finished, plus jitter.
+/// Failures are warned about and the job tried again next time.
+///
+/// Changes to `config.toml` and `sites.toml` are picked up as they're made;
+/// jobs that are still there keep their schedules.
+pub fn run(
+    mut extractor: Extractor,
+    mut config: Config,
+    mut reloader: Reloader,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let (mut jobs, mut jitter) = jobs(&config, &[])?;
+    eprintln!("Running {} job(s)...", jobs.len());
+    let mut seen = Seen::load()?;
+    loop {
+        let (index, next) = jobs
+            .iter()
+            .enumerate()
+            .map(|(index, job)| (index, job.next))
+            .min_by_key(|(_, next)| *next)
+            .expect("there are jobs");
+        thread::sleep(
+            next.saturating_duration_since(Instant::now())
+                .min(RELOAD_CHECK),
+        );
+        if let Some((new_config, new_extractor)) = reloader.changed() {
+            match self::jobs(&new_config, &jobs) {
+                Ok((new_jobs, new_jitter)) => {
+                    (config, extractor) = (new_config, new_extractor);
+                    (jobs, jitter) = (new_jobs, new_jitter);
+                    eprintln!("Reloaded the config, running {} job(s)...", jobs.len());
+                }
+                Err(err) => logging::warn(format!("Not reloading the config: {err:#}")),
+            }
+            continue;
+        }
+        if Instant::now() < next {
+            continue;
+        }
+
+        let job = &mut jobs[index];
+        let done = match &job.task {
+            Task::Feed(url) => match config.daemon.feeds.iter().find(|feed| &feed.url == url) {
+                Some(feed) => poll(feed, &mut seen, &extractor, &config, progress)
+                    .wrap_err_with(|| format!("Couldn't poll {url}")),
+                None => Ok(()),
+            },
+            Task::Bookmarks => crate::bookmarks::run(&extractor, &config, progress)
+                .map(drop)
+                .wrap_err("Couldn't stash bookmarks"),
+            Task::Mail => crate::mail::run(MailArgs { once: true }, &extractor, &config, progress)
+                .map(drop)
+                .wrap_err("Couldn't check the mailbox"),
+            Task::Retry => retry(&extractor, &config, progress).wrap_err("Couldn't retry"),
+        };
+        if let Err(err) = done {
+            logging::warn(format!("{err:#}"));
+        }
+        job.next = Instant::now() + job.every + random_up_to(jitter);
+    }
+}
+
+/// The jobs under `[daemon]`, and the jitter to add to their intervals.
+/// Jobs that were running already (with the same interval) keep their
+/// next runs; new ones run now.
+fn jobs(config: &Config, running: &[Job]) -> Result<(Vec<Job>, Duration)> {
+    let daemon = &config.daemon;
+    let jitter = daemon
+        .jitter
+        .as_deref()
+        .map(interval)
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut tasks = vec![];
+    for feed in &daemon.feeds {
+        tasks.push((Task::Feed(feed.url.clone()), Some(&feed.every)));
+    }
+    if daemon.bookmarks.is_some() && config.bookmarks.is_none() {
+        bail!("`daemon.bookmarks` needs a `[bookmarks]` section in config.toml.");
+    }
+    if daemon.mail.is_some() && config.mail.is_none() {
+        bail!("`daemon.mail` needs a `[mail]` section in config.toml.");
+    }
+    tasks.push((Task::Bookmarks, daemon.bookmarks.as_ref()));
+    tasks.push((Task::Mail, daemon.mail.as_ref()));
+    tasks.push((Task::Retry, daemon.retry.as_ref()));
+
+    let now = Instant::now();
+    let jobs = tasks
+        .into_iter()
+        .filter_map(|(task, every)| every.map(|every| (task, every)))
+        .map(|(task, every)| {
+            let every = interval(every)?;
+            if every.is_zero() {
+                bail!("Job intervals can't be zero.");
+            }
+            let next = running
+                .iter()
+                .find(|job| job.task == task && job.every == every)
+                .map_or_else(|| now + random_up_to(jitter), |job| job.next);
+            Ok(Job { task, every, next })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if jobs.is_empty() {
+        bail!("No jobs under `[daemon]` in config.toml.");
+    }
+    Ok((jobs, jitter))
+}
+
+/// Stash a feed's new entries, one by one or as a digest.
+/// Entries are only tried once; those that fail go to the retry queue.
+fn poll(
+    job: &FeedJob,
+    seen: &mut Seen,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<()> {
+    let links = feed::fetch(&job.url)?.links;
+    let known = seen.feeds.entry(job.url.clone()).or_default();
+    let new: Vec<String> = links
+        .iter()
+        .filter(|link| !known.contains(*link))
+        .take(job.limit)
+        .cloned()
+        .collect();
+    if new.is_empty() {
+        return Ok(());
+    }
+
+    if job.digest {
+        crate::digest::run(
+            DigestArgs::of_feed(&job.url, new.clone(), job.min_words),
+            extractor,
+            config,
+            progress,
+        )?;
+    } else {
+        for url in &new {
+            let article = match crate::fetch(extractor, config, url, progress) {
+                Ok(article) if article.word_count() < job.min_words => {
+                    let words = article.word_count();
+                    progress.status(format!("[skipped] {url}: only {words} words"));
+                    continue;
+                }
+                article => article,
+            };
+            match article
+                .and_then(|article| crate::save(article, config, progress))
+                .inspect_err(|err| crate::failed(config, url, err))
+            {
+                Ok(Some(path)) => progress.status(format!("[ok] {url} -> {}", path.display())),
+                Ok(None) => progress.status(format!("[skipped] {url}: already exists")),
+                Err(err) => progress.status(format!("[failed] {url}: {err:#}")),
+            }
+        }
+    }
+
+    // Only what's still in the feed needs remembering.
+    let links: HashSet<_> = links.into_iter().collect();
+    known.retain(|link| links.contains(link));
+    known.extend(new);
+    seen.save()
+}
+
+fn retry(extractor: &Extractor, config: &Config, progress: &Progress) -> Result<()> {
+    if RetryQueue::load()?.entries.is_empty() {
+        return Ok(());
+    }
+    crate::retry::run(RetryArgs { list: false }, extractor, config, progress).map(drop)
+}
+
+/// Parse an interval like `90s`, `30m`, `6h`, or `1d`.
+fn interval(interval: &str) -> Result<Duration> {
+    let invalid = || format!("Invalid interval `{interval}`, expected e.g. `30m` or `6h`");
+    let interval = interval.trim();
+    let (n, unit) = interval.split_at(
+        interval
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(interval.len()),
+    );
+    let n: u64 = n.parse().wrap_err_with(invalid)?;
+    let secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!(invalid()),
+    };
+    Ok(Duration::from_secs(n * secs))
+}
+
+/// A random duration up to `max`, to the millisecond.
+fn random_up_to(max: Duration) -> Duration {
+    // Each `RandomState` is randomly seeded, which is plenty for jitter.
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_millis(random % (max.as_millis() as u64 + 1))
+}
+
+/// The entries of each feed (by URL) that have been stashed already,
+/// kept as `daemon.json` in the data dir.
+#[derive(Serialize, Deserialize, Default)]
+struct Seen {
+    feeds: HashMap<String, HashSet<String>>,
+}
+impl Seen {
+    fn path() -> PathBuf {
+        config::data_dir().join("daemon.json")
+    }
+
+    fn load() -> Result<Self> {
+        match crypt::read_to_string(&Self::path()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::process::ExitCode;
+
+use bpaf::Bpaf;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use similar::TextDiff;
+use stash_core::{crypt, library::Library, normalize_url};
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct DiffArgs {
+    /// List the URL's captures instead of comparing them.
+    list: bool,
+
+    /// Compare from this capture (numbered as with `--list`);
+    /// defaults to the second-to-last.
+    #[bpaf(argument("N"))]
+    from: Option<usize>,
+
+    /// Compare to this capture; defaults to the last.
+    #[bpaf(argument("N"))]
+    to: Option<usize>,
+
+    /// The stashed URL.
+    #[bpaf(positional("URL"))]
+    url: String,
+}
+
+/// Show how an article's text changed between two captures of it,
+/// as a unified diff.
+pub fn run(args: DiffArgs) -> Result<ExitCode> {
+    let library = Library::load()?;
+    let url = normalize_url(&args.url);
+    let captures = library.captures(&url);
+    if captures.is_empty() {
+        bail!("{url} isn't in the library.");
+    }
+    if args.list {
+        for (i, entry) in captures.iter().enumerate() {
+            println!(
+                "{}\t{}\t{}",
+                i + 1,
+                entry.stashed_at.format("%Y-%m-%d %H:%M"),
+                entry.path.display()
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if captures.len() < 2 {
+        bail!("{url} has only been stashed once, so there's nothing to compare.");
+    }
+
+    let to = args.to.unwrap_or(captures.len());
+    let from = args.from.unwrap_or(to.saturating_sub(1));
+    let capture = |n: usize| {
+        let Some(entry) = n.checked_sub(1).and_then(|i| captures.get(i)) else {
+            bail!("There's no capture {n}; {url} has {}.", captures.len());
+        };
+        let text = crypt::read_to_string(&entry.text_path())
+            .wrap_err("Only captures since versioning was added can be compared")?;
+        Ok((entry.stashed_at.format("%Y-%m-%d %H:%M").to_string(), text))
+    };
+    let (from_date, from_text) = capture(from)?;
+    let (to_date, to_text) = capture(to)?;
+
+    let diff = TextDiff::from_lines(&from_text, &to_text);
+    if diff.ratio() == 1.0 {
+        eprintln!("No changes between {from_date} and {to_date}.");
+        return Ok(ExitCode::SUCCESS);
+    }
+    print!(
+        "{}",
+        diff.unified_diff()
+            .context_radius(2)
+            .header(&format!("#{from} {from_date}"), &format!("#{to} {to_date}"))
+    );
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,275 @@
+use std::{collections::HashMap, io::ErrorKind, path::PathBuf, process::ExitCode};
+
+use bpaf::Bpaf;
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use serde::{Deserialize, Serialize};
+
+use stash_core::{
+    Article, Extractor,
+    config::{self, Config},
+    crypt,
+    epub::{self, Book, Series},
+    failure::{Failure, StashError},
+    feed,
+    library::Library,
+    logging,
+    logging::Event,
+    progress::Progress,
+    usage::Usage,
+};
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct DigestArgs {
+    /// Title for the digest; defaults to "Digest" and today's date.
+    #[bpaf(argument("TITLE"))]
+    title: Option<String>,
+
+    /// A file of URLs to include, one per line.
+    #[bpaf(argument("PATH"))]
+    file: Option<PathBuf>,
+
+    /// Include the entries of this RSS/Atom feed.
+    #[bpaf(argument("URL"))]
+    feed: Option<String>,
+
+    /// Include at most this many (of the newest) feed entries.
+    #[bpaf(argument("N"))]
+    limit: Option<usize>,
+
+    /// Include articles from the library stashed since then,
+    /// either a date or a relative time like `7d` or `2w`.
+    #[bpaf(argument("WHEN"))]
+    since: Option<String>,
+
+    /// Include articles from the library from this domain.
+    #[bpaf(argument("DOMAIN"))]
+    domain: Option<String>,
+
+    /// Leave out articles with fewer words than this,
+    /// e.g. a feed's link posts or podcast episodes.
+    #[bpaf(argument("N"))]
+    min_words: Option<usize>,
+
+    /// URLs to include.
+    #[bpaf(positional("URL"))]
+    urls: Vec<String>,
+}
+
+impl DigestArgs {
+    /// A digest of some of a feed's entries, as an issue of its series.
+    pub fn of_feed(url: &str, entries: Vec<String>, min_words: usize) -> Self {
+        Self {
+            title: None,
+            file: None,
+            feed: Some(url.to_string()),
+            limit: Some(0),
+            since: None,
+            domain: None,
+            min_words: Some(min_words),
+            urls: entries,
+        }
+    }
+}
+
+/// Fetch all the requested articles and bundle them into one EPUB.
+pub fn run(
+    args: DigestArgs,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let mut urls = args.urls.clone();
+    if let Some(path) = &args.file {
+        urls.extend(
+            fs_err::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from),
+        );
+    }
+
+    let mut feed_title = None;
+    let mut series = None;
+    let mut issues = Issues::load()?;
+    if let Some(url) = &args.feed {
+        let feed = feed::fetch(url)?;
+        let limit = args.limit.unwrap_or(feed.links.len());
+        urls.extend(feed.links.into_iter().take(limit));
+        feed_title = Some(feed.title).filter(|title| !title.is_empty());
+        series = feed_title.clone().map(|title| Series {
+            title,
+            index: issues.next(url),
+        });
+    }
+
+    if args.since.is_some() || args.domain.is_some() {
+        let since = args.since.as_deref().map(parse_since).transpose()?;
+        let library = Library::load()?;
+        urls.extend(
+            library
+                .entries
+                .iter()
+                .filter(|entry| entry.same_as.is_none())
+                .filter(|entry| since.is_none_or(|since| entry.stashed_at >= since))
+                .filter(|entry| {
+                    args.domain
+                        .as_ref()
+                        .is_none_or(|domain| entry.domain().as_ref() == Some(domain))
+                })
+                .map(|entry| entry.url.clone()),
+        );
+    }
+
+    // Dedupe, keeping the first occurrence.
+    let mut seen = std::collections::HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+    if urls.is_empty() {
+        bail!("No articles to include in the digest.");
+    }
+
+    let batch = progress.batch(urls.len());
+    let mut articles: Vec<Article> = vec![];
+    let mut short = 0;
+    for url in &urls {
+        batch.set_message(url.clone());
+        let fetched = crate::fetch(extractor, config, url, progress);
+        if let Ok(article) = &fetched
+            && article.word_count() < args.min_words.unwrap_or_default()
+        {
+            let words = article.word_count();
+            progress.status(format!("[skipped] {url}: only {words} words"));
+            short += 1;
+            batch.inc(1);
+            continue;
+        }
+        match fetched
+            .and_then(|article| crate::check_length(article, config))
+            .inspect_err(|err| {
+                logging::failed(url, err);
+                crate::usage(Usage::failed(url, err));
+            }) {
+            Ok(mut article) => {
+                crate::warned(&article);
+                crate::translate(&mut article, config);
+                crate::discuss(&mut article, config);
+                progress.status(format!("[ok] {url}"));
+                articles.push(article);
+            }
+            Err(err) => progress.status(format!("[failed] {url}: {err:#}")),
+        }
+        batch.inc(1);
+    }
+    batch.finish_and_clear();
+    if articles.is_empty() && short > 0 {
+        bail!("All of the articles were too short to include.");
+    }
+    if articles.is_empty() {
+        return Err(eyre!("None of the articles could be fetched.").wrap_err(Failure::Network));
+    }
+
+    let now = Utc::now();
+    let title = args
+        .title
+        .unwrap_or_else(|| format!("Digest {}", now.format("%Y-%m-%d")));
+    let book = Book {
+        title,
+        authors: feed_title.clone().unwrap_or_else(|| "Various".to_string()),
+        published_at: now.to_rfc3339(),
+        source: feed_title
+            .clone()
+            .unwrap_or_else(|| format!("{} articles", articles.len())),
+        url: String::new(),
+        description: format!("A digest of {} articles.", articles.len()),
+        lang: String::new(),
+        publisher: feed_title.unwrap_or_default(),
+        subjects: vec![],
+        rights: String::new(),
+        series,
+        articles: articles.iter().collect(),
+    };
+    let path = book.path(&config.output_dir(), config);
+    let Some(path) = epub::resolve_collision(path, config.on_collision) else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    book.write(&path, config, progress)
+        .wrap_err_with(|| StashError::Output { path: path.clone() })?;
+    if book.series.is_some() {
+        issues.save()?;
+    }
+    for article in &articles {
+        crate::usage(Usage::stashed(article));
+    }
+    logging::emit(Event::OutputWritten {
+        url: args.feed.as_deref().unwrap_or_default(),
+        path: &path,
+    });
+    crate::written(
+        config,
+        &path,
+        &book,
+        args.feed.as_deref().unwrap_or_default(),
+    );
+    println!("{}", path.display());
+
+    if articles.len() + short < urls.len() {
+        eprintln!(
+            "{} of {} articles failed.",
+            urls.len() - articles.len() - short,
+            urls.len()
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The number of digests made so far of each feed (by URL),
+/// kept as `issues.json` in the data dir.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(transparent)]
+struct Issues {
+    counts: HashMap<String, u32>,
+}
+impl Issues {
+    fn path() -> PathBuf {
+        config::data_dir().join("issues.json")
+    }
+
+    fn load() -> Result<Self> {
+        match crypt::read_to_string(&Self::path()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The issue number for the next digest of the feed.
+    fn next(&mut self, feed_url: &str) -> u32 {
+        let count = self.counts.entry(feed_url.to_string()).or_default();
+        *count += 1;
+        *count
+    }
+}
+
+/// Parse a relative time like `7d` or `2w` (days or weeks ago),
+/// or otherwise a date.
+fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    let relative = since
+        .strip_suffix('d')
+        .map(|n| (n, 1))
+        .or_else(|| since.strip_suffix('w').map(|n| (n, 7)))
+        .and_then(|(n, days)| n.parse::<i64>().ok().map(|n| n * days));
+    match relative {
+        Some(days) => Ok(Utc::now() - Duration::days(days)),
+        None => dateparser::parse(since).map_err(|err| eyre!("Invalid date `{since}`: {err}")),
+    }
+}
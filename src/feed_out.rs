@@ -0,0 +1,200 @@
+use std::{io::Read, path::PathBuf, process::ExitCode};
+
+use bpaf::Bpaf;
+use chrono::Utc;
+use color_eyre::eyre::{Result, WrapErr};
+use stash_core::{
+    config::Config,
+    date,
+    library::{Entry, Library},
+    xhtml::{escape_attr, escape_text},
+};
+use url::Url;
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct FeedOutArgs {
+    /// Include at most this many of the most recently stashed articles;
+    /// defaults to 50.
+    #[bpaf(argument("N"), fallback(50))]
+    limit: usize,
+
+    /// Include each article's full text, read from its EPUB.
+    full: bool,
+
+    /// Link each entry to its EPUB under this URL,
+    /// e.g. wherever the output dir is served from.
+    #[bpaf(argument("URL"))]
+    base_url: Option<String>,
+
+    /// Only include articles with this tag (keyword), e.g. `recipes`.
+    #[bpaf(argument("TAG"))]
+    tag: Option<String>,
+
+    /// Write the feed to this file instead of stdout.
+    #[bpaf(short, long, argument("PATH"))]
+    output: Option<PathBuf>,
+}
+
+/// Write an Atom feed of the most recently stashed articles,
+/// or of those with a tag.
+pub fn run(args: FeedOutArgs, config: &Config) -> Result<ExitCode> {
+    let base_url = args
+        .base_url
+        .as_deref()
+        .map(Url::parse)
+        .transpose()
+        .wrap_err("Invalid `--base-url`")?;
+    let mut entries = Library::load()?.entries;
+    entries.retain(|entry| entry.same_as.is_none());
+    if let Some(tag) = &args.tag {
+        entries.retain(|entry| {
+            entry
+                .tags
+                .iter()
+                .any(|other| other.eq_ignore_ascii_case(tag))
+        });
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.stashed_at));
+    entries.truncate(args.limit);
+
+    let updated = entries
+        .first()
+        .map(|entry| entry.stashed_at)
+        .unwrap_or_else(Utc::now);
+    let (id, title) = match &args.tag {
+        Some(tag) => (
+            format!(
+                "urn:stash:tag:{}",
+                escape_text(&tag.to_lowercase().replace(' ', "-"))
+            ),
+            format!("Stashed articles tagged {}", escape_text(tag)),
+        ),
+        None => (
+            "urn:stash:library".to_string(),
+            "Stashed articles".to_string(),
+        ),
+    };
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>{id}</id>\n\
+         <title>{title}</title>\n\
+         <updated>{}</updated>\n\
+         <author><name>stash</name></author>\n\
+         <generator>stash</generator>\n",
+        updated.to_rfc3339()
+    );
+    for entry in &entries {
+        feed.push_str(&feed_entry(entry, &args, base_url.as_ref(), config));
+    }
+    feed.push_str("</feed>\n");
+
+    match &args.output {
+        Some(path) => fs_err::write(path, feed)?,
+        None => print!("{feed}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn feed_entry(
+    entry: &Entry,
+    args: &FeedOutArgs,
+    base_url: Option<&Url>,
+    config: &Config,
+) -> String {
+    // Each capture of a URL is its own entry.
+    let id = entry
+        .identifier
+        .clone()
+        .unwrap_or_else(|| format!("{}#stashed-{}", entry.url, entry.stashed_at.timestamp()));
+    let mut xml = format!(
+        "<entry>\n<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n\
+         <link rel=\"alternate\" href=\"{}\"/>\n",
+        escape_text(&id),
+        escape_text(&entry.title),
+        entry.stashed_at.to_rfc3339(),
+        escape_attr(&entry.url),
+    );
+    if !entry.authors.is_empty() {
+        xml.push_str(&format!(
+            "<author><name>{}</name></author>\n",
+            escape_text(&entry.authors)
+        ));
+    }
+    if let Some(published) = date::parse(&entry.published_at) {
+        xml.push_str(&format!(
+            "<published>{}</published>\n",
+            published.to_rfc3339()
+        ));
+    }
+    if let Some(base_url) = base_url {
+        let relative = entry
+            .path
+            .strip_prefix(config.output_dir())
+            .unwrap_or(&entry.path);
+        let mut url = base_url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments
+                .pop_if_empty()
+                .extend(relative.iter().map(|part| part.to_string_lossy()));
+        }
+        xml.push_str(&format!(
+            "<link rel=\"enclosure\" type=\"application/epub+zip\" href=\"{}\"/>\n",
+            escape_attr(url.as_str())
+        ));
+    }
+    if !entry.summary.is_empty() {
+        xml.push_str(&format!(
+            "<summary>{}</summary>\n",
+            escape_text(&entry.summary)
+        ));
+    }
+    for tag in &entry.tags {
+        xml.push_str(&format!("<category term=\"{}\"/>\n", escape_attr(tag)));
+    }
+    // The EPUB might've since been moved or (after uploading) removed.
+    if args.full
+        && let Some(text) = full_text(entry)
+    {
+        xml.push_str(&format!(
+            "<content type=\"html\">{}</content>\n",
+            escape_text(&text)
+        ));
+    }
+    xml.push_str("</entry>\n");
+    xml
+}
+
+/// The body of a single-article EPUB's text (from each of its parts,
+/// if it was split), without its images, which only exist inside the EPUB.
+fn full_text(entry: &Entry) -> Option<String> {
+    let file = fs_err::File::open(&entry.path).ok()?;
+    let mut zip = zip::ZipArchive::new(file).ok()?;
+    let mut text = String::new();
+    for part in 1.. {
+        let name = match part {
+            1 => "OEBPS/main.xhtml".to_string(),
+            part => format!("OEBPS/main-{part}.xhtml"),
+        };
+        let mut doc = String::new();
+        match zip.by_name(&name) {
+            Ok(mut file) => file.read_to_string(&mut doc).ok()?,
+            Err(_) if part > 1 => break,
+            Err(_) => return None,
+        };
+        let start = doc.find("<body")?;
+        let start = start + doc[start..].find('>')? + 1;
+        let end = doc.rfind("</body>")?;
+        let mut body = doc.get(start..end)?.trim();
+
+        while let Some(i) = body.find("<img") {
+            text.push_str(&body[..i]);
+            body = body[i..]
+                .find('>')
+                .map(|end| &body[i + end + 1..])
+                .unwrap_or_default();
+        }
+        text.push_str(body);
+    }
+    Some(text)
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use serde::{Deserialize, Serialize};
+use stash_core::{
+    config::{self, Config},
+    crypt,
+    library::Library,
+};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, value};
+use url::Url;
+
+/// Offer to follow a just-stashed article's site, given its URL and feeds:
+/// if it links to a feed that isn't followed yet and enough of the site's
+/// articles have been stashed, ask whether to add it to `daemon.feeds`
+/// in config.toml. With `follow.always`, it's added without asking.
+/// Declined feeds are remembered, so they're only offered once.
+pub fn offer(url: &str, feeds: &[String], config: &Config) -> Result<()> {
+    let Some(feed) = feeds.first() else {
+        return Ok(());
+    };
+    let followed = config
+        .daemon
+        .feeds
+        .iter()
+        .any(|job| feeds.contains(&job.url));
+    if followed {
+        return Ok(());
+    }
+
+    if !config.follow.always {
+        let suggest_after = config.follow.suggest_after;
+        let Some(domain) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+        else {
+            return Ok(());
+        };
+        let mut declined = Declined::load()?;
+        if suggest_after == 0 || declined.feeds.contains(feed) {
+            return Ok(());
+        }
+        let stashed = Library::load()?
+            .entries
+            .iter()
+            .filter(|entry| entry.same_as.is_none() && entry.domain().as_ref() == Some(&domain))
+            .count();
+        if stashed < suggest_after {
+            return Ok(());
+        }
+        let question =
+            format!("You've stashed {stashed} articles from {domain}. Follow its feed, {feed}?");
+        if !crate::ask_confirm(&question) {
+            declined.feeds.insert(feed.clone());
+            return declined.save();
+        }
+    }
+
+    add(
+        &config::config_dir().join("config.toml"),
+        feed,
+        &config.follow.every,
+    )?;
+    eprintln!(
+        "Following {feed}; `stash daemon` will poll it every {}.",
+        config.follow.every
+    );
+    Ok(())
+}
+
+/// Append a `[[daemon.feeds]]` entry to config.toml, keeping its formatting and comments.
+fn add(path: &Path, feed: &str, every: &str) -> Result<()> {
+    let src = fs_err::read_to_string(path)?;
+    let mut doc: DocumentMut = src
+        .parse()
+        .wrap_err_with(|| format!("Couldn't parse {}", path.display()))?;
+    let daemon = doc
+        .entry("daemon")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| eyre!("`daemon` in config.toml isn't a table"))?;
+    let feeds = daemon
+        .entry("feeds")
+        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| eyre!("`daemon.feeds` in config.toml isn't a list"))?;
+    let mut job = Table::new();
+    job["url"] = value(feed);
+    job["every"] = value(every);
+    feeds.push(job);
+    fs_err::write(path, doc.to_string())?;
+    Ok(())
+}
+
+/// The feeds that have been offered and declined,
+/// kept as `declined-feeds.json` in the data dir.
+#[derive(Serialize, Deserialize, Default)]
+struct Declined {
+    feeds: HashSet<String>,
+}
+impl Declined {
+    fn path() -> PathBuf {
+        config::data_dir().join("declined-feeds.json")
+    }
+
+    fn load() -> Result<Self> {
+        match crypt::read_to_string(&Self::path()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
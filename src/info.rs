@@ -0,0 +1,79 @@
+use std::process::ExitCode;
+
+use bpaf::Bpaf;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use stash_core::{Extractor, config::Config, progress::Progress, reading_time, warning::Warning};
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct InfoArgs {
+    /// Print it as JSON.
+    json: bool,
+
+    /// The article's URL.
+    #[bpaf(positional("URL"))]
+    url: String,
+}
+
+/// What `stash info` prints.
+#[derive(Serialize)]
+struct Info<'a> {
+    url: &'a str,
+    title: &'a str,
+    authors: &'a str,
+    published_at: &'a str,
+    words: usize,
+    reading_time: String,
+    lang: &'a str,
+    extraction: &'a str,
+    warnings: &'a [Warning],
+}
+
+/// Fetch and extract an article, and print its metadata
+/// without building a book or recording it in the library.
+pub fn run(
+    args: InfoArgs,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let mut article = extractor.fetch_article(&args.url, progress)?;
+    article.normalize_date(config.timezone);
+    if let Some(problem) = article.length_problem(&config.length) {
+        article.warn(Warning::Length { problem });
+    }
+    let words = article.word_count();
+    let info = Info {
+        url: &article.url,
+        title: &article.title,
+        authors: &article.authors,
+        published_at: &article.published_at,
+        words,
+        reading_time: reading_time(words, config.reading_speed),
+        lang: &article.lang,
+        extraction: &article.extraction,
+        warnings: &article.warnings,
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let unknown = |value: &str| {
+        if value.is_empty() {
+            "-".to_string()
+        } else {
+            value.to_string()
+        }
+    };
+    println!("URL:        {}", info.url);
+    println!("Title:      {}", unknown(info.title));
+    println!("Authors:    {}", unknown(info.authors));
+    println!("Published:  {}", unknown(info.published_at));
+    println!("Words:      {} ({})", info.words, info.reading_time);
+    println!("Language:   {}", unknown(info.lang));
+    println!("Extraction: {}", info.extraction);
+    for warning in info.warnings {
+        println!("Warning:    {warning}.");
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,68 @@
+//! Stashing a batch of URLs several at a time (see `[jobs]`): fetching
+//! as many as `fetches` at once, while building at most `builds` of them.
+//! Their downloads, images included, are capped by `throttle::download`.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        Condvar, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use color_eyre::eyre::Result;
+use stash_core::{Article, config::Jobs};
+
+/// Stash each URL: `fetch` it, then `build` it, and report its outcome
+/// to `done` as it finishes, so not necessarily in order.
+pub fn stash_all(
+    urls: &[String],
+    jobs: Jobs,
+    fetch: impl Fn(&str) -> Result<Article> + Sync,
+    build: impl Fn(Article) -> Result<Option<PathBuf>> + Sync,
+    done: impl Fn(&str, Result<Option<PathBuf>>) + Sync,
+) {
+    let next = AtomicUsize::new(0);
+    let builds = Semaphore::new(jobs.builds);
+    thread::scope(|scope| {
+        // Each worker fetches one URL at a time.
+        for _ in 0..jobs.fetches.clamp(1, urls.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(url) = urls.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = fetch(url).and_then(|article| builds.run(|| build(article)));
+                    done(url, result);
+                }
+            });
+        }
+    });
+}
+
+/// Lets only so many threads do something at once.
+struct Semaphore {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+impl Semaphore {
+    fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn run<R>(&self, f: impl FnOnce() -> R) -> R {
+        {
+            let available = self.available.lock().expect("Lock");
+            let mut available = self
+                .freed
+                .wait_while(available, |available| *available == 0)
+                .expect("Lock");
+            *available -= 1;
+        }
+        let result = f();
+        *self.available.lock().expect("Lock") += 1;
+        self.freed.notify_one();
+        result
+    }
+}
@@ -0,0 +1,161 @@
+use std::{process::ExitCode, thread, time::Duration};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use imap::{ClientBuilder, ConnectionMode};
+use mail_parser::{Message, MessageParser, PartType};
+use stash_core::{
+    Article, Extractor,
+    config::{Config, Mail},
+    failure::Failure,
+    logging,
+    progress::Progress,
+};
+
+/// Messages with fewer words than this (not counting links) are taken
+/// to be sharing links, e.g. from a phone, rather than being newsletters.
+const MAX_LINK_MESSAGE_WORDS: usize = 50;
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct MailArgs {
+    /// Check the mailbox once and exit, instead of polling it.
+    pub once: bool,
+}
+
+/// Stash what arrives in the `[mail]` mailbox: each unread message's
+/// links, or for newsletters the message itself. Messages are marked
+/// as read once handled, whether or not stashing them worked.
+pub fn run(
+    args: MailArgs,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let Some(mail) = &config.mail else {
+        bail!("No `[mail]` section in config.toml.");
+    };
+    loop {
+        let checked = check_mailbox(mail, extractor, config, progress);
+        if args.once {
+            return checked.map(|_| ExitCode::SUCCESS);
+        }
+        if let Err(err) = checked {
+            logging::warn(format!("Couldn't check the mailbox: {err:#}"));
+        }
+        thread::sleep(Duration::from_secs(mail.poll_interval));
+    }
+}
+
+fn check_mailbox(
+    mail: &Mail,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<()> {
+    let mode = if mail.tls {
+        ConnectionMode::AutoTls
+    } else {
+        ConnectionMode::Plaintext
+    };
+    let mut session = ClientBuilder::new(&mail.host, mail.port)
+        .mode(mode)
+        .connect()
+        .wrap_err_with(|| format!("Couldn't connect to {}", mail.host))
+        .wrap_err(Failure::Network)?
+        .login(&mail.username, &mail.password)
+        .map_err(|(err, _)| err)
+        .wrap_err_with(|| format!("Couldn't log in as {}", mail.username))?;
+    session.select(&mail.mailbox)?;
+
+    let mut uids: Vec<_> = session.uid_search("UNSEEN")?.into_iter().collect();
+    uids.sort();
+    for uid in uids {
+        let fetches = session.uid_fetch(uid.to_string(), "BODY.PEEK[]")?;
+        let raw = fetches.iter().next().and_then(|fetch| fetch.body());
+        match raw.and_then(|raw| MessageParser::default().parse(raw)) {
+            Some(message) => stash_message(&message, extractor, config, progress),
+            None => logging::warn(format!("Couldn't parse message {uid}.")),
+        }
+        // Failures are reported rather than retried on every check.
+        session.uid_store(uid.to_string(), "+FLAGS (\\Seen)")?;
+    }
+    session.logout()?;
+    Ok(())
+}
+
+fn stash_message(message: &Message, extractor: &Extractor, config: &Config, progress: &Progress) {
+    let subject = message.subject().unwrap_or("(no subject)");
+    let text = message.body_text(0).unwrap_or_default();
+    let links = links(&text);
+    let words = text
+        .split_whitespace()
+        .filter(|word| !is_link(word))
+        .count();
+
+    if !links.is_empty() && words < MAX_LINK_MESSAGE_WORDS {
+        for url in links {
+            let article = extractor.fetch_article(&url, progress);
+            report(&url, article, config, progress);
+        }
+        return;
+    }
+
+    let html = message.html_part(0).and_then(|part| match &part.body {
+        PartType::Html(html) => Some(html),
+        _ => None,
+    });
+    let Some(html) = html else {
+        logging::warn(format!("Message has no links or HTML: {subject}"));
+        return;
+    };
+
+    // Newsletters have no URL of their own,
+    // so refer to them by message ID instead.
+    let url = format!("mid:{}", message.message_id().unwrap_or_default());
+    let article = extractor.extract_article(&url, html).map(|mut article| {
+        if article.title.is_empty() {
+            article.title = subject.to_string();
+        }
+        if article.authors.is_empty()
+            && let Some(sender) = message.from().and_then(|from| from.first())
+        {
+            article.authors = sender
+                .name()
+                .or(sender.address())
+                .unwrap_or_default()
+                .to_string();
+        }
+        article
+    });
+    report(subject, article, config, progress);
+}
+
+fn report(label: &str, article: Result<Article>, config: &Config, progress: &Progress) {
+    match article
+        .and_then(|article| crate::save(article, config, progress))
+        .inspect_err(|err| crate::failed(config, label, err))
+    {
+        Ok(Some(path)) => progress.status(format!("[ok] {label} -> {}", path.display())),
+        Ok(None) => progress.status(format!("[skipped] {label}: already exists")),
+        Err(err) => progress.status(format!("[failed] {label}: {err:#}")),
+    }
+}
+
+/// The distinct web links in some text.
+pub fn links(text: &str) -> Vec<String> {
+    let mut links: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        let link = word
+            .trim_start_matches(['<', '(', '['])
+            .trim_end_matches(['>', ')', ']', '.', ',', ';', '!', '?']);
+        if is_link(link) && !links.iter().any(|l| l == link) {
+            links.push(link.to_string());
+        }
+    }
+    links
+}
+
+fn is_link(word: &str) -> bool {
+    let word = word.trim_start_matches(['<', '(', '[']);
+    word.starts_with("http://") || word.starts_with("https://")
+}
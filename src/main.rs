@@ -1,263 +1,882 @@
+mod annotations;
+mod bookmarks;
+mod bot;
+mod check;
+mod check_links;
+mod daemon;
+mod diff;
+mod digest;
+mod feed_out;
+mod follow;
+mod info;
+mod jobs;
+mod mail;
+mod merge;
+mod open;
+mod preview;
+mod reload;
+mod retry;
+mod serve;
+mod stats;
+
 use std::{
-    collections::HashMap,
     io::Read,
     path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Mutex,
 };
 
 use bpaf::Bpaf;
-use color_eyre::eyre::{bail, eyre, Result};
-use dom_smoothie::{Article as ExtractArticle, Config as ExtractConfig, Readability};
-use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
-use scraper::{Html, Selector};
-use serde::{Deserialize, Serialize};
-use url::Url;
-
-const APP_NAME: &str = "stash";
-const USER_AGENTS: &[&str] = &[
-    "curl/8.11",
-    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
-];
-
-#[derive(Deserialize, Debug)]
-struct Config {
-    output_dir: String,
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+
+use annotations::AnnotationsCommand;
+use bot::BotCommand;
+use check_links::CheckLinksArgs;
+use diff::DiffArgs;
+use digest::DigestArgs;
+use feed_out::FeedOutArgs;
+use info::InfoArgs;
+use mail::MailArgs;
+use merge::MergeArgs;
+use open::OpenArgs;
+use preview::PreviewArgs;
+use reload::Reloader;
+use retry::RetryArgs;
+use serve::ServeArgs;
+use stash_core::{
+    Article, Extractor, archive, blocklist, calibre,
+    config::{self, Config, ImageOverrides, Jobs, Validation},
+    crypt, discussion,
+    epub::Book,
+    failure::{Failure, StashError},
+    hook,
+    library::Library,
+    logging::{self, Event, LogFormat},
+    mirror,
+    progress::Progress,
+    reading_time,
+    retry::RetryQueue,
+    saved, summary, throttle, translate, upload,
+    usage::Usage,
+    warning::Warning,
+    webhook::{self, Status},
+};
+use stats::StatsArgs;
+
+#[derive(Clone, Debug, Bpaf)]
+#[bpaf(options, version)]
+/// An web article extractor.
+/// Uses automatic or manually-defined-rule extraction,
+/// then generates an epub from the extracted content.
+struct Args {
+    /// Format for diagnostics on stderr: `text` (default) or `json`.
+    #[bpaf(argument("FORMAT"), fallback(LogFormat::Text))]
+    log_format: LogFormat,
+
+    /// Use a profile's settings (under `[profiles]` in the config)
+    /// over the rest, e.g. those for a particular e-reader.
+    #[bpaf(argument("NAME"), env("STASH_PROFILE"))]
+    profile: Option<String>,
+
+    /// Optimize images for e-paper displays (grayscale, dithered),
+    /// as with `eink = true` under `[images]` in the config.
+    eink: bool,
+
+    /// Extract pages as they were last fetched, without going online
+    /// at all: images are left out, and so are discussions, summaries,
+    /// translations, and uploads.
+    offline: bool,
+
+    /// Also save each article's page as it was, with its stylesheets,
+    /// images, and fonts, next to its book, as with `mirror = true`
+    /// in the config.
+    mirror: bool,
+
+    /// How many articles to fetch at once and how many to build at once,
+    /// in batches without previews: `N` for both, or `FETCHES,BUILDS`;
+    /// as with `[jobs]` in the config.
+    #[bpaf(argument("N"))]
+    jobs: Option<Jobs>,
+
+    /// Leave images out of books entirely, as with `enabled = false`
+    /// under `[images]` in the config.
+    no_images: bool,
+
+    /// Machine-translate articles into this language (e.g. `en`)
+    /// with the `[translation]` service in the config.
+    #[bpaf(argument("LANG"))]
+    translate: Option<String>,
+
+    /// Add new books to Calibre, as with `enabled = true`
+    /// under `[calibre]` in the config.
+    to_calibre: bool,
+
+    /// Follow the feeds of stashed articles' sites without asking,
+    /// as with `always = true` under `[follow]` in the config.
+    follow: bool,
+
+    /// Append each article's Hacker News or Reddit discussion, as with
+    /// `enabled = true` under `[discussion]` in the config.
+    discussion: bool,
+
+    #[bpaf(external)]
+    command: Command,
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-#[serde(untagged)]
-enum ExtractionMethod {
-    #[default]
-    Auto,
-    Manual {
-        title: String,
-        body: String,
-        authors: String,
-        date: String,
+#[derive(Clone, Debug, Bpaf)]
+enum Command {
+    /// Import, search, and export highlights made on stashed books.
+    #[bpaf(command("annotations"))]
+    Annotations {
+        #[bpaf(external(annotations::annotations_command))]
+        command: AnnotationsCommand,
+    },
+
+    /// Stash the bookmarks tagged for it in linkding or Shiori.
+    #[bpaf(command("bookmarks"))]
+    Bookmarks,
+
+    /// Run a chat bot that stashes the links it's sent.
+    #[bpaf(command("bot"))]
+    Bot {
+        #[bpaf(external(bot::bot_command))]
+        command: BotCommand,
+    },
+
+    /// Check whether stashed articles' pages are still there,
+    /// noting dead and redirected links in the library.
+    #[bpaf(command("check-links"))]
+    CheckLinks(#[bpaf(external(check_links::check_links_args))] CheckLinksArgs),
+
+    /// Manage the config files.
+    #[bpaf(command("config"))]
+    Config {
+        #[bpaf(external(config_command))]
+        command: ConfigCommand,
+    },
+
+    /// Poll feeds and integrations on the schedules under `[daemon]`.
+    #[bpaf(command("daemon"))]
+    Daemon,
+
+    /// Show how an article changed between captures of it.
+    #[bpaf(command("diff"))]
+    Diff(#[bpaf(external(diff::diff_args))] DiffArgs),
+
+    /// Bundle several articles into a single EPUB,
+    /// with one chapter per article.
+    #[bpaf(command("digest"))]
+    Digest(#[bpaf(external(digest::digest_args))] DigestArgs),
+
+    /// Write an Atom feed of recently stashed articles.
+    #[bpaf(command("feed-out"))]
+    FeedOut(#[bpaf(external(feed_out::feed_out_args))] FeedOutArgs),
+
+    /// Print an article's title, byline, length, and how it was extracted,
+    /// without stashing it.
+    #[bpaf(command("info"))]
+    Info(#[bpaf(external(info::info_args))] InfoArgs),
+
+    /// Stash the links and newsletters arriving in a mailbox.
+    #[bpaf(command("mail"))]
+    Mail(#[bpaf(external(mail::mail_args))] MailArgs),
+
+    /// Merge EPUBs stash has made into one, with a chapter per article.
+    #[bpaf(command("merge"))]
+    Merge(#[bpaf(external(merge::merge_args))] MergeArgs),
+
+    /// Open the stashed book best matching a search.
+    #[bpaf(command("open"))]
+    Open(#[bpaf(external(open::open_args))] OpenArgs),
+
+    /// Try stashing URLs that failed before again.
+    #[bpaf(command("retry"))]
+    Retry(#[bpaf(external(retry::retry_args))] RetryArgs),
+
+    /// Manage the community's site configs.
+    #[bpaf(command("sites"))]
+    Sites {
+        #[bpaf(external(sites_command))]
+        command: SitesCommand,
+    },
+
+    /// Serve an article as it's extracted, re-extracting it as sites.toml
+    /// is edited, to tune a site's selectors in the browser.
+    #[bpaf(command("preview"))]
+    Preview(#[bpaf(external(preview::preview_args))] PreviewArgs),
+
+    /// Run an HTTP API for stashing articles in the background.
+    #[bpaf(command("serve"))]
+    Serve(#[bpaf(external(serve::serve_args))] ServeArgs),
+
+    /// Show how articles were extracted and how stashing failed,
+    /// from stats kept locally, to find sites that need a config.
+    #[bpaf(command("stats"))]
+    Stats(#[bpaf(external(stats::stats_args))] StatsArgs),
+
+    Stash {
+        #[bpaf(external)]
+        metadata: Metadata,
+
+        /// Print exactly one line per URL to stdout,
+        /// `<status>\t<url>\t<path>` (status `ok`, `skipped`, or `failed`,
+        /// and the path empty unless it's `ok`), with all else on stderr;
+        /// there's no preview. For `xargs`, `parallel`, and pipelines.
+        #[bpaf(short('q'), long("quiet"))]
+        quiet: bool,
+
+        /// Url(s) to extract.
+        #[bpaf(positional("URL"), some("At least one URL is required"))]
+        urls: Vec<String>,
     },
 }
-impl ExtractionMethod {
-    fn extract(&self, uri: &str, html: &str) -> Result<Article> {
-        match self {
-            Self::Auto => auto_extract(uri, html),
-            Self::Manual {
-                title,
-                body,
-                authors,
-                date,
-            } => manual_extract(uri, html, title, body, authors, date),
+
+/// Metadata to use instead of what's extracted,
+/// e.g. for a site whose byline can't be found.
+#[derive(Clone, Debug, Bpaf)]
+struct Metadata {
+    /// Use this title.
+    #[bpaf(argument("TITLE"))]
+    title: Option<String>,
+
+    /// Use this author (or authors, e.g. `Ann Author, Bo Byline`).
+    #[bpaf(argument("AUTHOR"))]
+    author: Option<String>,
+
+    /// Use this publication date, e.g. `2024-03-05`.
+    #[bpaf(argument("DATE"))]
+    date: Option<String>,
+
+    /// Note why it's stashed (e.g. `for thesis chapter 3`),
+    /// kept in the library and on its "About this capture" page.
+    #[bpaf(argument("NOTE"))]
+    note: Option<String>,
+}
+impl Metadata {
+    fn apply(&self, article: &mut Article) {
+        if let Some(title) = &self.title {
+            article.title = title.clone();
+        }
+        if let Some(author) = &self.author {
+            article.authors = author.clone();
+        }
+        if let Some(date) = &self.date {
+            article.published_at = date.clone();
+        }
+        if let Some(note) = &self.note {
+            article.note = note.clone();
         }
     }
 }
 
-fn auto_extract(url: &str, html: &str) -> Result<Article> {
-    let cfg = ExtractConfig::default();
-    let mut readability = Readability::new(html, Some(url), Some(cfg))?;
-    let article: ExtractArticle = readability.parse()?;
-    Ok(Article {
-        url: url.to_string(),
-        title: article.title,
-        authors: article.byline.unwrap_or_default(),
-        published_at: article.published_time.unwrap_or_default(),
-        content: article.content.to_string(), // HTML content
-    })
+#[derive(Clone, Debug, Bpaf)]
+enum ConfigCommand {
+    /// Validate config.toml and sites.toml,
+    /// including every site's selectors.
+    #[bpaf(command("check"))]
+    Check,
 }
 
-fn selector(sel: &str) -> Result<Selector> {
-    Selector::parse(sel).map_err(|err| eyre!(err.to_string()))
+#[derive(Clone, Debug, Bpaf)]
+enum SitesCommand {
+    /// Download the site configs at `sites_source` in config.toml,
+    /// to use for domains that sites.toml doesn't configure.
+    #[bpaf(command("update"))]
+    Update,
+
+    /// Check sites' extraction against their saved pages,
+    /// the `tests` of their entries in sites.toml.
+    #[bpaf(command("test"))]
+    Test,
 }
 
-fn manual_extract(
-    url: &str,
-    html: &str,
-    title_sel: &str,
-    body_sel: &str,
-    authors_sel: &str,
-    date_sel: &str,
-) -> Result<Article> {
-    let doc = Html::parse_document(html);
-    let title_sel = selector(title_sel)?;
-    let body_sel = selector(body_sel)?;
-    let authors_sel = selector(authors_sel)?;
-    let date_sel = selector(date_sel)?;
-
-    let mut entry = Article {
-        url: url.to_string(),
-        ..Default::default()
+/// Update the community's site configs, and say how many there are
+/// (and how many are overridden by the user's own).
+fn update_sites(config: &Config, config_dir: &Path) -> Result<ExitCode> {
+    let Some(source) = &config.sites_source else {
+        bail!("`stash sites update` needs a `sites_source` in config.toml.");
     };
+    let community = stash_core::sites::update(source, &stash_core::sites::dir(config_dir))?;
+    let own = Extractor::load(&config_dir.join("sites.toml"))?;
+    let own: Vec<_> = own.domains().collect();
+    let total = community.domains().count();
+    let overridden = community
+        .domains()
+        .filter(|domain| own.contains(domain))
+        .count();
+    eprintln!("Updated {total} site config(s), {overridden} overridden by sites.toml.");
+    Ok(ExitCode::SUCCESS)
+}
 
-    if let Some(el) = doc.select(&title_sel).next() {
-        entry.title = el.text().collect::<Vec<_>>().join("");
-    } else {
-        eprintln!("WARN: Title element not found.");
+/// Run every site config's tests, with a status line for each.
+fn test_sites(extractor: &Extractor, config: &Config) -> Result<ExitCode> {
+    let mut sites: Vec<_> = extractor
+        .sites()
+        .filter(|(_, site)| !site.tests.is_empty())
+        .collect();
+    if sites.is_empty() {
+        eprintln!("No site configs have tests.");
+        return Ok(ExitCode::SUCCESS);
     }
+    sites.sort_by_key(|(domain, _)| *domain);
 
-    if let Some(el) = doc.select(&authors_sel).next() {
-        entry.authors = el.text().collect::<Vec<_>>().join("");
-    } else {
-        eprintln!("WARN: Authors element not found.");
+    let (mut total, mut failed) = (0, 0);
+    for (domain, site) in sites {
+        for test in &site.tests {
+            total += 1;
+            let fixture = test.fixture.display();
+            match test.run(domain, site, config.timezone) {
+                Ok(problems) if problems.is_empty() => println!("[ok] {domain}: {fixture}"),
+                Ok(problems) => {
+                    failed += 1;
+                    println!("[failed] {domain}: {fixture}");
+                    for problem in problems {
+                        println!("  {problem}");
+                    }
+                }
+                Err(err) => {
+                    failed += 1;
+                    println!("[failed] {domain}: {fixture}: {err:#}");
+                }
+            }
+        }
     }
+    if failed == 0 {
+        return Ok(ExitCode::SUCCESS);
+    }
+    eprintln!("{failed} of {total} site tests failed.");
+    Ok(ExitCode::from(Failure::Extraction.code()))
+}
 
-    if let Some(el) = doc.select(&date_sel).next() {
-        entry.published_at = el.text().collect::<Vec<_>>().join("");
-    } else {
-        eprintln!("WARN: Published At element not found.");
+fn ask_confirm(question: &str) -> bool {
+    println!("{}", question);
+    let mut input = [0];
+    let _ = std::io::stdin().read(&mut input);
+    matches!(input[0] as char, 'y' | 'Y')
+}
+
+/// What became of a stashed URL.
+enum Stashed {
+    Written(PathBuf),
+
+    /// The user declined at the preview.
+    Declined,
+
+    /// An output file already existed and was left alone.
+    Skipped,
+}
+
+/// Look for the article's discussion, if it hasn't one already.
+fn discuss(article: &mut Article, config: &Config) {
+    if !config.discussion.enabled || article.discussion.is_some() {
+        return;
+    }
+    match discussion::find(&config.discussion, &article.url) {
+        Ok(thread) => article.discussion = thread,
+        Err(err) => logging::warn(format!("Couldn't look for a discussion: {err:#}")),
     }
+}
 
-    if let Some(el) = doc.select(&body_sel).next() {
-        entry.content = el.inner_html();
+/// Fetch an article; given a discussion thread's URL
+/// (with discussions enabled), the article it's about;
+/// given a saved page's path, the article in it.
+fn fetch(
+    extractor: &Extractor,
+    config: &Config,
+    url: &str,
+    progress: &Progress,
+) -> Result<Article> {
+    let mut article = if saved::is_page(url) {
+        extractor.extract_saved(Path::new(url))?
+    } else if config.discussion.enabled && discussion::is_thread(url) {
+        let thread = discussion::thread(&config.discussion, url)?;
+        let mut article = match &thread.story_url {
+            Some(story) => extractor.fetch_article(story, progress)?,
+            None => extractor.fetch_article(url, progress)?,
+        };
+        article.discussion = Some(thread);
+        article
     } else {
-        bail!("Could not find main content element.");
+        extractor.fetch_article(url, progress)?
+    };
+    article.normalize_date(config.timezone);
+    Ok(article)
+}
+
+/// Fetch, preview, and (if confirmed) build a single article.
+fn stash(
+    extractor: &Extractor,
+    config: &Config,
+    url: &str,
+    metadata: &Metadata,
+    progress: &Progress,
+) -> Result<Stashed> {
+    let mut entry = fetch(extractor, config, url, progress)?;
+    metadata.apply(&mut entry);
+    let entry = check_length(entry, config)?;
+
+    // Preview results.
+    let confirmed = progress.suspend(|| {
+        println!("Title: {}", entry.title);
+        println!("Authors: {}", entry.authors);
+        println!("Published: {}", entry.published_at);
+        println!("Content: {}", entry.content);
+        // After the content, so it's visible without scrolling back up.
+        let stats = entry.stats();
+        println!("Extraction: {}", entry.extraction);
+        println!(
+            "Length: {} words ({}), {} paragraphs, {} images",
+            stats.words,
+            reading_time(stats.words, config.reading_speed),
+            stats.paragraphs,
+            stats.images
+        );
+        for warning in &entry.warnings {
+            println!("Warning: {warning}.");
+        }
+        ask_confirm("Ok?")
+    });
+    // As JSON, they're wanted in the log too.
+    if logging::format() == LogFormat::Json {
+        warned(&entry);
     }
-    if entry.content.is_empty() {
-        bail!("Main content element is empty.");
+    if !confirmed {
+        return Ok(Stashed::Declined);
     }
+    let (url, feeds) = (entry.url.clone(), entry.feeds.clone());
+    Ok(match build(entry, config, progress)? {
+        Some(path) => {
+            if let Err(err) = follow::offer(&url, &feeds, config) {
+                logging::warn(format!("Couldn't follow the site's feed: {err:#}"));
+            }
+            Stashed::Written(path)
+        }
+        None => Stashed::Skipped,
+    })
+}
+
+/// What's suspicious about an article's length (see `[length]`), if anything,
+/// or an error if that's configured to fail.
+fn length_problem(article: &Article, config: &Config) -> Result<Option<String>> {
+    let problem = article.length_problem(&config.length);
+    match problem {
+        Some(problem) if config.length.check == Validation::Fail => {
+            Err(eyre!(problem).wrap_err(StashError::Extraction {
+                field: Some("body"),
+                selector: None,
+            }))
+        }
+        problem => Ok(problem),
+    }
+}
 
-    Ok(entry)
+/// Note if an article's length is suspicious, or fail if that's configured.
+fn check_length(mut article: Article, config: &Config) -> Result<Article> {
+    if let Some(problem) = length_problem(&article, config)? {
+        article.warn(Warning::Length { problem });
+    }
+    Ok(article)
 }
 
-#[derive(Serialize, Default)]
-struct Article {
-    url: String,
-    title: String,
-    content: String,
-    authors: String,
-    published_at: String,
+/// Log what may have gone wrong extracting an article, all at once.
+fn warned(article: &Article) {
+    logging::emit(Event::Warnings {
+        url: &article.url,
+        warnings: &article.warnings,
+    });
 }
-impl Article {
-    fn build_epub(&self, output_dir: &Path) -> epub_builder::Result<PathBuf> {
-        let fname = slug::slugify(&self.title);
-        let fname = format!("{fname}.epub");
-        let path = output_dir.join(fname);
-        let output = fs_err::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&path)
-            .unwrap();
-
-        let content = EpubContent::new("main.xhtml", self.content.as_bytes())
-            .title(&self.title)
-            .reftype(epub_builder::ReferenceType::Text);
-        let mut builder = EpubBuilder::new(ZipLibrary::new()?)?;
-
-        builder
-            .metadata("author", &self.authors)?
-            .metadata("title", &self.title)?
-            .metadata("description", &self.url)?
-            .add_content(content)?;
-
-        match dateparser::parse(&self.published_at) {
-            Ok(parsed) => {
-                builder.set_publication_date(parsed);
-            }
-            Err(err) => {
-                eprintln!(
-                    "Failed to parse published datetime {}: {}",
-                    self.published_at, err
-                );
-            }
+
+/// Check an article's length, then build it as with `build`,
+/// logging its warnings, as there's no preview to show them in.
+fn save(mut article: Article, config: &Config, progress: &Progress) -> Result<Option<PathBuf>> {
+    article.normalize_date(config.timezone);
+    let article = check_length(article, config)?;
+    warned(&article);
+    build(article, config, progress)
+}
+
+/// Build an article's EPUB (translating and summarizing it first, if configured)
+/// and record it in the library.
+/// Returns `None` if an existing file was left alone.
+fn build(mut article: Article, config: &Config, progress: &Progress) -> Result<Option<PathBuf>> {
+    let config = config.for_site(article.profile.as_deref());
+    article.normalize_date(config.timezone);
+    translate(&mut article, config);
+    discuss(&mut article, config);
+    if let Some(summary) = &config.summary {
+        match summary::summarize(summary, &article) {
+            Ok(summary) => article.summary = summary,
+            Err(err) => logging::warn(format!("Couldn't summarize the article: {err:#}")),
         }
+    }
+    let library = Library::load()?;
+    let path = match library.copy_of(&article).filter(|_| config.dedupe) {
+        Some(original) => {
+            logging::warn(format!(
+                "{} is a copy of {}, already stashed as {}",
+                article.url,
+                original.url,
+                original.path.display()
+            ));
+            Library::link(&article, original)?;
+            RetryQueue::remove(&article.url)?;
+            None
+        }
+        None => article.build_epub(&config.output_dir(), config, progress)?,
+    };
+    if let Some(path) = &path {
+        logging::emit(Event::OutputWritten {
+            url: &article.url,
+            path,
+        });
+        Library::record(&article, path)?;
+        RetryQueue::remove(&article.url)?;
+        usage(Usage::stashed(&article));
+        archive::add(config, &article);
+        mirror::add(config, &article, path, progress);
+        written(config, path, &Book::single(&article), &article.url);
+    }
+    if let Some(webhook) = &config.webhook {
+        let report = webhook::Report {
+            status: if path.is_some() {
+                Status::Ok
+            } else {
+                Status::Skipped
+            },
+            url: &article.url,
+            title: &article.title,
+            path: path.as_deref(),
+            error: None,
+        };
+        webhook::notify(webhook, &report);
+    }
+    Ok(path)
+}
+
+/// Warn if the usage stats (see `stash stats`) couldn't be updated.
+fn usage(updated: Result<()>) {
+    if let Err(err) = updated {
+        logging::warn(format!("Couldn't update the usage stats: {err:#}"));
+    }
+}
 
-        builder.generate(output)?;
-        Ok(path)
+/// Hand a newly written book on to whatever's configured:
+/// the post-build hook, Calibre, and uploads.
+fn written(config: &Config, path: &Path, book: &Book, url: &str) {
+    hook::run(
+        &config.post_hook,
+        path,
+        url,
+        &book.title,
+        &book.authors,
+        &book.published_at,
+    );
+    if config.calibre.enabled {
+        calibre::add(config, path, book);
+    }
+    if upload::upload(config, path)
+        && !config.keep_local
+        && let Err(err) = fs_err::remove_file(path)
+    {
+        logging::warn(format!("Couldn't remove the uploaded file: {err}"));
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-#[serde(transparent)]
-struct Extractor {
-    configs: HashMap<String, ExtractionMethod>,
+/// Translate an article, if a language to translate into is configured.
+fn translate(article: &mut Article, config: &Config) {
+    if let Some(translation) = &config.translation
+        && let Some(lang) = &translation.language
+        && let Err(err) = translate::translate(translation, article, lang)
+    {
+        logging::warn(format!("Couldn't translate the article: {err:#}"));
+    }
 }
-impl Extractor {
-    fn load(path: &Path) -> Result<Self> {
-        Ok(toml::from_str(&fs_err::read_to_string(path)?)?)
-    }
-
-    fn fetch_article(&self, url: &str) -> Result<Article> {
-        let url_parsed = Url::parse(url)?;
-        let default = ExtractionMethod::default();
-        let method = match url_parsed.domain() {
-            Some(domain) => {
-                println!("Domain: {}", domain);
-                self.configs.get(domain).unwrap_or(&default)
-            }
-            None => &default,
+
+/// Report a URL that couldn't be stashed,
+/// and queue it for `stash retry`.
+fn failed(config: &Config, url: &str, err: &color_eyre::Report) {
+    logging::failed(url, err);
+    usage(Usage::failed(url, err));
+    // Newsletters from `stash mail` have no URL to fetch again,
+    // and blocked URLs would only be blocked again.
+    if (url.starts_with("http://") || url.starts_with("https://"))
+        && !matches!(StashError::of(err), Some(StashError::Blocked { .. }))
+        && let Err(err) = RetryQueue::add(url, &format!("{err:#}"))
+    {
+        logging::warn(format!("Couldn't queue {url} for retrying: {err:#}"));
+    }
+    if let Some(webhook) = &config.webhook {
+        let report = webhook::Report {
+            status: Status::Failed,
+            url,
+            title: "",
+            path: None,
+            error: Some(format!("{err:#}")),
         };
+        webhook::notify(webhook, &report);
+    }
+}
 
-        for ua in USER_AGENTS {
-            let resp = ureq::get(url).set("User-Agent", ua).call();
-            let html = match resp {
-                Err(err) => match err {
-                    ureq::Error::Status(code, resp) => {
-                        let err = format!("[{ua}]: {code} {:?}", resp.status_text());
-                        let body = resp.into_string()?;
-                        let log_path = dirs::cache_dir()
-                            .expect("Cache dir present")
-                            .join("stash-error.log");
-                        eprintln!(
-                            "{}\nResponse content written to `{}`.",
-                            err,
-                            log_path.display()
-                        );
-                        fs_err::write(log_path, body).expect("Unable to write file");
-                        continue;
-                    }
-                    err => {
-                        eprintln!("[{ua}]: {err}");
-                        continue;
-                    }
-                },
-                Ok(resp) => resp.into_string(),
-            }?;
-            return method.extract(url, &html);
-        }
+/// Load `config.toml`, with the command line's overrides of it.
+fn load_config(config_dir: &Path, opts: &Args) -> Result<Config> {
+    load_config_as(config_dir, opts, opts.profile.as_deref())
+}
 
-        Err(eyre!("All user-agents failed."))
+/// Load the config with each profile sites use, for their books
+/// (see `Config::for_site`), unless `--profile` picks one for all of them.
+fn load_site_profiles(
+    config_dir: &Path,
+    opts: &Args,
+    config: &mut Config,
+    extractor: &Extractor,
+) -> Result<()> {
+    if opts.profile.is_some() {
+        return Ok(());
+    }
+    for (profile, domain) in extractor.profiles() {
+        let profiled = load_config_as(config_dir, opts, Some(profile))
+            .wrap_err_with(|| format!("Invalid profile for `{domain}` in sites.toml"))?;
+        config.site_profiles.insert(profile.to_string(), profiled);
     }
+    Ok(())
 }
 
-#[derive(Clone, Debug, Bpaf)]
-#[bpaf(options, version)]
-/// An web article extractor.
-/// Uses automatic or manually-defined-rule extraction,
-/// then generates an epub from the extracted content.
-struct Args {
-    /// Url to extract.
-    #[bpaf(positional("URL"))]
-    url: String,
+/// Like `load_config`, with a profile.
+fn load_config_as(config_dir: &Path, opts: &Args, profile: Option<&str>) -> Result<Config> {
+    let mut config = Config::load_profile(config_dir, profile)?;
+    if opts.eink {
+        config.images.force(ImageOverrides {
+            eink: Some(true),
+            ..Default::default()
+        });
+    }
+    if opts.no_images {
+        config.images.force(ImageOverrides {
+            enabled: Some(false),
+            ..Default::default()
+        });
+    }
+    if opts.mirror {
+        config.mirror = true;
+    }
+    if let Some(jobs) = opts.jobs {
+        config.jobs = jobs;
+    }
+    if opts.to_calibre {
+        config.calibre.enabled = true;
+    }
+    if opts.discussion {
+        config.discussion.enabled = true;
+    }
+    if opts.follow {
+        config.follow.always = true;
+    }
+    if let Some(lang) = &opts.translate {
+        let Some(translation) = &mut config.translation else {
+            bail!("`--translate` needs a `[translation]` service in config.toml.");
+        };
+        translation.language = Some(lang.clone());
+    }
+    if opts.offline {
+        config.offline();
+    }
+    Ok(config)
 }
 
-fn ask_confirm(question: &str) -> bool {
-    println!("{}", question);
-    let mut input = [0];
-    let _ = std::io::stdin().read(&mut input);
-    match input[0] as char {
-        'y' | 'Y' => true,
-        _ => false,
+/// Load `sites.toml`, the community's site configs, and the plugins.
+fn load_extractor(config_dir: &Path, config: &Config, offline: bool) -> Result<Extractor> {
+    Ok(Extractor::load(&config_dir.join("sites.toml"))?
+        .with_community_sites(config_dir)?
+        .with_fivefilters(&config_dir.join("fivefilters"))?
+        .with_remote(config.remote_extractor.clone())
+        .with_nitter(config.nitter.clone())
+        .with_wikipedia(config.wikipedia.clone())
+        .with_byline(config.byline.clone())
+        .with_headless(config.headless.clone(), config.length.min_words)
+        .with_plugins(&config_dir.join("plugins"))?
+        .with_max_body_size(config.max_page_size * 1024 * 1024)
+        .with_offline(offline))
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            Failure::exit_code(&err)
+        }
     }
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<ExitCode> {
+    let config_dir = config::config_dir();
     let opts = args().run();
+    logging::init(opts.log_format);
+    if let Command::Config {
+        command: ConfigCommand::Check,
+    } = opts.command
+    {
+        return check::check(&config_dir).map(|_| ExitCode::SUCCESS);
+    }
 
-    let config_dir = dirs::config_dir()
-        .expect("Config dir exists")
-        .join(APP_NAME);
-    let config_path = config_dir.join("config.toml");
-    let config: Config = toml::from_str(&fs_err::read_to_string(config_path)?)?;
+    let mut config = load_config(&config_dir, &opts)?;
+    crypt::init(config.encryption.clone());
+    throttle::init(config.bandwidth.clone());
+    throttle::init_downloads(config.jobs.fetches);
+    blocklist::init(config.blocklist.clone());
 
-    let extractor_path = config_dir.join("sites.toml");
-    let extractor = Extractor::load(&extractor_path)?;
-    let entry = extractor.fetch_article(&opts.url)?;
+    if let Command::Sites {
+        command: SitesCommand::Update,
+    } = opts.command
+    {
+        return update_sites(&config, &config_dir);
+    }
 
-    // Preview results.
-    println!("Title: {}", entry.title);
-    println!("Authors: {}", entry.authors);
-    println!("Published: {}", entry.published_at);
-    println!("Content: {}", entry.content);
-    if ask_confirm("Ok?") {
-        let output_dir: PathBuf = shellexpand::tilde(&config.output_dir).to_string().into();
-        let path = entry.build_epub(&output_dir)?;
-        println!("{}", path.display());
+    let extractor = load_extractor(&config_dir, &config, opts.offline)?;
+    load_site_profiles(&config_dir, &opts, &mut config, &extractor)?;
+    let reloader = Reloader::new(&config_dir, &opts);
+
+    let progress = match opts.log_format {
+        // Bars would garble the JSON lines on stderr.
+        LogFormat::Json => Progress::hidden(),
+        LogFormat::Text => Progress::default(),
+    };
+    let (urls, metadata) = match opts.command {
+        Command::Config { .. } => unreachable!("Handled above"),
+        Command::Annotations { command } => return annotations::run(command),
+        Command::Bookmarks => return bookmarks::run(&extractor, &config, &progress),
+        Command::Bot { command } => return bot::run(command, &extractor, config, &progress),
+        Command::CheckLinks(_) if opts.offline => {
+            bail!("Checking links means going online, so it can't be done with --offline")
+        }
+        Command::CheckLinks(args) => {
+            return check_links::run(args, &extractor, &config, &progress);
+        }
+        Command::Daemon => return daemon::run(extractor, config, reloader, &progress),
+        Command::Diff(args) => return diff::run(args),
+        Command::Digest(args) => return digest::run(args, &extractor, &config, &progress),
+        Command::FeedOut(args) => return feed_out::run(args, &config),
+        Command::Info(args) => return info::run(args, &extractor, &config, &progress),
+        Command::Mail(args) => return mail::run(args, &extractor, &config, &progress),
+        Command::Merge(args) => return merge::run(args, &config, &progress),
+        Command::Open(args) => return open::run(args, &config),
+        Command::Preview(args) => {
+            return preview::run(args, extractor, config, reloader, &progress);
+        }
+        Command::Retry(args) => return retry::run(args, &extractor, &config, &progress),
+        Command::Sites {
+            command: SitesCommand::Test,
+        } => return test_sites(&extractor, &config),
+        Command::Sites { .. } => unreachable!("Handled above"),
+        Command::Serve(args) => return serve::run(args, extractor, config, reloader),
+        Command::Stats(args) => return stats::run(args, &extractor),
+        Command::Stash {
+            urls,
+            mut metadata,
+            quiet,
+        } => {
+            if let Some(date) = &mut metadata.date {
+                let Some(normalized) = stash_core::date::normalize(date, config.timezone) else {
+                    bail!("Couldn't parse the date `{date}`, expected e.g. `2024-03-05`.");
+                };
+                *date = normalized;
+            }
+            if quiet {
+                return Ok(stash_quietly(&extractor, &config, &urls, &metadata));
+            }
+            (urls, metadata)
+        }
+    };
+    if let [url] = urls.as_slice() {
+        let code = match stash(&extractor, &config, url, &metadata, &progress)
+            .inspect_err(|err| failed(&config, url, err))?
+        {
+            Stashed::Written(path) => {
+                println!("{}", path.display());
+                ExitCode::SUCCESS
+            }
+            Stashed::Skipped => ExitCode::SUCCESS,
+            Stashed::Declined => ExitCode::from(Failure::Aborted.code()),
+        };
+        return Ok(code);
     }
-    Ok(())
+
+    let batch = progress.batch(urls.len());
+    let mut failures = vec![];
+    for url in &urls {
+        batch.set_message(url.clone());
+        match stash(&extractor, &config, url, &metadata, &progress)
+            .inspect_err(|err| failed(&config, url, err))
+        {
+            Ok(Stashed::Written(path)) => {
+                progress.status(format!("[ok] {url} -> {}", path.display()))
+            }
+            Ok(Stashed::Declined) => progress.status(format!("[skipped] {url}")),
+            Ok(Stashed::Skipped) => progress.status(format!("[skipped] {url}: already exists")),
+            Err(err) => {
+                progress.status(format!("[failed] {url}: {err:#}"));
+                failures.push(Failure::of(&err));
+            }
+        }
+        batch.inc(1);
+    }
+    batch.finish_and_clear();
+    Ok(batch_code(&failures, urls.len()))
+}
+
+/// Stash URLs without previews, several at a time (see `jobs`), printing
+/// a line per URL to stdout as each finishes: `<status>\t<url>\t<path>`.
+/// Everything else goes to stderr.
+fn stash_quietly(
+    extractor: &Extractor,
+    config: &Config,
+    urls: &[String],
+    metadata: &Metadata,
+) -> ExitCode {
+    let failures = Mutex::new(vec![]);
+    let fetch = |url: &str| {
+        let mut article = fetch(extractor, config, url, &Progress::hidden())?;
+        metadata.apply(&mut article);
+        Ok(article)
+    };
+    jobs::stash_all(
+        urls,
+        config.jobs,
+        fetch,
+        |article| save(article, config, &Progress::hidden()),
+        |url, result| {
+            let result = result.inspect_err(|err| failed(config, url, err));
+            let (status, path) = match &result {
+                Ok(Some(path)) => ("ok", path.display().to_string()),
+                Ok(None) => ("skipped", String::new()),
+                Err(err) => {
+                    // As JSON, the failure's been logged already.
+                    if logging::format() == LogFormat::Text {
+                        eprintln!("[failed] {url}: {err:#}");
+                    }
+                    failures.lock().expect("Lock").push(Failure::of(err));
+                    ("failed", String::new())
+                }
+            };
+            println!("{status}\t{url}\t{path}");
+        },
+    );
+    batch_code(&failures.into_inner().expect("Lock"), urls.len())
+}
+
+/// The exit code for a batch of `total` URLs with these failures.
+fn batch_code(failures: &[Option<Failure>], total: usize) -> ExitCode {
+    if failures.is_empty() {
+        return ExitCode::SUCCESS;
+    }
+    eprintln!("{} of {total} URLs failed.", failures.len());
+
+    // Only report a specific class if every failure shares it.
+    let code = match failures {
+        [first, rest @ ..] if rest.iter().all(|f| f == first) => {
+            first.map_or(Failure::GENERIC_CODE, Failure::code)
+        }
+        _ => Failure::GENERIC_CODE,
+    };
+    ExitCode::from(code)
 }
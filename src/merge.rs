@@ -0,0 +1,98 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use bpaf::Bpaf;
+use chrono::Utc;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use stash_core::{
+    Article,
+    config::Config,
+    date,
+    epub::{self, Book},
+    failure::StashError,
+    logging::{self, Event},
+    merge,
+    progress::Progress,
+};
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct MergeArgs {
+    /// Title for the compilation; defaults to "Compilation" and today's date.
+    #[bpaf(argument("TITLE"))]
+    title: Option<String>,
+
+    /// Order the articles by when they were published,
+    /// rather than as the EPUBs were given.
+    by_date: bool,
+
+    /// EPUBs made by stash to merge.
+    #[bpaf(positional("EPUB"))]
+    paths: Vec<PathBuf>,
+}
+
+/// Merge EPUBs stash has made into one compilation, with a chapter
+/// (and a table of contents entry) per article, as for a digest.
+/// The books are left as they are.
+pub fn run(args: MergeArgs, config: &Config, progress: &Progress) -> Result<ExitCode> {
+    if args.paths.len() < 2 {
+        bail!("Give at least two EPUBs to merge.");
+    }
+    let mut articles: Vec<Article> = vec![];
+    for path in &args.paths {
+        articles.extend(merge::articles(path)?);
+    }
+    if args.by_date {
+        // Those with unknown dates go last.
+        articles.sort_by_key(|article| {
+            let published = date::parse(&article.published_at);
+            (published.is_none(), published)
+        });
+    }
+
+    let now = Utc::now();
+    let title = args
+        .title
+        .unwrap_or_else(|| format!("Compilation {}", now.format("%Y-%m-%d")));
+    let authors = match articles.first() {
+        Some(first)
+            if articles
+                .iter()
+                .all(|article| article.authors == first.authors) =>
+        {
+            first.authors.clone()
+        }
+        _ => "Various".to_string(),
+    };
+    let lang = match articles.first() {
+        Some(first) if articles.iter().all(|article| article.lang == first.lang) => {
+            first.lang.clone()
+        }
+        _ => String::new(),
+    };
+    let book = Book {
+        title,
+        authors,
+        published_at: now.to_rfc3339(),
+        source: format!("{} articles", articles.len()),
+        url: String::new(),
+        description: format!("A compilation of {} articles.", articles.len()),
+        lang,
+        publisher: String::new(),
+        subjects: vec![],
+        rights: String::new(),
+        series: None,
+        articles: articles.iter().collect(),
+    };
+    let path = book.path(&config.output_dir(), config);
+    let Some(path) = epub::resolve_collision(path, config.on_collision) else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    book.write(&path, config, progress)
+        .wrap_err_with(|| StashError::Output { path: path.clone() })?;
+    logging::emit(Event::OutputWritten {
+        url: "",
+        path: &path,
+    });
+    crate::written(config, &path, &book, "");
+    println!("{}", path.display());
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,88 @@
+use std::{
+    ffi::OsStr,
+    process::{Command, ExitCode},
+};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use stash_core::{
+    config::Config,
+    library::{Entry, Library},
+};
+
+/// The most other matches listed.
+const MAX_OTHERS: usize = 10;
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct OpenArgs {
+    /// Open the article's page in the browser instead of its book.
+    web: bool,
+
+    /// Words to look for in articles' titles, authors, notes, URLs, and summaries.
+    #[bpaf(positional("QUERY"), some("Give something to search for"))]
+    query: Vec<String>,
+}
+
+/// Open the stashed book that best matches a search (see `Library::search`),
+/// with `open_with` in the config or the system's default.
+pub fn run(args: OpenArgs, config: &Config) -> Result<ExitCode> {
+    let library = Library::load()?;
+    let query = args.query.join(" ");
+    let found = library.search(&query);
+    let Some(entry) = found.first() else {
+        bail!("Nothing in the library matches `{query}`.");
+    };
+    eprintln!("Opening {}", listed(entry));
+    if found.len() > 1 {
+        eprintln!("{} other(s) matched too:", found.len() - 1);
+        for other in found.iter().skip(1).take(MAX_OTHERS) {
+            eprintln!("  {} ({})", listed(other), other.url);
+        }
+        if found.len() - 1 > MAX_OTHERS {
+            eprintln!("  ...");
+        }
+    }
+    if args.web {
+        return launch(&[], entry.url.as_ref());
+    }
+    if !entry.path.exists() {
+        bail!(
+            "{} is no longer there; try `--web` to open its page.",
+            entry.path.display()
+        );
+    }
+    launch(&config.open_with, entry.path.as_os_str())
+}
+
+/// An entry's title, with its note if it has one.
+fn listed(entry: &Entry) -> String {
+    match entry.short_note() {
+        Some(note) => format!("{} [{note}]", entry.title),
+        None => entry.title.clone(),
+    }
+}
+
+/// Open a file or URL with a command, or else the system's default for it.
+/// It's left running, as readers stay open until they're closed.
+fn launch(command: &[String], target: &OsStr) -> Result<ExitCode> {
+    let mut command = match command.split_first() {
+        Some((program, args)) => {
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        None => Command::new("xdg-open"),
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .arg(target)
+        .spawn()
+        .wrap_err_with(|| format!("Couldn't run `{program}`"))?;
+    Ok(ExitCode::SUCCESS)
+}
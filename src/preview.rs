@@ -0,0 +1,159 @@
+use std::{io::Cursor, process::ExitCode};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::{Result, eyre};
+use stash_core::{
+    Article, Extractor, config::Config, normalize_url, progress::Progress, reading_time, xhtml,
+};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::reload::Reloader;
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct PreviewArgs {
+    /// Address to listen on; defaults to 127.0.0.1:8421.
+    #[bpaf(argument("ADDR"), fallback("127.0.0.1:8421".to_string()))]
+    bind: String,
+
+    /// URL to preview.
+    #[bpaf(positional("URL"))]
+    url: String,
+}
+
+/// How often the page checks for a new extraction, in milliseconds.
+const POLL_INTERVAL: u32 = 1000;
+
+/// Serve an article as it's extracted, re-extracting it whenever
+/// `sites.toml` or `config.toml` changes, for tuning a site's selectors.
+/// The page is fetched once; each re-extraction is from the page as
+/// then fetched (see `pages`). The browser reloads by polling `/version`.
+pub fn run(
+    args: PreviewArgs,
+    extractor: Extractor,
+    config: Config,
+    mut reloader: Reloader,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let url = normalize_url(&args.url);
+    let mut extraction = extractor.fetch_article(&url, progress);
+    let mut version: u64 = 1;
+    let mut page = render(&url, &extraction, &config, version);
+    if let Ok(article) = &extraction {
+        eprintln!("Extracted by {}.", article.extraction);
+    }
+
+    let server =
+        Server::http(&args.bind).map_err(|err| eyre!("Couldn't listen on {}: {err}", args.bind))?;
+    eprintln!("Previewing at http://{}", args.bind);
+
+    for request in server.incoming_requests() {
+        if let Some((config, extractor)) = reloader.changed() {
+            extraction = extractor
+                .with_offline(true)
+                .fetch_article(&url, &Progress::hidden());
+            version += 1;
+            page = render(&url, &extraction, &config, version);
+            match &extraction {
+                Ok(article) => eprintln!("Re-extracted, by {}.", article.extraction),
+                Err(err) => eprintln!("Couldn't re-extract: {err:#}"),
+            }
+        }
+
+        let path = request.url().split('?').next().unwrap_or_default();
+        let response = match (request.method(), path) {
+            (Method::Get, "/") => respond(200, "text/html; charset=utf-8", page.clone()),
+            (Method::Get, "/version") => respond(200, "text/plain", version.to_string()),
+            _ => respond(404, "text/plain", "Not found.".to_string()),
+        };
+        let _ = request.respond(response);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The article as a page, with a summary of its extraction above it
+/// like the CLI's preview, or why it couldn't be extracted.
+fn render(url: &str, extraction: &Result<Article>, config: &Config, version: u64) -> String {
+    let (title, body) = match extraction {
+        Ok(article) => {
+            let stats = article.stats();
+            let mut summary = vec![
+                format!("Extraction: {}", article.extraction),
+                format!(
+                    "Length: {} words ({}), {} paragraphs, {} images",
+                    stats.words,
+                    reading_time(stats.words, config.reading_speed),
+                    stats.paragraphs,
+                    stats.images
+                ),
+            ];
+            for warning in &article.warnings {
+                summary.push(format!("Warning: {warning}."));
+            }
+            if let Some(problem) = article.length_problem(&config.length) {
+                summary.push(format!("Warning: {problem}."));
+            }
+            let byline = [&article.authors, &article.published_at]
+                .into_iter()
+                .filter(|value| !value.is_empty())
+                .map(|value| xhtml::escape_text(value))
+                .collect::<Vec<_>>()
+                .join(" · ");
+            let summary: String = summary
+                .iter()
+                .map(|line| format!("<li>{}</li>", xhtml::escape_text(line)))
+                .collect();
+            (
+                article.title.clone(),
+                format!(
+                    "<ul class=\"stash-preview\">{summary}</ul>\n\
+                     <h1>{}</h1>\n<p class=\"byline\">{byline}</p>\n{}",
+                    xhtml::escape_text(&article.title),
+                    article.content
+                ),
+            )
+        }
+        Err(err) => (
+            "Couldn't extract".to_string(),
+            format!(
+                "<ul class=\"stash-preview error\"><li>Couldn't extract {}:</li></ul>\n<pre>{}</pre>",
+                xhtml::escape_text(url),
+                xhtml::escape_text(&format!("{err:#}"))
+            ),
+        ),
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8" />
+<title>{title}</title>
+<style>
+body {{ max-width: 40em; margin: 2em auto; padding: 0 1em; font-family: Georgia, serif; line-height: 1.5; }}
+img {{ max-width: 100%; height: auto; }}
+.stash-preview {{ list-style: none; padding: 0.5em 1em; background: #eee; font: 0.85em sans-serif; }}
+.stash-preview.error {{ background: #fdd; }}
+.byline {{ color: #666; }}
+</style>
+</head>
+<body>
+{body}
+<script>
+setInterval(async () => {{
+  try {{
+    const latest = await (await fetch("/version")).text();
+    if (latest !== "{version}") location.reload();
+  }} catch {{}}
+}}, {POLL_INTERVAL});
+</script>
+</body>
+</html>
+"#,
+        title = xhtml::escape_text(&title),
+    )
+}
+
+fn respond(status: u16, content_type: &str, body: String) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).expect("Valid header"))
+}
@@ -0,0 +1,70 @@
+//! Reloading `config.toml` and `sites.toml` when they change,
+//! for the commands that keep running.
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use stash_core::{Extractor, blocklist, config::Config, crypt, logging, throttle};
+
+use crate::Args;
+
+/// Watches the config files, by their modification times.
+pub struct Reloader {
+    config_dir: PathBuf,
+    opts: Args,
+    modified: Vec<Option<SystemTime>>,
+}
+impl Reloader {
+    pub fn new(config_dir: &Path, opts: &Args) -> Self {
+        let mut reloader = Self {
+            config_dir: config_dir.to_path_buf(),
+            opts: opts.clone(),
+            modified: vec![],
+        };
+        reloader.modified = reloader.modified();
+        reloader
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        ["config.toml", "sites.toml"]
+            .iter()
+            .map(|file| {
+                fs_err::metadata(self.config_dir.join(file))
+                    .and_then(|meta| meta.modified())
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// The config and extractor loaded again, if either file changed since
+    /// they were last loaded, with the new `[encryption]`, `[bandwidth]`, `[jobs]`,
+    /// and `blocklist` settings put in place. If they don't load (e.g. they're mid-edit),
+    /// that's warned about, and they're loaded again on their next change.
+    pub fn changed(&mut self) -> Option<(Config, Extractor)> {
+        let modified = self.modified();
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let loaded = crate::load_config(&self.config_dir, &self.opts).and_then(|mut config| {
+            let extractor = crate::load_extractor(&self.config_dir, &config, self.opts.offline)?;
+            crate::load_site_profiles(&self.config_dir, &self.opts, &mut config, &extractor)?;
+            Ok((config, extractor))
+        });
+        match loaded {
+            Ok((config, extractor)) => {
+                crypt::init(config.encryption.clone());
+                throttle::init(config.bandwidth.clone());
+                throttle::init_downloads(config.jobs.fetches);
+                blocklist::init(config.blocklist.clone());
+                Some((config, extractor))
+            }
+            Err(err) => {
+                logging::warn(format!("Not reloading the config: {err:#}"));
+                None
+            }
+        }
+    }
+}
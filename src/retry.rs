@@ -0,0 +1,79 @@
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::jobs;
+use bpaf::Bpaf;
+use color_eyre::eyre::Result;
+use stash_core::{
+    Extractor, config::Config, failure::Failure, progress::Progress, retry::RetryQueue,
+};
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct RetryArgs {
+    /// List the queued URLs instead of retrying them.
+    pub list: bool,
+}
+
+/// Stash the URLs in the retry queue again, without previews,
+/// several at a time (see `jobs`).
+/// Those that work are taken out of the queue;
+/// those that still fail stay for next time.
+pub fn run(
+    args: RetryArgs,
+    extractor: &Extractor,
+    config: &Config,
+    progress: &Progress,
+) -> Result<ExitCode> {
+    let queue = RetryQueue::load()?;
+    if args.list {
+        for entry in &queue.entries {
+            println!(
+                "{}\t{} attempt(s), last {}: {}",
+                entry.url,
+                entry.attempts,
+                entry.failed_at.format("%Y-%m-%d %H:%M"),
+                entry.error
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if queue.entries.is_empty() {
+        eprintln!("Nothing to retry.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let urls: Vec<_> = queue
+        .entries
+        .iter()
+        .map(|entry| entry.url.clone())
+        .collect();
+    let batch = progress.batch(urls.len());
+    let failures = AtomicUsize::new(0);
+    jobs::stash_all(
+        &urls,
+        config.jobs,
+        |url| extractor.fetch_article(url, progress),
+        |article| crate::save(article, config, progress),
+        |url, result| {
+            match result.inspect_err(|err| crate::failed(config, url, err)) {
+                Ok(Some(path)) => progress.status(format!("[ok] {url} -> {}", path.display())),
+                Ok(None) => progress.status(format!("[skipped] {url}: already exists")),
+                Err(err) => {
+                    progress.status(format!("[failed] {url}: {err:#}"));
+                    failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            batch.inc(1);
+        },
+    );
+    batch.finish_and_clear();
+    let failures = failures.into_inner();
+
+    if failures == 0 {
+        return Ok(ExitCode::SUCCESS);
+    }
+    eprintln!("{failures} of {} URLs failed again.", queue.entries.len());
+    Ok(ExitCode::from(Failure::GENERIC_CODE))
+}
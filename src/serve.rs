@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+    process::ExitCode,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::{Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+use stash_core::{Extractor, config::Config, library::Library, progress::Progress};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::reload::Reloader;
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct ServeArgs {
+    /// Address to listen on; defaults to 127.0.0.1:8420.
+    #[bpaf(argument("ADDR"), fallback("127.0.0.1:8420".to_string()))]
+    bind: String,
+
+    /// Require requests to have an `Authorization: Bearer <TOKEN>` header.
+    /// Strongly recommended when listening on a public address.
+    #[bpaf(argument("TOKEN"), env("STASH_SERVE_TOKEN"))]
+    token: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Status {
+    Queued,
+    Running,
+    Done { path: String },
+    Failed { error: String },
+}
+
+#[derive(Serialize, Clone)]
+struct Job {
+    id: u64,
+    url: String,
+    #[serde(flatten)]
+    status: Status,
+}
+
+#[derive(Deserialize)]
+struct StashRequest {
+    url: String,
+
+    /// The page as captured by a browser, to use instead of fetching it.
+    html: Option<String>,
+}
+
+type Jobs = Arc<Mutex<BTreeMap<u64, Job>>>;
+
+/// The most a request's body can be, in bytes:
+/// enough for a page captured by a browser.
+const MAX_REQUEST_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Serve an HTTP API for stashing articles:
+///
+/// - `POST /stash` with `{"url": ...}` queues an article, returning its job;
+///   with `"html"` too (e.g. from a bookmarklet), that's extracted
+///   instead of fetching the page;
+/// - `GET /jobs` and `GET /jobs/<id>` report on jobs;
+/// - `GET /library` lists everything stashed.
+///
+/// Articles are stashed one at a time in the background,
+/// without the usual preview, with `config.toml` and `sites.toml`
+/// as they are when each job starts.
+pub fn run(
+    args: ServeArgs,
+    mut extractor: Extractor,
+    mut config: Config,
+    mut reloader: Reloader,
+) -> Result<ExitCode> {
+    let server =
+        Server::http(&args.bind).map_err(|err| eyre!("Couldn't listen on {}: {err}", args.bind))?;
+    eprintln!("Listening on http://{}", args.bind);
+
+    let jobs: Jobs = Arc::default();
+    let (queue, queued) = mpsc::channel::<(u64, Option<String>)>();
+    let worker_jobs = jobs.clone();
+    thread::spawn(move || {
+        for (id, html) in queued {
+            if let Some((new_config, new_extractor)) = reloader.changed() {
+                (config, extractor) = (new_config, new_extractor);
+                eprintln!("Reloaded the config.");
+            }
+            work(id, html, &worker_jobs, &extractor, &config);
+        }
+    });
+
+    let mut next_id = 1;
+    for mut request in server.incoming_requests() {
+        let path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let path = path.trim_end_matches('/');
+        // Only stashing is allowed from other origins (e.g. a bookmarklet),
+        // so other pages open in the browser can't read the library or jobs,
+        // and only with a token, so they can't stash without it either.
+        // Browsers check whether it's allowed first, as requests must be JSON.
+        let cross_origin_allowed = path == "/stash" && args.token.is_some();
+        let allow = |response| {
+            if cross_origin_allowed {
+                cross_origin(response)
+            } else {
+                response
+            }
+        };
+        if *request.method() == Method::Options {
+            let _ = request.respond(allow(empty(204)));
+            continue;
+        }
+        if let Some(token) = &args.token
+            && !authorized(&request, token)
+        {
+            let _ = request.respond(allow(json(401, &Message::new("Unauthorized."))));
+            continue;
+        }
+
+        let response = match (request.method(), path) {
+            (Method::Post, "/stash") if !is_json(&request) => allow(json(
+                415,
+                &Message::new("Requests must be sent as `Content-Type: application/json`."),
+            )),
+            (Method::Post, "/stash") => allow(match read_json::<StashRequest>(&mut request) {
+                Ok(body) => {
+                    let job = Job {
+                        id: next_id,
+                        url: body.url,
+                        status: Status::Queued,
+                    };
+                    next_id += 1;
+                    jobs.lock().expect("Lock").insert(job.id, job.clone());
+                    queue.send((job.id, body.html)).expect("Worker is running");
+                    json(202, &job)
+                }
+                Err(err) => json(400, &Message::new(format!("Invalid request: {err}"))),
+            }),
+            (Method::Get, "/jobs") => {
+                let jobs: Vec<_> = jobs.lock().expect("Lock").values().cloned().collect();
+                json(200, &jobs)
+            }
+            (Method::Get, path) if path.starts_with("/jobs/") => {
+                let job = path["/jobs/".len()..]
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|id| jobs.lock().expect("Lock").get(&id).cloned());
+                match job {
+                    Some(job) => json(200, &job),
+                    None => json(404, &Message::new("No such job.")),
+                }
+            }
+            (Method::Get, "/library") => match Library::load() {
+                Ok(library) => json(200, &library),
+                Err(err) => json(500, &Message::new(format!("{err:#}"))),
+            },
+            _ => json(404, &Message::new("Not found.")),
+        };
+        let _ = request.respond(response);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn work(id: u64, html: Option<String>, jobs: &Jobs, extractor: &Extractor, config: &Config) {
+    let set_status = |status| {
+        if let Some(job) = jobs.lock().expect("Lock").get_mut(&id) {
+            job.status = status;
+        }
+    };
+    let Some(url) = jobs
+        .lock()
+        .expect("Lock")
+        .get(&id)
+        .map(|job| job.url.clone())
+    else {
+        return;
+    };
+
+    set_status(Status::Running);
+    let article = match html {
+        Some(html) => extractor.extract_article(&url, &html),
+        None => extractor.fetch_article(&url, &Progress::hidden()),
+    };
+    let result = article
+        .and_then(|article| {
+            crate::save(article, config, &Progress::hidden())?
+                .ok_or_else(|| eyre!("Output file already exists."))
+        })
+        .inspect_err(|err| crate::failed(config, &url, err));
+    set_status(match result {
+        Ok(path) => Status::Done {
+            path: path.display().to_string(),
+        },
+        Err(err) => Status::Failed {
+            error: format!("{err:#}"),
+        },
+    });
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("Authorization")
+            && header
+                .value
+                .as_str()
+                .strip_prefix("Bearer ")
+                .is_some_and(|given| same_token(given, token))
+    })
+}
+
+/// Compare tokens in constant time, so how long it takes to refuse
+/// one doesn't tell how much of it was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn is_json(request: &Request) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("Content-Type")
+            && header
+                .value
+                .as_str()
+                .split(';')
+                .next()
+                .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+    })
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_REQUEST_SIZE + 1)
+        .read_to_string(&mut body)?;
+    if body.len() as u64 > MAX_REQUEST_SIZE {
+        bail!(
+            "the body is over the {} MB limit",
+            MAX_REQUEST_SIZE / 1024 / 1024
+        );
+    }
+    Ok(serde_json::from_str(&body)?)
+}
+
+#[derive(Serialize)]
+struct Message {
+    message: String,
+}
+impl Message {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+fn json(status: u16, body: &impl Serialize) -> Response<Cursor<Vec<u8>>> {
+    let body = serde_json::to_vec(body).expect("Serializable");
+    let len = body.len();
+    empty(status)
+        .with_data(Cursor::new(body), Some(len))
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn empty(status: u16) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(vec![]).with_status_code(status)
+}
+
+/// A response with headers allowing cross-origin requests to stash.
+fn cross_origin(response: Response<Cursor<Vec<u8>>>) -> Response<Cursor<Vec<u8>>> {
+    response
+        .with_header(header("Access-Control-Allow-Origin", "*"))
+        .with_header(header("Access-Control-Allow-Methods", "POST"))
+        .with_header(header(
+            "Access-Control-Allow-Headers",
+            "Authorization, Content-Type",
+        ))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("Valid header")
+}
@@ -0,0 +1,94 @@
+use std::{cmp::Reverse, collections::BTreeMap, process::ExitCode};
+
+use bpaf::Bpaf;
+use color_eyre::eyre::Result;
+use stash_core::{Extractor, usage::Usage};
+
+#[derive(Clone, Debug, Bpaf)]
+pub struct StatsArgs {
+    /// Break the counts down by domain, those that fail most first,
+    /// with whether each has a site config.
+    extraction: bool,
+}
+
+/// Sum up the usage stats: how articles were extracted,
+/// and how stashing failed.
+pub fn run(args: StatsArgs, extractor: &Extractor) -> Result<ExitCode> {
+    let usage = Usage::load()?;
+    if usage.domains.is_empty() {
+        eprintln!("Nothing's been stashed yet.");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.extraction {
+        by_domain(&usage, extractor);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut stashed: BTreeMap<&str, u32> = BTreeMap::new();
+    let mut failed: BTreeMap<&str, u32> = BTreeMap::new();
+    for domain in usage.domains.values() {
+        for (extraction, n) in &domain.stashed {
+            *stashed.entry(extraction).or_default() += n;
+        }
+        for (class, n) in &domain.failed {
+            *failed.entry(class).or_default() += n;
+        }
+    }
+    println!(
+        "{} stashed and {} failed, from {} domain(s).",
+        stashed.values().sum::<u32>(),
+        failed.values().sum::<u32>(),
+        usage.domains.len()
+    );
+    for (heading, counts) in [("Extracted by", stashed), ("Failures", failed)] {
+        if counts.is_empty() {
+            continue;
+        }
+        println!("\n{heading}:");
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by_key(|(_, n)| Reverse(*n));
+        for (name, n) in counts {
+            println!("  {name:<12} {n}");
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// A line per domain: its failures and how its articles were extracted.
+/// Those failing without a site config are the ones to write one for.
+fn by_domain(usage: &Usage, extractor: &Extractor) {
+    let configured: Vec<_> = extractor.domains().collect();
+    let mut domains: Vec<_> = usage.domains.iter().collect();
+    domains.sort_by(|(_, a), (_, b)| {
+        b.failed_total()
+            .cmp(&a.failed_total())
+            .then(b.stashed_total().cmp(&a.stashed_total()))
+    });
+    let width = domains
+        .iter()
+        .map(|(domain, _)| domain.len())
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{:<width$}  FAILED  STASHED  CONFIG  EXTRACTED BY",
+        "DOMAIN"
+    );
+    for (domain, counts) in domains {
+        let extractions: Vec<_> = counts
+            .stashed
+            .iter()
+            .map(|(extraction, n)| format!("{extraction} {n}"))
+            .collect();
+        println!(
+            "{domain:<width$}  {:<6}  {:<7}  {:<6}  {}",
+            counts.failed_total(),
+            counts.stashed_total(),
+            if configured.contains(&domain.as_str()) {
+                "yes"
+            } else {
+                "no"
+            },
+            extractions.join(", ")
+        );
+    }
+}
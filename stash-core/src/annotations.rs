@@ -0,0 +1,522 @@
+//! Highlights and notes made on stashed books in e-readers,
+//! read from KOReader's and Kobo's annotation files.
+//!
+//! KOReader keeps a book's annotations in `metadata.epub.lua` in a `.sdr`
+//! directory beside it, as a Lua table. Kobo keeps those of sideloaded books
+//! as Adobe-style `.annot` XML files under `Digital Editions/Annotations`.
+//! Both record the book's `dc:identifier`, which is how they're matched
+//! to the library.
+
+use std::{collections::BTreeMap, io::Read, path::Path};
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use quick_xml::{Reader, XmlVersion, events::Event};
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+/// A highlighted passage of a book, with any note made on it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Highlight {
+    pub text: String,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+
+    /// The chapter it's in, if the reader says.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub chapter: String,
+
+    /// When it was made, as the reader gives it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub created_at: String,
+}
+
+/// The annotations of one book.
+#[derive(Debug, Default)]
+pub struct Annotations {
+    /// The book's identifiers, e.g. `urn:uuid:…`.
+    pub identifiers: Vec<String>,
+
+    /// The book's path on the device, if given,
+    /// to match books by filename when their identifiers don't.
+    pub book_path: Option<String>,
+    pub highlights: Vec<Highlight>,
+}
+
+impl Annotations {
+    /// Whether these are the annotations of the book with this identifier.
+    /// Readers write them inconsistently (`urn:uuid:…`, `uuid:…`, or bare).
+    pub fn is_of(&self, identifier: &str) -> bool {
+        let bare = |id: &str| {
+            let id = id.trim().to_lowercase();
+            let id = id.strip_prefix("urn:").unwrap_or(&id);
+            id.strip_prefix("uuid:").unwrap_or(id).to_string()
+        };
+        let identifier = bare(identifier);
+        self.identifiers.iter().any(|id| bare(id) == identifier)
+    }
+}
+
+/// Whether the file is one `read` understands.
+pub fn is_annotations(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    name.ends_with(".annot") || (name.starts_with("metadata.") && name.ends_with(".lua"))
+}
+
+/// Read a KOReader `metadata.*.lua` or a Kobo `.annot` file.
+pub fn read(path: &Path) -> Result<Annotations> {
+    let contents = fs_err::read_to_string(path)?;
+    let annotations = if path.extension().is_some_and(|ext| ext == "annot") {
+        kobo(&contents)
+    } else {
+        koreader(&contents)
+    };
+    annotations.wrap_err_with(|| format!("Couldn't read annotations from {}", path.display()))
+}
+
+/// The `dc:identifier` of an EPUB.
+pub fn identifier(path: &Path) -> Result<String> {
+    let mut zip = ZipArchive::new(fs_err::File::open(path)?)?;
+    let container = entry(&mut zip, "META-INF/container.xml")?;
+    let opf_path = first(&container, |name, attrs| {
+        (name == "rootfile").then(|| attrs.get("full-path").cloned())?
+    })
+    .ok_or_else(|| eyre!("No package document in {}", path.display()))?;
+    let opf = entry(&mut zip, &opf_path)?;
+
+    // The package's `unique-identifier` is the one readers go by,
+    // though there's usually only one anyway.
+    let mut reader = Reader::from_str(&opf);
+    let mut unique = None;
+    let mut in_identifier = false;
+    let mut found = vec![];
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let name = start.local_name();
+                let name: &str = AsRef::<str>::as_ref(&name);
+                let attrs = attributes(&start)?;
+                if name == "package" {
+                    unique = attrs.get("unique-identifier").cloned();
+                } else if name == "identifier" {
+                    in_identifier = true;
+                    found.push((attrs.get("id").cloned(), String::new()));
+                }
+            }
+            Event::Text(text) if in_identifier => {
+                if let Some((_, value)) = found.last_mut() {
+                    value.push_str(&text.xml_content(XmlVersion::Implicit1_0));
+                }
+            }
+            Event::End(_) => in_identifier = false,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    found
+        .iter()
+        .find(|(id, _)| id.is_some() && *id == unique)
+        .or(found.first())
+        .map(|(_, value)| value.trim().to_string())
+        .ok_or_else(|| eyre!("No identifier in {}", path.display()))
+}
+
+pub(crate) fn entry(zip: &mut ZipArchive<fs_err::File>, name: &str) -> Result<String> {
+    let mut contents = String::new();
+    zip.by_name(name)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// The first element of an XML document for which `f` gives something.
+pub(crate) fn first<T>(
+    xml: &str,
+    mut f: impl FnMut(&str, &BTreeMap<String, String>) -> Option<T>,
+) -> Option<T> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().ok()? {
+            Event::Start(start) | Event::Empty(start) => {
+                let name = start.local_name();
+                let attrs = attributes(&start).ok()?;
+                if let Some(found) = f(AsRef::<str>::as_ref(&name), &attrs) {
+                    return Some(found);
+                }
+            }
+            Event::Eof => return None,
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn attributes(
+    start: &quick_xml::events::BytesStart,
+) -> Result<BTreeMap<String, String>> {
+    let mut attrs = BTreeMap::new();
+    for attr in start.attributes() {
+        let attr = attr?;
+        let value = attr.normalized_value(XmlVersion::Implicit1_0)?;
+        let name = attr.key.local_name();
+        attrs.insert(AsRef::<str>::as_ref(&name).to_string(), value.to_string());
+    }
+    Ok(attrs)
+}
+
+/// Parse an Adobe-style annotation set, as Kobo writes:
+///
+/// ```xml
+/// <annotationSet xmlns="http://ns.adobe.com/digitaleditions/annotations" …>
+///   <publication><dc:identifier>urn:uuid:…</dc:identifier></publication>
+///   <annotation>
+///     <dc:date>2024-03-05T12:00:00Z</dc:date>
+///     <target><fragment start="…" end="…"><text>The passage</text></fragment></target>
+///     <content><text>A note</text></content>
+///   </annotation>
+/// </annotationSet>
+/// ```
+fn kobo(xml: &str) -> Result<Annotations> {
+    let mut reader = Reader::from_str(xml);
+    let mut annotations = Annotations::default();
+    let mut path: Vec<String> = vec![];
+    let mut text = String::new();
+    let mut highlight: Option<Highlight> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let name = start.local_name();
+                let name = AsRef::<str>::as_ref(&name).to_string();
+                if name == "annotation" {
+                    highlight = Some(Highlight::default());
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Text(content) => text.push_str(&content.xml_content(XmlVersion::Implicit1_0)),
+            Event::CData(content) => text.push_str(content.as_ref()),
+            Event::GeneralRef(entity) => {
+                let name: &str = entity.as_ref();
+                if let Some(c) = entity.resolve_char_ref().ok().flatten() {
+                    text.push(c);
+                } else if let Some(c) = predefined_entity(name) {
+                    text.push_str(c);
+                }
+            }
+            Event::End(_) => {
+                let parents: Vec<&str> = path.iter().map(String::as_str).collect();
+                let value = text.trim().to_string();
+                match parents.as_slice() {
+                    [.., "publication", "identifier"] => annotations.identifiers.push(value),
+                    [.., "annotation", "date"] => {
+                        if let Some(highlight) = &mut highlight {
+                            highlight.created_at = value;
+                        }
+                    }
+                    [.., "target", "fragment", "text"] => {
+                        if let Some(highlight) = &mut highlight {
+                            highlight.text = value;
+                        }
+                    }
+                    [.., "content", "text"] => {
+                        if let Some(highlight) = &mut highlight {
+                            highlight.note = value;
+                        }
+                    }
+                    [.., "annotation"] => {
+                        if let Some(highlight) = highlight.take()
+                            && !highlight.text.is_empty()
+                        {
+                            annotations.highlights.push(highlight);
+                        }
+                    }
+                    _ => {}
+                }
+                path.pop();
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(annotations)
+}
+
+pub(crate) fn predefined_entity(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        _ => return None,
+    })
+}
+
+/// Read KOReader's book metadata: `annotations` in recent versions,
+/// `highlight` (keyed by page) in older ones.
+fn koreader(lua: &str) -> Result<Annotations> {
+    let metadata = Lua::parse(lua)?;
+    let doc_props = metadata.get("doc_props");
+    let mut annotations = Annotations {
+        identifiers: doc_props
+            .and_then(|props| props.get("identifiers"))
+            .and_then(Lua::as_str)
+            .map(|identifiers| {
+                identifiers
+                    .lines()
+                    .map(|id| id.trim().to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        book_path: metadata
+            .get("doc_path")
+            .and_then(Lua::as_str)
+            .map(String::from),
+        highlights: vec![],
+    };
+
+    let highlight = |item: &Lua| {
+        let field = |name| {
+            item.get(name)
+                .and_then(Lua::as_str)
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+        Highlight {
+            text: field("text"),
+            note: field("note"),
+            chapter: field("chapter"),
+            created_at: field("datetime"),
+        }
+    };
+    if let Some(items) = metadata.get("annotations") {
+        annotations.highlights.extend(items.values().map(highlight));
+    } else if let Some(pages) = metadata.get("highlight") {
+        annotations
+            .highlights
+            .extend(pages.values().flat_map(Lua::values).map(highlight));
+    }
+    // Bookmarks without a highlighted passage have nothing to keep.
+    annotations
+        .highlights
+        .retain(|highlight| !highlight.text.is_empty());
+    Ok(annotations)
+}
+
+/// The subset of Lua that KOReader writes its metadata in:
+/// a `return`ed table of strings, numbers, booleans, and more tables.
+#[derive(Debug)]
+enum Lua {
+    Str(String),
+    Other,
+    Table(Vec<(Option<String>, Lua)>),
+}
+impl Lua {
+    fn parse(lua: &str) -> Result<Self> {
+        let mut parser = LuaParser {
+            chars: lua.chars().collect(),
+            pos: 0,
+        };
+        parser.skip_space();
+        if !parser.eat_word("return") {
+            bail!("Expected a `return`ed table");
+        }
+        parser.value()
+    }
+
+    /// A table field by name.
+    fn get(&self, key: &str) -> Option<&Lua> {
+        match self {
+            Lua::Table(fields) => fields
+                .iter()
+                .find(|(name, _)| name.as_deref() == Some(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// A table's values, in order.
+    fn values(&self) -> impl Iterator<Item = &Lua> {
+        let fields = match self {
+            Lua::Table(fields) => fields.as_slice(),
+            _ => &[],
+        };
+        let mut fields: Vec<_> = fields.iter().collect();
+        // Numeric keys are written as `[1]`, `[2]`, etc., in no particular order.
+        fields.sort_by_key(|(name, _)| name.as_deref().and_then(|name| name.parse::<u64>().ok()));
+        fields.into_iter().map(|(_, value)| value)
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Lua::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+struct LuaParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+impl LuaParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = self.peek().ok_or_else(|| eyre!("Unexpected end of file"))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_space();
+        let c = self.next()?;
+        if c != expected {
+            bail!(
+                "Expected `{expected}` but found `{c}` at character {}",
+                self.pos
+            );
+        }
+        Ok(())
+    }
+
+    /// Skip whitespace and `--` comments.
+    fn skip_space(&mut self) {
+        loop {
+            while self.peek().is_some_and(char::is_whitespace) {
+                self.pos += 1;
+            }
+            if self.chars[self.pos..].starts_with(&['-', '-']) {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let word: Vec<char> = word.chars().collect();
+        if self.chars[self.pos..].starts_with(&word) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Lua> {
+        self.skip_space();
+        match self.peek() {
+            Some('{') => self.table(),
+            Some('"' | '\'') => self.string().map(Lua::Str),
+            Some(_) => {
+                // Numbers, booleans, and `nil` are all skipped over alike.
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '+' | '_'))
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    bail!("Unexpected `{}` at character {}", self.next()?, self.pos);
+                }
+                Ok(Lua::Other)
+            }
+            None => bail!("Unexpected end of file"),
+        }
+    }
+
+    fn table(&mut self) -> Result<Lua> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        let mut index = 0;
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Lua::Table(fields));
+                }
+                Some(',' | ';') => self.pos += 1,
+                Some('[') => {
+                    self.pos += 1;
+                    self.skip_space();
+                    let key = if matches!(self.peek(), Some('"' | '\'')) {
+                        self.string()?
+                    } else {
+                        let start = self.pos;
+                        while self.peek().is_some_and(|c| c != ']') {
+                            self.pos += 1;
+                        }
+                        let key: String = self.chars[start..self.pos].iter().collect();
+                        key.trim().to_string()
+                    };
+                    self.expect(']')?;
+                    self.expect('=')?;
+                    fields.push((Some(key), self.value()?));
+                }
+                Some(c) if c.is_alphabetic() || c == '_' => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                        self.pos += 1;
+                    }
+                    let name: String = self.chars[start..self.pos].iter().collect();
+                    self.skip_space();
+                    if self.peek() == Some('=') {
+                        self.pos += 1;
+                        fields.push((Some(name), self.value()?));
+                    } else {
+                        // A bare `true`, `false`, or `nil`.
+                        index += 1;
+                        fields.push((Some(index.to_string()), Lua::Other));
+                    }
+                }
+                Some(_) => {
+                    index += 1;
+                    fields.push((Some(index.to_string()), self.value()?));
+                }
+                None => bail!("Unclosed table"),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let quote = self.next()?;
+        let mut value = String::new();
+        loop {
+            match self.next()? {
+                c if c == quote => return Ok(value),
+                '\\' => match self.next()? {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    '\n' => value.push('\n'),
+                    c if c.is_ascii_digit() => {
+                        // `\ddd`, a byte in decimal; KOReader writes UTF-8 as is,
+                        // so these are only ever ASCII control characters.
+                        let mut code = c.to_digit(10).unwrap_or_default();
+                        for _ in 0..2 {
+                            match self.peek().and_then(|c| c.to_digit(10)) {
+                                Some(digit) => {
+                                    code = code * 10 + digit;
+                                    self.pos += 1;
+                                }
+                                None => break,
+                            }
+                        }
+                        value.extend(char::from_u32(code));
+                    }
+                    c => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+
+use crate::{
+    Article,
+    config::{self, Config},
+    crypt,
+    epub::Book,
+    logging, xhtml,
+};
+
+/// Add an article to the git archive: its content as a standalone HTML
+/// page, and its metadata as JSON next to it, at the same path as the
+/// book would have under the output dir. The repo's created if need be.
+/// Failures are only warned about, as the book's been written regardless.
+pub fn add(config: &Config, article: &Article) {
+    let Some(archive) = &config.archive else {
+        return;
+    };
+    if let Err(err) = run(&archive.dir(), config, article) {
+        logging::warn(format!("Couldn't add to the archive: {err:#}"));
+    }
+}
+
+fn run(dir: &Path, config: &Config, article: &Article) -> Result<()> {
+    // git can only commit one article at a time.
+    let _lock = config::lock_data();
+    if !dir.join(".git").exists() {
+        fs_err::create_dir_all(dir)?;
+        git(dir, &["init", "--quiet"])?;
+    }
+
+    let page = Book::single(article)
+        .path(dir, config)
+        .with_extension("html");
+    let meta = page.with_extension("json");
+    if let Some(parent) = page.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    crypt::write(&page, html(article))?;
+    let mut metadata = serde_json::to_value(article)?;
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.remove("content");
+    }
+    crypt::write(&meta, serde_json::to_string_pretty(&metadata)? + "\n")?;
+
+    let files: Vec<PathBuf> = [page, meta]
+        .into_iter()
+        .map(|path| {
+            path.strip_prefix(dir)
+                .map(Path::to_path_buf)
+                .unwrap_or(path)
+        })
+        .collect();
+    let files: Vec<&str> = files.iter().filter_map(|path| path.to_str()).collect();
+    git(dir, &[&["add", "--"], &files[..]].concat())?;
+
+    // Re-stashing an unchanged article leaves nothing to commit.
+    let unchanged = Command::new("git")
+        .current_dir(dir)
+        .args(["diff", "--cached", "--quiet", "--"])
+        .args(&files)
+        .status()?
+        .success();
+    if !unchanged {
+        let message = format!("stash: {}", article.title);
+        git(
+            dir,
+            &[&["commit", "--quiet", "-m", &message, "--"], &files[..]].concat(),
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .wrap_err("Couldn't run git")?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn html(article: &Article) -> String {
+    let lang = if article.lang.is_empty() {
+        String::new()
+    } else {
+        format!(r#" lang="{}""#, xhtml::escape_attr(&article.lang))
+    };
+    let meta = [
+        ("author", &article.authors),
+        ("date", &article.published_at),
+        ("source", &article.url),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(name, value)| {
+        format!(
+            r#"<meta name="{name}" content="{}" />"#,
+            xhtml::escape_attr(value)
+        )
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+    let title = xhtml::escape_text(&article.title);
+    format!(
+        "<!DOCTYPE html>\n<html{lang}>\n<head>\n<meta charset=\"utf-8\" />\n\
+         <title>{title}</title>\n{meta}\n</head>\n<body>\n<h1>{title}</h1>\n{}\n</body>\n</html>\n",
+        article.content
+    )
+}
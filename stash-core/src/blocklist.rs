@@ -0,0 +1,40 @@
+//! The domains never to fetch from (`blocklist` in config.toml), checked
+//! before every request stash makes for an article: its page, images,
+//! mirrored assets, comments, discussions, feeds, and link checks.
+
+use std::sync::RwLock;
+
+use color_eyre::eyre::{Result, eyre};
+use url::Url;
+
+use crate::{config, failure::StashError};
+
+static BLOCKLIST: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Set the blocklist, until it's set again (e.g. when the config's reloaded).
+pub fn init(blocklist: Vec<String>) {
+    *BLOCKLIST.write().expect("Lock") = blocklist;
+}
+
+/// The blocklisted domain a URL is on, if any.
+pub fn blocked(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    BLOCKLIST
+        .read()
+        .expect("Lock")
+        .iter()
+        .find(|domain| config::on_domain(host, domain))
+        .cloned()
+}
+
+/// Fail if a URL is on a blocklisted domain.
+pub fn check(url: &str) -> Result<()> {
+    match blocked(url) {
+        Some(domain) => Err(eyre!(StashError::Blocked {
+            url: url.to_string(),
+            domain,
+        })),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,132 @@
+//! Tuning automatic extraction's byline and date for sites it nearly
+//! gets right, without writing a whole manual config for them:
+//! `[byline]` in `config.toml` for every site, or `[<domain>.byline]`
+//! in `sites.toml` for one. Whatever these find is used over what
+//! automatic extraction found; where they find nothing, that's kept.
+
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::Article;
+
+/// Where bylines usually are, unlike a sidebar's or a related article's.
+const HEADER: &str = "header";
+
+/// Byline-ish elements to look for in the header.
+const HEADER_AUTHORS: &str = "[rel~=author], [itemprop~=author], [class*=byline], [class*=author]";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Byline {
+    /// Take the authors from links with `rel="author"`.
+    #[serde(default)]
+    pub prefer_rel_author: bool,
+
+    /// Look for the byline and date in the page's `<header>`s,
+    /// and only there for `rel="author"` links.
+    #[serde(default)]
+    pub header_only: bool,
+
+    /// Where the authors are, tried before anything else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authors: Option<String>,
+
+    /// Where the date is, tried before anything else; an element's
+    /// `datetime` attribute is used over its text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+impl Byline {
+    fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+/// Find an automatically extracted article's byline and date again,
+/// as these settings say.
+pub fn apply(settings: &Byline, html: &str, article: &mut Article) {
+    if !settings.is_set() {
+        return;
+    }
+    let doc = Html::parse_document(html);
+    let scope = if settings.header_only {
+        format!("{HEADER} ")
+    } else {
+        String::new()
+    };
+    let hinted = |sel: &Option<String>| {
+        let sel = Selector::parse(sel.as_deref()?).ok()?;
+        doc.select(&sel).next()
+    };
+
+    let rel_author = || {
+        settings
+            .prefer_rel_author
+            .then(|| texts(&doc, &format!("{scope}[rel~=author]")))
+            .flatten()
+    };
+    let in_header = || {
+        settings
+            .header_only
+            .then(|| {
+                let sel = HEADER_AUTHORS
+                    .split(", ")
+                    .map(|sel| format!("{HEADER} {sel}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                texts(&doc, &sel)
+            })
+            .flatten()
+    };
+    let authors = hinted(&settings.authors)
+        .map(text)
+        .filter(|authors| !authors.is_empty())
+        .or_else(rel_author)
+        .or_else(in_header);
+    if let Some(authors) = authors {
+        article.authors = authors;
+    }
+
+    let date = hinted(&settings.date).or_else(|| {
+        let sel = Selector::parse(&format!("{HEADER} time")).expect("Valid selector");
+        settings
+            .header_only
+            .then(|| doc.select(&sel).next())
+            .flatten()
+    });
+    if let Some(el) = date {
+        let date = el
+            .attr("datetime")
+            .map(|date| date.trim().to_string())
+            .unwrap_or_else(|| text(el));
+        if !date.is_empty() {
+            article.published_at = date;
+        }
+    }
+}
+
+/// The distinct texts of the elements matching a selector, as a list
+/// of names; nothing if there are none.
+fn texts(doc: &Html, sel: &str) -> Option<String> {
+    let sel = Selector::parse(sel).ok()?;
+    let mut names: Vec<String> = vec![];
+    for name in doc.select(&sel).map(text) {
+        // A byline's container holds the author's link, too.
+        if !name.is_empty() && !names.iter().any(|other| other.contains(&name)) {
+            names.retain(|other| !name.contains(other.as_str()));
+            names.push(name);
+        }
+    }
+    (!names.is_empty()).then(|| names.join(", "))
+}
+
+/// An element's text as a byline: without "By", and with its
+/// whitespace collapsed.
+fn text(el: ElementRef) -> String {
+    let text = el.text().collect::<Vec<_>>().join(" ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.strip_prefix("By ")
+        .or_else(|| text.strip_prefix("by "))
+        .unwrap_or(&text)
+        .to_string()
+}
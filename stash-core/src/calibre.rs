@@ -0,0 +1,65 @@
+use std::{path::Path, process::Command};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+
+use crate::{config::Config, epub::Book, logging};
+
+/// Add a newly written book to a Calibre library with `calibredb add`,
+/// mapping its metadata onto Calibre's fields. Failures are only
+/// warned about, as the file's been written regardless.
+pub fn add(config: &Config, path: &Path, book: &Book) {
+    if let Err(err) = run(config, path, book) {
+        logging::warn(format!("Couldn't add to Calibre: {err:#}"));
+    }
+}
+
+fn run(config: &Config, path: &Path, book: &Book) -> Result<()> {
+    let mut cmd = Command::new("calibredb");
+    cmd.arg("add");
+    if let Some(library) = config.calibre.library() {
+        cmd.arg("--with-library").arg(library);
+    }
+    cmd.arg("--title").arg(book.full_title(config));
+    if !book.authors.is_empty() {
+        cmd.arg("--authors").arg(authors(&book.authors));
+    }
+    if !book.subjects.is_empty() {
+        cmd.arg("--tags").arg(book.subjects.join(","));
+    }
+    if !book.lang.is_empty() {
+        cmd.arg("--languages").arg(&book.lang);
+    }
+    if !book.url.is_empty() {
+        cmd.arg("--identifier").arg(format!("uri:{}", book.url));
+    }
+    if let Some(series) = &book.series {
+        cmd.arg("--series")
+            .arg(&series.title)
+            .arg("--series-index")
+            .arg(series.index.to_string());
+    }
+    let output = cmd
+        .arg(path)
+        .output()
+        .wrap_err("Couldn't run `calibredb`")?;
+    if !output.status.success() {
+        bail!(
+            "`calibredb` failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Calibre separates authors with `&`,
+/// where bylines usually use commas and "and".
+fn authors(byline: &str) -> String {
+    byline
+        .split([',', '&'])
+        .flat_map(|part| part.split(" and "))
+        .map(str::trim)
+        .filter(|author| !author.is_empty())
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
@@ -0,0 +1,168 @@
+//! A page's own comment section, for sites configured to keep it
+//! (`[<domain>.comments]` in `sites.toml`): found with selectors, or
+//! fetched from the comment platform the page embeds. It's added to the
+//! book as an appendix after the article.
+
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, bail, eyre};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use crate::{USER_AGENTS, blocklist, discussion::Comment, logging};
+
+const DISQUS_EMBED: &str = "https://disqus.com/embed/comments/";
+
+/// A site's `[comments]` settings: either selectors or a platform.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Comments {
+    /// Each comment, e.g. `#comments .comment`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<String>,
+
+    /// Within a comment, its author's name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    /// Within a comment, its text; the whole comment if it's not given,
+    /// which for nested comments includes their replies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+
+    /// The platform the page embeds its comments from, instead of selectors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+
+    /// The most comments to keep, in the order they're found.
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Disqus,
+}
+
+/// Find the comments of a page. Failures are only warned about,
+/// as the article's been extracted regardless.
+pub fn find(settings: &Comments, url: &str, html: &str, offline: bool) -> Vec<Comment> {
+    let found = match (&settings.platform, &settings.selector) {
+        (Some(Platform::Disqus), _) if offline => Ok(vec![]),
+        (Some(Platform::Disqus), _) => disqus(url, html),
+        (None, Some(selector)) => select(settings, selector, html),
+        (None, None) => Err(eyre!("Give either a `selector` or a `platform`")),
+    };
+    match found {
+        Ok(mut comments) => {
+            comments.truncate(settings.limit);
+            comments
+        }
+        Err(err) => {
+            logging::warn(format!("Couldn't get the comments of {url}: {err:#}"));
+            vec![]
+        }
+    }
+}
+
+fn select(settings: &Comments, selector: &str, html: &str) -> Result<Vec<Comment>> {
+    let parse =
+        |sel: &str| Selector::parse(sel).map_err(|err| eyre!("Invalid selector `{sel}`: {err}"));
+    let comment = parse(selector)?;
+    let author = settings.author.as_deref().map(parse).transpose()?;
+    let body = settings.body.as_deref().map(parse).transpose()?;
+    let doc = Html::parse_document(html);
+    Ok(doc
+        .select(&comment)
+        .filter_map(|el| {
+            let text = match &body {
+                Some(sel) => el.select(sel).next()?,
+                None => el,
+            };
+            let html = text.inner_html().trim().to_string();
+            let author = author
+                .as_ref()
+                .and_then(|sel| el.select(sel).next())
+                .map(|el| el.text().collect::<String>().trim().to_string())
+                .unwrap_or_default();
+            (!html.is_empty()).then_some(Comment {
+                author,
+                html,
+                points: None,
+            })
+        })
+        .collect())
+}
+
+/// The comments of a page embedding Disqus, from the thread data
+/// in Disqus' embed page (which pages load in an iframe).
+fn disqus(url: &str, html: &str) -> Result<Vec<Comment>> {
+    let Some(shortname) = disqus_shortname(html) else {
+        bail!("There's no Disqus embed on the page");
+    };
+    let embed = Url::parse_with_params(
+        DISQUS_EMBED,
+        &[("base", "default"), ("f", &shortname), ("t_u", url)],
+    )?;
+    blocklist::check(embed.as_str())?;
+    let page = ureq::get(embed.as_str())
+        .timeout(Duration::from_secs(30))
+        .set("User-Agent", USER_AGENTS[0])
+        .set("Referer", url)
+        .call()?
+        .into_string()?;
+    let doc = Html::parse_document(&page);
+    let sel = Selector::parse("script#disqus-threadData").expect("Valid selector");
+    let Some(data) = doc.select(&sel).next() else {
+        bail!("Disqus' embed page has no thread data");
+    };
+    let data: Value = serde_json::from_str(&data.text().collect::<String>())?;
+    let posts = data["response"]["posts"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    Ok(posts
+        .iter()
+        .filter(|post| !post["isDeleted"].as_bool().unwrap_or_default())
+        .map(|post| Comment {
+            author: post["author"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            html: post["message"].as_str().unwrap_or_default().to_string(),
+            points: post["likes"].as_i64(),
+        })
+        .collect())
+}
+
+/// The site's Disqus shortname, from its embed script
+/// (`//<shortname>.disqus.com/embed.js`) or its `disqus_shortname` variable.
+fn disqus_shortname(html: &str) -> Option<String> {
+    let valid = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    if let Some(end) = html.find(".disqus.com/embed.js") {
+        let start = html[..end]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .map_or(0, |i| i + 1);
+        let name = &html[start..end];
+        if valid(name) {
+            return Some(name.to_string());
+        }
+    }
+    let rest = &html[html.find("disqus_shortname")? + "disqus_shortname".len()..];
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let name = &rest[1..1 + rest[1..].find(quote)?];
+    valid(name).then(|| name.to_string())
+}
@@ -0,0 +1,1184 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use chrono_tz::Tz;
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use epub_builder::EpubVersion;
+use serde::{Deserialize, Serialize};
+
+use crate::{APP_NAME, byline::Byline, failure::StashError, logging};
+
+/// Prefix for environment variables that override config values,
+/// e.g. `STASH_OUTPUT_DIR` for `output_dir`. Nested keys are
+/// separated with a double underscore, e.g. `STASH_FOO__BAR` for `foo.bar`.
+const ENV_PREFIX: &str = "STASH_";
+
+/// Environment variable for the config dir itself,
+/// which is where `config.toml` and `sites.toml` are looked up.
+const CONFIG_DIR_VAR: &str = "STASH_CONFIG_DIR";
+
+/// Written on first run, when there's no config yet.
+const DEFAULT_CONFIG: &str = include_str!("assets/config.toml");
+
+/// Environment variable for the data dir.
+const DATA_DIR_VAR: &str = "STASH_DATA_DIR";
+
+/// Environment variable for the cache dir.
+const CACHE_DIR_VAR: &str = "STASH_CACHE_DIR";
+
+/// Environment variable for the passphrase of encrypted files, see `crypt`.
+pub const PASSPHRASE_VAR: &str = "STASH_PASSPHRASE";
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub output_dir: String,
+
+    /// Whether to generate a cover for each EPUB.
+    #[serde(default = "default_true")]
+    pub cover: bool,
+
+    /// An SVG template to use for generated covers,
+    /// instead of the built-in one.
+    pub cover_template: Option<String>,
+
+    /// A CSS file to embed as the EPUB's stylesheet.
+    pub stylesheet: Option<String>,
+
+    /// The size of books' text (e.g. `120%` or `1.1em`),
+    /// for readers whose default is too small or large.
+    pub font_size: Option<String>,
+
+    /// Fonts to package into the EPUB.
+    #[serde(default)]
+    pub fonts: Vec<Font>,
+
+    /// Subdirectories of the output dir to sort articles into,
+    /// by site or tag; the first that matches is used.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+
+    /// Template for output filenames (relative to the output dir),
+    /// see `Book::path` for the placeholders.
+    #[serde(default = "default_filename")]
+    pub filename: String,
+
+    /// Whether titles are transliterated into ASCII for filenames;
+    /// if not, their letters are kept in whatever script they're in.
+    #[serde(default = "default_true")]
+    pub transliterate: bool,
+
+    /// Whether an article that's already been stashed under another URL
+    /// (e.g. syndicated elsewhere) is linked to that copy in the library
+    /// instead of written again.
+    #[serde(default = "default_true")]
+    pub dedupe: bool,
+
+    /// How embedded images are downscaled and re-encoded.
+    #[serde(default)]
+    pub images: Images,
+
+    /// Text polishing (smart quotes etc.).
+    #[serde(default)]
+    pub typography: Typography,
+
+    /// Words per minute, for estimating reading times.
+    #[serde(default = "default_reading_speed")]
+    pub reading_speed: usize,
+
+    /// Template for the EPUB's title metadata,
+    /// see `Book::full_title` for the placeholders.
+    #[serde(default = "default_title")]
+    pub title: String,
+
+    /// What to do when the output file already exists.
+    #[serde(default)]
+    pub on_collision: Collision,
+
+    /// Download rate limits.
+    #[serde(default)]
+    pub bandwidth: Bandwidth,
+
+    /// How many articles to fetch and build at once.
+    #[serde(default)]
+    pub jobs: Jobs,
+
+    /// Domains (and their subdomains) never to fetch from.
+    #[serde(default)]
+    pub blocklist: Vec<String>,
+
+    /// The largest page to fetch, in megabytes.
+    #[serde(default = "default_max_page_size")]
+    pub max_page_size: u64,
+
+    /// The timezone of publication dates that don't give one, e.g. "Europe/Berlin".
+    #[serde(default)]
+    pub timezone: Tz,
+
+    /// The EPUB version to generate, 3 or 2.
+    #[serde(default = "default_epub_version")]
+    pub epub_version: u8,
+
+    /// Articles larger than this (in kilobytes of HTML) are split into
+    /// several content documents, see `xhtml::split`; 0 never splits them.
+    #[serde(default = "default_max_document_size")]
+    pub max_document_size: usize,
+
+    /// Whether to check each book's structure once it's written.
+    #[serde(default)]
+    pub validate: Validation,
+
+    /// The lengths of articles that were likely misextracted.
+    #[serde(default)]
+    pub length: Length,
+
+    /// A mailbox to stash articles from, see `stash mail`.
+    pub mail: Option<Mail>,
+
+    /// A Telegram bot to stash articles from, see `stash bot telegram`.
+    pub telegram: Option<Telegram>,
+
+    /// A Matrix bot to stash articles from, see `stash bot matrix`.
+    pub matrix: Option<Matrix>,
+
+    /// A bookmarking service to stash articles from, see `stash bookmarks`.
+    pub bookmarks: Option<Bookmarks>,
+
+    /// What `stash daemon` polls, and how often.
+    #[serde(default)]
+    pub daemon: Daemon,
+
+    /// When to offer to add stashed sites' feeds to `daemon.feeds`.
+    #[serde(default)]
+    pub follow: Follow,
+
+    /// How Wikipedia articles are stashed.
+    #[serde(default)]
+    pub wikipedia: Wikipedia,
+
+    /// How automatic extraction finds bylines and dates, see `byline`.
+    #[serde(default)]
+    pub byline: Byline,
+
+    /// Where to report each stashed (or failed) article.
+    pub webhook: Option<Webhook>,
+
+    /// A command to run on each newly written file, see `hook::run`.
+    #[serde(default)]
+    pub post_hook: Vec<String>,
+
+    /// A command to open books with in `stash open`, given the book's
+    /// path; the system's default for EPUBs if it's not set.
+    #[serde(default)]
+    pub open_with: Vec<String>,
+
+    /// A model to summarize articles with.
+    pub summary: Option<Summary>,
+
+    /// A machine translation service.
+    pub translation: Option<Translation>,
+
+    /// Adding books to a Calibre library.
+    #[serde(default)]
+    pub calibre: Calibre,
+
+    /// Appending articles' Hacker News or Reddit discussions.
+    #[serde(default)]
+    pub discussion: Discussion,
+
+    /// A WebDAV folder (e.g. on Nextcloud) to upload books to.
+    pub webdav: Option<WebDav>,
+
+    /// An S3-compatible bucket (e.g. on MinIO) to upload books to.
+    pub s3: Option<S3>,
+
+    /// A command to render pages in a headless browser with, given the
+    /// page's URL, printing its HTML (e.g. `["chromium", "--headless",
+    /// "--dump-dom"]`). Pages extracted automatically to fewer than
+    /// `[length]`'s `min_words` are rendered with it too.
+    #[serde(default)]
+    pub headless: Vec<String>,
+
+    /// Tried when a page can't be extracted with its site config or automatically.
+    pub remote_extractor: Option<RemoteExtractor>,
+
+    /// A Nitter instance (e.g. `https://nitter.net`) to fetch
+    /// Twitter/X threads through.
+    pub nitter: Option<String>,
+
+    /// Where `stash sites update` gets the community's site configs:
+    /// a git repo, or the URL of a `sites.toml`.
+    pub sites_source: Option<String>,
+
+    /// Whether to also save each article's page as it was,
+    /// with its assets, see `mirror::add`.
+    #[serde(default)]
+    pub mirror: bool,
+
+    /// A git repo to also keep each article in, as HTML.
+    pub archive: Option<Archive>,
+
+    /// Encrypt the library and archived pages, see `crypt`.
+    pub encryption: Option<Encryption>,
+
+    /// Whether to keep books in the output dir once they've been uploaded.
+    #[serde(default = "default_true")]
+    pub keep_local: bool,
+
+    /// Named sets of settings to use over the rest, e.g. for a device,
+    /// chosen with `--profile`; see `apply_profile`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, toml::Table>,
+
+    /// The config with each profile sites use (see `Site::profile`).
+    #[serde(skip)]
+    pub site_profiles: HashMap<String, Config>,
+}
+impl Config {
+    /// The config to make a site's books with: with its profile, if it has one.
+    pub fn for_site(&self, profile: Option<&str>) -> &Self {
+        profile
+            .and_then(|profile| self.site_profiles.get(profile))
+            .unwrap_or(self)
+    }
+
+    /// Load `config.toml` from the config dir,
+    /// with any `STASH_*` environment variables layered on top.
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        Self::load_profile(config_dir, None)
+    }
+
+    /// Like `load`, with a profile's settings over the rest.
+    pub fn load_profile(config_dir: &Path, profile: Option<&str>) -> Result<Self> {
+        let path = config_dir.join("config.toml");
+        Self::load_from(&path, profile).wrap_err(StashError::Config { path })
+    }
+
+    fn load_from(path: &Path, profile: Option<&str>) -> Result<Self> {
+        let overrides = env_overrides();
+        let mut table: toml::Table = match fs_err::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)?,
+
+            // The environment may provide everything needed,
+            // in which case the config file is optional.
+            Err(err) if err.kind() == ErrorKind::NotFound && !overrides.is_empty() => {
+                toml::Table::new()
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if let Some(dir) = path.parent() {
+                    fs_err::create_dir_all(dir)?;
+                }
+                fs_err::write(path, DEFAULT_CONFIG)?;
+                logging::warn(format!(
+                    "Created a default config at {}; edit it to change where books are saved.",
+                    path.display()
+                ));
+                toml::from_str(DEFAULT_CONFIG)?
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(profile) = profile {
+            apply_profile(&mut table, profile)?;
+        }
+        for (key, value) in overrides {
+            set_key(&mut table, &key, value);
+        }
+        let config: Self = table.try_into()?;
+        config.epub_version()?;
+        Ok(config)
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        expand_path(&self.output_dir)
+    }
+
+    pub fn cover_template(&self) -> Option<PathBuf> {
+        self.cover_template.as_deref().map(expand_path)
+    }
+
+    pub fn stylesheet(&self) -> Option<PathBuf> {
+        self.stylesheet.as_deref().map(expand_path)
+    }
+
+    /// Turn off everything that would go online besides fetching the
+    /// page: images (which aren't cached), discussions, summaries,
+    /// translations, uploads, and the webhook.
+    pub fn offline(&mut self) {
+        self.images.force(ImageOverrides {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        self.mirror = false;
+        self.discussion.enabled = false;
+        self.summary = None;
+        self.translation = None;
+        self.webdav = None;
+        self.s3 = None;
+        self.webhook = None;
+    }
+
+    pub fn epub_version(&self) -> Result<EpubVersion> {
+        match self.epub_version {
+            2 => Ok(EpubVersion::V20),
+            3 => Ok(EpubVersion::V30),
+            version => bail!("Unsupported EPUB version {version}, expected 2 or 3."),
+        }
+    }
+}
+
+/// How to handle an output file that already exists.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Collision {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+
+    /// Write to a new file with a numbered suffix, e.g. `title-2.epub`.
+    Number,
+
+    /// Leave the existing file alone and skip writing.
+    Skip,
+}
+
+/// What to do with structural problems in a written book,
+/// or with an article whose length looks wrong.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Validation {
+    /// Don't check at all.
+    Off,
+
+    /// Warn about problems but keep the book.
+    #[default]
+    Warn,
+
+    /// Fail, removing the book if it was written.
+    Fail,
+}
+
+/// Word counts outside which an article probably wasn't extracted
+/// properly; 0 doesn't check that end.
+#[derive(Deserialize, Debug)]
+pub struct Length {
+    /// Fewer words than this usually means the extraction failed,
+    /// e.g. it got a cookie banner or a paywall's teaser.
+    #[serde(default = "default_min_words")]
+    pub min_words: usize,
+
+    /// More usually means it got a whole archive or index page.
+    #[serde(default = "default_max_words")]
+    pub max_words: usize,
+
+    #[serde(default)]
+    pub check: Validation,
+}
+impl Default for Length {
+    fn default() -> Self {
+        Self {
+            min_words: default_min_words(),
+            max_words: default_max_words(),
+            check: Validation::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Images {
+    /// Whether to keep images at all; without them,
+    /// `<img>`s and `<figure>`s are removed.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Images larger than this (in pixels, either way) are downscaled.
+    #[serde(default = "default_max_dimension")]
+    pub max_dimension: u32,
+
+    /// JPEG quality to re-encode images with, 1–100.
+    #[serde(default = "default_jpeg_quality")]
+    pub jpeg_quality: u8,
+
+    /// Optimize images for e-paper displays.
+    #[serde(default)]
+    pub eink: bool,
+
+    /// Settings no site can override, e.g. from `--no-images`.
+    #[serde(skip)]
+    pub forced: ImageOverrides,
+}
+impl Default for Images {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_dimension: default_max_dimension(),
+            jpeg_quality: default_jpeg_quality(),
+            eink: false,
+            forced: ImageOverrides::default(),
+        }
+    }
+}
+impl Images {
+    /// These settings, with a site's overrides of them
+    /// (and then the forced ones over those).
+    pub fn with(&self, overrides: &ImageOverrides) -> Self {
+        self.overridden(overrides).overridden(&self.forced)
+    }
+
+    /// Set these whatever sites' settings say.
+    pub fn force(&mut self, overrides: ImageOverrides) {
+        let forced = overrides.or(&self.forced);
+        *self = self.overridden(&overrides);
+        self.forced = forced;
+    }
+
+    fn overridden(&self, overrides: &ImageOverrides) -> Self {
+        Self {
+            enabled: overrides.enabled.unwrap_or(self.enabled),
+            max_dimension: overrides.max_dimension.unwrap_or(self.max_dimension),
+            jpeg_quality: overrides.jpeg_quality.unwrap_or(self.jpeg_quality),
+            eink: overrides.eink.unwrap_or(self.eink),
+            forced: self.forced,
+        }
+    }
+}
+
+/// A site's own `[images]` settings, in `sites.toml`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ImageOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_dimension: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jpeg_quality: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eink: Option<bool>,
+}
+impl ImageOverrides {
+    /// These overrides, or where they don't set something, the others'.
+    fn or(&self, others: &Self) -> Self {
+        Self {
+            enabled: self.enabled.or(others.enabled),
+            max_dimension: self.max_dimension.or(others.max_dimension),
+            jpeg_quality: self.jpeg_quality.or(others.jpeg_quality),
+            eink: self.eink.or(others.eink),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Typography {
+    /// Whether to polish the text at all.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The language whose conventions to use, e.g. `en` or `fr`,
+    /// instead of the one the page declares.
+    pub language: Option<String>,
+}
+
+/// An IMAP mailbox whose messages are stashed:
+/// either the links they contain, or newsletters themselves.
+#[derive(Deserialize, Debug)]
+pub struct Mail {
+    pub host: String,
+
+    #[serde(default = "default_imap_port")]
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+
+    /// Whether to connect with TLS; only worth disabling
+    /// for servers on the same machine, e.g. a mail bridge.
+    #[serde(default = "default_true")]
+    pub tls: bool,
+
+    /// How often to check for new messages, in seconds.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+}
+
+/// A Telegram bot that stashes the links it's sent,
+/// replying with the books.
+#[derive(Deserialize, Debug)]
+pub struct Telegram {
+    /// The bot's token, from @BotFather.
+    pub token: String,
+
+    /// The IDs of the users allowed to use the bot; anyone can if empty.
+    #[serde(default)]
+    pub allowed_users: Vec<i64>,
+
+    /// The Bot API server, if not Telegram's own,
+    /// e.g. a local one to send books larger than 50MB.
+    #[serde(default = "default_telegram_api")]
+    pub api_url: String,
+}
+
+/// A Matrix account that stashes the links posted in some rooms,
+/// replying with the books.
+#[derive(Deserialize, Debug)]
+pub struct Matrix {
+    /// E.g. `https://matrix.org`.
+    pub homeserver: String,
+    pub access_token: String,
+
+    /// The rooms to watch, by ID (e.g. `!abc123:matrix.org`),
+    /// each with its own settings.
+    pub rooms: HashMap<String, MatrixRoom>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct MatrixRoom {
+    /// Overrides the top-level `epub_version` for this room's books.
+    pub epub_version: Option<u8>,
+
+    /// Added to the subjects of this room's books.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Jobs for `stash daemon`, each run every so often. Intervals are
+/// a number with a unit, e.g. `90s`, `30m`, `6h`, or `1d`.
+#[derive(Deserialize, Debug, Default)]
+pub struct Daemon {
+    /// Feeds whose new entries are stashed.
+    #[serde(default)]
+    pub feeds: Vec<FeedJob>,
+
+    /// How often to stash from `[bookmarks]`.
+    pub bookmarks: Option<String>,
+
+    /// How often to check the `[mail]` mailbox.
+    pub mail: Option<String>,
+
+    /// How often to retry the URLs that failed.
+    pub retry: Option<String>,
+
+    /// Up to how much later than scheduled to run each job, so that
+    /// they don't all hit the network (or a site) at once.
+    pub jitter: Option<String>,
+}
+
+/// See `follow::offer` in the CLI.
+#[derive(Deserialize, Debug)]
+pub struct Follow {
+    /// Follow every stashed site's feed without asking.
+    #[serde(default)]
+    pub always: bool,
+
+    /// Offer to follow a site's feed once this many of its articles
+    /// have been stashed; 0 never offers.
+    #[serde(default = "default_suggest_after")]
+    pub suggest_after: usize,
+
+    /// How often followed feeds are polled.
+    #[serde(default = "default_follow_every")]
+    pub every: String,
+}
+impl Default for Follow {
+    fn default() -> Self {
+        Self {
+            always: false,
+            suggest_after: default_suggest_after(),
+            every: default_follow_every(),
+        }
+    }
+}
+
+/// See `sources::wikipedia`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Wikipedia {
+    /// Whether to keep the article's images (and the infobox's).
+    #[serde(default = "default_true")]
+    pub images: bool,
+
+    /// The width (in pixels) of the thumbnails to get for images.
+    #[serde(default = "default_wikipedia_image_width")]
+    pub image_width: u32,
+}
+impl Default for Wikipedia {
+    fn default() -> Self {
+        Self {
+            images: true,
+            image_width: default_wikipedia_image_width(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FeedJob {
+    pub url: String,
+
+    /// How often to poll the feed.
+    pub every: String,
+
+    /// Stash at most this many new entries each time.
+    #[serde(default = "default_feed_limit")]
+    pub limit: usize,
+
+    /// Bundle each poll's new entries into a digest,
+    /// instead of a book per entry.
+    #[serde(default)]
+    pub digest: bool,
+
+    /// Skip entries with fewer words than this, e.g. link posts
+    /// or podcast episodes; 0 keeps them all.
+    #[serde(default)]
+    pub min_words: usize,
+}
+
+/// A linkding or Shiori instance whose bookmarks with a certain tag
+/// are stashed, then re-tagged as processed.
+#[derive(Deserialize, Debug)]
+pub struct Bookmarks {
+    pub service: BookmarkService,
+
+    /// E.g. `https://links.example.com`.
+    pub url: String,
+
+    /// The API token, for linkding.
+    pub token: Option<String>,
+
+    /// The login, for Shiori.
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Bookmarks with this tag are stashed.
+    #[serde(default = "default_bookmark_tag")]
+    pub tag: String,
+
+    /// And then this tag replaces it.
+    #[serde(default = "default_processed_tag")]
+    pub processed_tag: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BookmarkService {
+    Linkding,
+    Shiori,
+}
+
+/// A URL to POST to after each article is stashed or fails,
+/// see `webhook::notify`.
+#[derive(Deserialize, Debug)]
+pub struct Webhook {
+    pub url: String,
+
+    /// A template for the request body; a JSON object
+    /// of all the values by default.
+    pub payload: Option<String>,
+
+    /// Only report failures.
+    #[serde(default)]
+    pub failures_only: bool,
+}
+
+/// An OpenAI-compatible API (e.g. a local llama.cpp server)
+/// to generate article summaries with, see `summary::summarize`.
+#[derive(Deserialize, Debug)]
+pub struct Summary {
+    /// The API's base URL, e.g. `http://localhost:8080/v1`.
+    pub endpoint: String,
+    pub model: String,
+    pub api_key: Option<String>,
+
+    /// Instructions for the model, instead of the default ones.
+    pub prompt: Option<String>,
+}
+
+/// An external extraction service (e.g. a self-hosted Readability API)
+/// for pages stash can't extract itself, see `remote::extract`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RemoteExtractor {
+    /// Where the URL's posted, e.g. `http://localhost:3000/extract`.
+    pub url: String,
+    pub api_key: Option<String>,
+}
+
+/// A machine translation service, see `translate::translate`.
+#[derive(Deserialize, Debug)]
+pub struct Translation {
+    pub backend: TranslationBackend,
+
+    /// The API's base URL; required for LibreTranslate.
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+
+    /// Translate every article into this language, e.g. `en`.
+    pub language: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslationBackend {
+    DeepL,
+    LibreTranslate,
+}
+
+/// See `upload::upload`.
+#[derive(Deserialize, Debug)]
+pub struct WebDav {
+    /// The folder to upload to, e.g.
+    /// `https://cloud.example.com/remote.php/dav/files/me/Reading`.
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// See `upload::upload`.
+#[derive(Deserialize, Debug)]
+pub struct S3 {
+    /// The service to use, e.g. `https://s3.eu-west-1.amazonaws.com`
+    /// or `http://localhost:9000` for MinIO.
+    pub endpoint: String,
+
+    #[serde(default = "default_region")]
+    pub region: String,
+
+    pub bucket: String,
+
+    /// Prepended to each book's key, e.g. `books/`.
+    #[serde(default)]
+    pub prefix: String,
+
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// See `archive::add`.
+#[derive(Deserialize, Debug)]
+pub struct Archive {
+    pub dir: String,
+}
+impl Archive {
+    pub fn dir(&self) -> PathBuf {
+        expand_path(&self.dir)
+    }
+}
+
+/// How many articles batches (`stash -q` with many URLs, `stash retry`)
+/// fetch at once, and how many of those they build at once; builds are
+/// bound by CPU and disk (images especially) rather than the network.
+/// `fetches` caps all downloads at once too, see `throttle::download`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Jobs {
+    #[serde(default = "default_fetches")]
+    pub fetches: usize,
+
+    #[serde(default = "default_builds")]
+    pub builds: usize,
+}
+impl Default for Jobs {
+    fn default() -> Self {
+        Self {
+            fetches: default_fetches(),
+            builds: default_builds(),
+        }
+    }
+}
+/// `N` for both, or `FETCHES,BUILDS`.
+impl FromStr for Jobs {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |n: &str| match n.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(eyre!("Invalid jobs `{s}`, expected e.g. `4` or `8,2`.")),
+        };
+        match s.split_once(',') {
+            Some((fetches, builds)) => Ok(Self {
+                fetches: parse(fetches)?,
+                builds: parse(builds)?,
+            }),
+            None => {
+                let n = parse(s)?;
+                Ok(Self {
+                    fetches: n,
+                    builds: n,
+                })
+            }
+        }
+    }
+}
+
+fn default_fetches() -> usize {
+    4
+}
+
+fn default_builds() -> usize {
+    2
+}
+
+/// See `throttle`.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Bandwidth {
+    /// The most to download overall, in kilobytes a second.
+    pub limit: Option<u64>,
+
+    /// Limits for particular domains (and their subdomains).
+    #[serde(default)]
+    pub domains: HashMap<String, u64>,
+}
+
+/// See `crypt`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Encryption {
+    /// A command printing the passphrase, e.g. from a password manager;
+    /// otherwise it's taken from `STASH_PASSPHRASE`.
+    #[serde(default)]
+    pub passphrase_command: Vec<String>,
+}
+
+/// See `discussion::find`.
+#[derive(Deserialize, Debug)]
+pub struct Discussion {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// How many of the top comments to include.
+    #[serde(default = "default_comments")]
+    pub comments: usize,
+}
+impl Default for Discussion {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            comments: default_comments(),
+        }
+    }
+}
+
+/// See `calibre::add`.
+#[derive(Deserialize, Debug, Default)]
+pub struct Calibre {
+    /// Whether to add every new book to Calibre.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The library to add to, instead of Calibre's default one.
+    pub library: Option<String>,
+}
+impl Calibre {
+    pub fn library(&self) -> Option<PathBuf> {
+        self.library.as_deref().map(expand_path)
+    }
+}
+
+/// A font file to embed, usable from the stylesheet by its family name.
+#[derive(Deserialize, Debug)]
+pub struct Font {
+    pub family: String,
+    pub path: String,
+    pub weight: Option<String>,
+    pub style: Option<String>,
+}
+impl Font {
+    pub fn path(&self) -> PathBuf {
+        expand_path(&self.path)
+    }
+}
+
+/// Where to save articles from some sites or with some tags.
+#[derive(Deserialize, Debug)]
+pub struct Route {
+    /// Relative to the output dir, unless it's absolute.
+    pub dir: String,
+
+    /// Sites to match, including their subdomains.
+    #[serde(default)]
+    pub domains: Vec<String>,
+
+    /// Tags (keywords) to match, any of which will do.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+impl Route {
+    /// Whether an article from `host` with these tags goes here.
+    /// A route with neither domains nor tags matches everything.
+    pub fn matches(&self, host: Option<&str>, tags: &[String]) -> bool {
+        let domain_matches = self.domains.is_empty()
+            || host.is_some_and(|host| self.domains.iter().any(|domain| on_domain(host, domain)));
+        let tag_matches = self.tags.is_empty()
+            || self
+                .tags
+                .iter()
+                .any(|tag| tags.iter().any(|other| other.eq_ignore_ascii_case(tag)));
+        domain_matches && tag_matches
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        expand_path(&self.dir)
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub fn on_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+fn expand_path(path: &str) -> PathBuf {
+    shellexpand::tilde(path).to_string().into()
+}
+
+fn default_comments() -> usize {
+    10
+}
+
+fn default_bookmark_tag() -> String {
+    "stash".into()
+}
+
+fn default_processed_tag() -> String {
+    "stashed".into()
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".into()
+}
+
+fn default_region() -> String {
+    "us-east-1".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_filename() -> String {
+    "{title}.{ext}".to_string()
+}
+
+fn default_max_dimension() -> u32 {
+    1600
+}
+
+fn default_jpeg_quality() -> u8 {
+    80
+}
+
+fn default_title() -> String {
+    "{title}".to_string()
+}
+
+fn default_epub_version() -> u8 {
+    3
+}
+
+fn default_max_page_size() -> u64 {
+    50
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_suggest_after() -> usize {
+    3
+}
+
+fn default_follow_every() -> String {
+    "1d".into()
+}
+
+fn default_max_document_size() -> usize {
+    256
+}
+
+fn default_wikipedia_image_width() -> u32 {
+    800
+}
+
+fn default_min_words() -> usize {
+    200
+}
+
+fn default_max_words() -> usize {
+    200_000
+}
+
+fn default_reading_speed() -> usize {
+    230
+}
+
+fn default_feed_limit() -> usize {
+    10
+}
+
+fn default_poll_interval() -> u64 {
+    300
+}
+
+/// The data dir, where e.g. the library index is kept;
+/// `~/.local/share/stash` unless `STASH_DATA_DIR` is set.
+pub fn data_dir() -> PathBuf {
+    match std::env::var_os(DATA_DIR_VAR) {
+        Some(dir) => dir.into(),
+        None => dirs::data_dir().expect("Data dir exists").join(APP_NAME),
+    }
+}
+
+/// The cache dir, where e.g. fetched pages are kept;
+/// `~/.cache/stash` unless `STASH_CACHE_DIR` is set.
+pub fn cache_dir() -> PathBuf {
+    match std::env::var_os(CACHE_DIR_VAR) {
+        Some(dir) => dir.into(),
+        None => dirs::cache_dir().expect("Cache dir exists").join(APP_NAME),
+    }
+}
+
+/// Held while updating a file in the data dir (or the archive), so that
+/// articles stashed at once don't lose each other's changes.
+pub(crate) fn lock_data() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The config dir, `~/.config/stash` unless `STASH_CONFIG_DIR` is set.
+pub fn config_dir() -> PathBuf {
+    match std::env::var_os(CONFIG_DIR_VAR) {
+        Some(dir) => dir.into(),
+        None => dirs::config_dir()
+            .expect("Config dir exists")
+            .join(APP_NAME),
+    }
+}
+
+/// Collect config overrides from the environment.
+///
+/// Values are parsed as TOML where possible (so `STASH_FOO=true`
+/// or `STASH_FOO=3` give a bool or integer), otherwise they're
+/// taken as plain strings. Only variables for `Config`'s own fields
+/// count, so others (e.g. `STASH_DATA_DIR` or `STASH_PROFILE`) are
+/// left to what reads them.
+fn env_overrides() -> Vec<(String, toml::Value)> {
+    let fields = field_names::<Config>();
+    std::env::vars()
+        .filter_map(|(key, value)| {
+            let key = key.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            let field = key.split_once("__").map_or(key.as_str(), |(head, _)| head);
+            if !fields.contains(&field) {
+                return None;
+            }
+            let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(toml::Value::String(value));
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// The names of a struct's fields as serde knows them, got by
+/// having it deserialize from something that only takes note of them.
+fn field_names<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+    impl<'de> serde::Deserializer<'de> for Fields<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(
+            self,
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("Not a struct"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("Only after the fields"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+    fields
+}
+
+/// Use the settings of a profile (`[profiles.<name>]`) over the rest of
+/// the config. Its tables are merged into the config's, so e.g.
+/// `[profiles.kobo.images]` can change `max_dimension` and leave
+/// the rest of `[images]` as it is.
+pub fn apply_profile(table: &mut toml::Table, name: &str) -> Result<()> {
+    let profile = table
+        .get("profiles")
+        .and_then(|profiles| profiles.get(name))
+        .cloned();
+    let Some(toml::Value::Table(profile)) = profile else {
+        let known: Vec<_> = table
+            .get("profiles")
+            .and_then(toml::Value::as_table)
+            .map(|profiles| profiles.keys().map(|name| format!("`{name}`")).collect())
+            .unwrap_or_default();
+        match known.as_slice() {
+            [] => bail!("There's no profile `{name}`, as there are none under `[profiles]`."),
+            known => bail!(
+                "There's no profile `{name}`; the profiles are {}.",
+                known.join(", ")
+            ),
+        }
+    };
+    merge(table, profile);
+    Ok(())
+}
+
+fn merge(table: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(inner)), toml::Value::Table(over)) => merge(inner, over),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Set a (possibly nested, `__`-separated) key in the table,
+/// creating intermediate tables as necessary.
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) {
+    match key.split_once("__") {
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(inner) = entry {
+                set_key(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
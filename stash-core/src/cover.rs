@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use color_eyre::eyre::Result;
+
+use crate::{date, epub::Book, template, xhtml::escape_attr};
+
+const DEFAULT_TEMPLATE: &str = include_str!("assets/cover.svg");
+
+/// Roughly how many characters of the title fit on one line
+/// of the default template, and how many lines to allow.
+const TITLE_LINE_CHARS: usize = 22;
+const TITLE_MAX_LINES: usize = 6;
+
+/// The cover page, which just shows the cover image.
+pub const COVER_PAGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>Cover</title>
+  <style>body { margin: 0; padding: 0; text-align: center; } img { max-width: 100%; max-height: 100%; }</style>
+</head>
+<body epub:type="cover">
+  <div><img src="cover.svg" alt="Cover"/></div>
+</body>
+</html>
+"#;
+
+/// Render an SVG cover for the book, using the template at
+/// `template_path` if given, otherwise the built-in one.
+///
+/// Templates can use the placeholders `{title}`, `{title_lines}`
+/// (the title wrapped into `<tspan>`s), `{authors}`, `{domain}`,
+/// `{date}`, and `{url}`. All values are XML-escaped.
+pub fn render(book: &Book, template_path: Option<&Path>) -> Result<String> {
+    let template = match template_path {
+        Some(path) => fs_err::read_to_string(path)?,
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+
+    let date = match date::parse(&book.published_at) {
+        Some(date) => date.format("%B %-d, %Y").to_string(),
+        None => book.published_at.clone(),
+    };
+    let title_lines = wrap(&book.title, TITLE_LINE_CHARS, TITLE_MAX_LINES)
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let dy = if i == 0 { "0" } else { "1.2em" };
+            format!(r#"<tspan x="300" dy="{dy}">{}</tspan>"#, escape_attr(line))
+        })
+        .collect::<String>();
+
+    Ok(template::render(
+        &template,
+        &[
+            ("title", &escape_attr(&book.title)),
+            ("title_lines", &title_lines),
+            ("authors", &escape_attr(&book.authors)),
+            ("domain", &escape_attr(&book.source)),
+            ("date", &escape_attr(&date)),
+            ("url", &escape_attr(&book.url)),
+        ],
+    ))
+}
+
+/// Greedily wrap text into lines of at most `width` characters
+/// (unless a single word is longer), truncating with an ellipsis
+/// past `max_lines`.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
@@ -0,0 +1,190 @@
+//! Encryption at rest of what stash keeps of articles besides the books:
+//! the library index, the captures' text, the cached pages, the
+//! archive's pages, mirrors, the retry queue, the usage stats, the digest
+//! issue numbers, the daemon's seen feed entries, the declined feeds, the
+//! user agents that worked for each site, and the last error response.
+//! With `[encryption]` in config.toml these are written
+//! encrypted (ChaCha20-Poly1305, with a key derived from a passphrase),
+//! and decrypted as they're read. Files written before encryption was
+//! turned on are still read as they are, and encrypted the next time
+//! they're written.
+
+use std::{
+    io::{self, ErrorKind},
+    num::NonZeroU32,
+    path::Path,
+    process::Command,
+    sync::{Mutex, OnceLock, RwLock},
+};
+
+use ring::{
+    aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::config::{Encryption, PASSPHRASE_VAR};
+
+/// What encrypted files start with, followed by the salt,
+/// the nonce, and the ciphertext.
+const MAGIC: &[u8] = b"stash-encrypted-1\n";
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const ITERATIONS: NonZeroU32 = NonZeroU32::new(600_000).expect("Non-zero");
+
+static ENCRYPTION: RwLock<Option<Encryption>> = RwLock::new(None);
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Keys by their salts, as deriving one is deliberately slow.
+static KEYS: Mutex<Vec<([u8; SALT_LEN], [u8; KEY_LEN])>> = Mutex::new(Vec::new());
+
+/// Set whether to encrypt files, until it's set again (e.g. when the
+/// config's reloaded). If that changes how the passphrase is given,
+/// it's asked for again.
+pub fn init(encryption: Option<Encryption>) {
+    let mut current = ENCRYPTION.write().expect("Lock");
+    if *current == encryption {
+        return;
+    }
+    *current = encryption;
+    *PASSPHRASE.lock().expect("Lock") = None;
+    KEYS.lock().expect("Lock").clear();
+}
+
+fn enabled() -> Option<Encryption> {
+    ENCRYPTION.read().expect("Lock").clone()
+}
+
+/// Write a file, encrypted if encryption's on.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    match enabled() {
+        Some(_) => {
+            let sealed = seal(contents.as_ref()).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Couldn't encrypt {}: {err}", path.display()),
+                )
+            })?;
+            fs_err::write(path, sealed)
+        }
+        None => fs_err::write(path, contents),
+    }
+}
+
+/// Read a file, decrypting it if it's encrypted.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let data = fs_err::read(path)?;
+    let data = match data.strip_prefix(MAGIC) {
+        Some(sealed) => open(sealed).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Couldn't decrypt {}: {err}", path.display()),
+            )
+        })?,
+        None => data,
+    };
+    String::from_utf8(data).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+fn seal(plain: &[u8]) -> io::Result<Vec<u8>> {
+    // One salt (and so one key) for everything written in a run.
+    static SALT: OnceLock<[u8; SALT_LEN]> = OnceLock::new();
+    let salt = *SALT.get_or_init(random);
+    let nonce: [u8; NONCE_LEN] = random();
+
+    let mut data = plain.to_vec();
+    cipher(&salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut data,
+        )
+        .map_err(|_| io::Error::other("Encryption failed"))?;
+    Ok([MAGIC, &salt, &nonce, &data].concat())
+}
+
+fn open(sealed: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = || {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            "the passphrase is wrong, or the file is corrupt",
+        )
+    };
+    let (salt, rest) = sealed.split_first_chunk::<SALT_LEN>().ok_or_else(invalid)?;
+    let (nonce, ciphertext) = rest.split_first_chunk::<NONCE_LEN>().ok_or_else(invalid)?;
+
+    let mut data = ciphertext.to_vec();
+    let plain = cipher(salt)?
+        .open_in_place(
+            Nonce::assume_unique_for_key(*nonce),
+            Aad::from(MAGIC),
+            &mut data,
+        )
+        .map_err(|_| invalid())?;
+    Ok(plain.to_vec())
+}
+
+fn cipher(salt: &[u8; SALT_LEN]) -> io::Result<LessSafeKey> {
+    let mut keys = KEYS.lock().expect("Lock");
+    let key = match keys.iter().find(|(s, _)| s == salt) {
+        Some((_, key)) => *key,
+        None => {
+            let mut key = [0; KEY_LEN];
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                ITERATIONS,
+                salt,
+                passphrase()?.as_bytes(),
+                &mut key,
+            );
+            keys.push((*salt, key));
+            key
+        }
+    };
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).expect("Valid key length");
+    Ok(LessSafeKey::new(key))
+}
+
+/// The passphrase, from `passphrase_command` if there is one,
+/// otherwise `STASH_PASSPHRASE`.
+fn passphrase() -> io::Result<String> {
+    let mut cached = PASSPHRASE.lock().expect("Lock");
+    if let Some(passphrase) = cached.as_ref() {
+        return Ok(passphrase.clone());
+    }
+    let command = enabled()
+        .map(|encryption| encryption.passphrase_command)
+        .unwrap_or_default();
+    let passphrase = match command.split_first() {
+        Some((program, args)) => {
+            let output = Command::new(program).args(args).output()?;
+            if !output.status.success() {
+                return Err(io::Error::other(format!(
+                    "`{program}` failed to give the passphrase: {}",
+                    output.status
+                )));
+            }
+            String::from_utf8_lossy(&output.stdout)
+                .trim_end_matches(['\r', '\n'])
+                .to_string()
+        }
+        None => std::env::var(PASSPHRASE_VAR).map_err(|_| {
+            io::Error::other(format!(
+                "there's no passphrase; set {PASSPHRASE_VAR}, \
+                 or `passphrase_command` under [encryption] in config.toml"
+            ))
+        })?,
+    };
+    if passphrase.is_empty() {
+        return Err(io::Error::other("the passphrase is empty"));
+    }
+    Ok(cached.insert(passphrase).clone())
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("System randomness");
+    bytes
+}
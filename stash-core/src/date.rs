@@ -0,0 +1,85 @@
+//! Publication dates, which sites give in all sorts of formats.
+//! They're normalized to RFC 3339 (ISO 8601) on the way in, keeping the
+//! timezone the site gave, so that the date a book's filed under is the
+//! date it was published where it was published.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Formats with an offset that aren't quite RFC 3339.
+const OFFSET_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+    "%Y-%m-%dT%H:%M%z",
+    "%Y-%m-%d %H:%M:%S%.f%z",
+    "%Y-%m-%d %H:%M:%S%.f %z",
+];
+
+/// ISO-ish formats without an offset.
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Normalize a publication date to RFC 3339 in its own timezone, or `tz`
+/// if it doesn't give one. Dates without a time are kept as just a date
+/// (`2024-03-05`), rather than made midnight somewhere.
+pub fn normalize(date: &str, tz: Tz) -> Option<String> {
+    let date = date.trim();
+    if date.is_empty() {
+        return None;
+    }
+    if let Some(parsed) = with_offset(date) {
+        return Some(parsed.to_rfc3339());
+    }
+    if let Ok(parsed) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Some(parsed.to_string());
+    }
+    if let Some(parsed) = NAIVE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+    {
+        let parsed = tz.from_local_datetime(&parsed).earliest()?;
+        return Some(parsed.fixed_offset().to_rfc3339());
+    }
+
+    // Anything else is left to `dateparser`, which fills in missing times.
+    // Parsing with two different defaults tells whether the time was missing.
+    let midnight = dateparser::parse_with(date, &tz, NaiveTime::MIN).ok()?;
+    let noon = NaiveTime::from_hms_opt(12, 0, 0).expect("valid time");
+    let local = midnight.with_timezone(&tz);
+    if dateparser::parse_with(date, &tz, noon).ok()? != midnight {
+        Some(local.date_naive().to_string())
+    } else {
+        Some(local.fixed_offset().to_rfc3339())
+    }
+}
+
+/// Parse a publication date, normalized or not.
+/// Dates without a time are taken as midnight UTC.
+pub fn parse(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    with_offset(date)
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::MIN).and_utc().fixed_offset())
+        })
+        .or_else(|| {
+            dateparser::parse(date)
+                .ok()
+                .map(|date: DateTime<Utc>| date.fixed_offset())
+        })
+}
+
+fn with_offset(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .ok()
+        .or_else(|| {
+            OFFSET_FORMATS
+                .iter()
+                .find_map(|format| DateTime::parse_from_str(date, format).ok())
+        })
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::{APP_NAME, blocklist, config::Discussion, failure::Failure};
+
+const HN_ITEM: &str = "https://hacker-news.firebaseio.com/v0/item";
+const HN_SEARCH: &str = "https://hn.algolia.com/api/v1/search";
+const REDDIT: &str = "https://www.reddit.com";
+
+/// A discussion of an article, with its top comments.
+#[derive(Serialize, Debug, Clone)]
+pub struct Thread {
+    /// E.g. "Hacker News" or "r/rust".
+    pub site: String,
+    pub url: String,
+
+    /// The article the thread is about, unless it's a text post.
+    pub story_url: Option<String>,
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Comment {
+    pub author: String,
+
+    /// The comment as HTML.
+    pub html: String,
+    pub points: Option<i64>,
+}
+
+/// Whether the URL is of a Hacker News or Reddit thread itself.
+pub fn is_thread(url: &str) -> bool {
+    hn_id(url).is_some() || reddit_permalink(url).is_some()
+}
+
+/// Get the thread at a Hacker News or Reddit URL.
+pub fn thread(config: &Discussion, url: &str) -> Result<Thread> {
+    if let Some(id) = hn_id(url) {
+        hn_thread(config, &id)
+    } else if let Some(permalink) = reddit_permalink(url) {
+        reddit_thread(config, &permalink)
+    } else {
+        bail!("Not a Hacker News or Reddit thread: {url}");
+    }
+}
+
+/// Find the most commented-on Hacker News or Reddit thread about an article.
+pub fn find(config: &Discussion, url: &str) -> Result<Option<Thread>> {
+    enum Found {
+        HackerNews(String),
+        Reddit(String),
+    }
+    let mut found: Vec<(u64, Found)> = vec![];
+
+    let hits = get(
+        HN_SEARCH,
+        &[
+            ("query", url),
+            ("restrictSearchableAttributes", "url"),
+            ("tags", "story"),
+        ],
+    )?;
+    for hit in hits["hits"].as_array().into_iter().flatten() {
+        if hit["url"].as_str().is_some_and(|hit| same_url(hit, url))
+            && let Some(id) = hit["objectID"].as_str()
+        {
+            let comments = hit["num_comments"].as_u64().unwrap_or_default();
+            found.push((comments, Found::HackerNews(id.to_string())));
+        }
+    }
+
+    let posts = get(&format!("{REDDIT}/api/info.json"), &[("url", url)])?;
+    for post in posts["data"]["children"].as_array().into_iter().flatten() {
+        let post = &post["data"];
+        if let Some(permalink) = post["permalink"].as_str() {
+            let comments = post["num_comments"].as_u64().unwrap_or_default();
+            found.push((comments, Found::Reddit(permalink.to_string())));
+        }
+    }
+
+    match found
+        .into_iter()
+        .filter(|(comments, _)| *comments > 0)
+        .max_by_key(|(comments, _)| *comments)
+    {
+        Some((_, Found::HackerNews(id))) => hn_thread(config, &id).map(Some),
+        Some((_, Found::Reddit(permalink))) => reddit_thread(config, &permalink).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn hn_thread(config: &Discussion, id: &str) -> Result<Thread> {
+    let story = get(&format!("{HN_ITEM}/{id}.json"), &[])?;
+    // Comments are listed in the site's ranking order.
+    let mut comments = vec![];
+    for kid in story["kids"].as_array().into_iter().flatten() {
+        if comments.len() >= config.comments {
+            break;
+        }
+        let comment = get(&format!("{HN_ITEM}/{kid}.json"), &[])?;
+        if comment["deleted"].as_bool() == Some(true) || comment["dead"].as_bool() == Some(true) {
+            continue;
+        }
+        comments.push(Comment {
+            author: str(&comment["by"]),
+            html: str(&comment["text"]),
+            points: None,
+        });
+    }
+    Ok(Thread {
+        site: "Hacker News".into(),
+        url: format!("https://news.ycombinator.com/item?id={id}"),
+        story_url: story["url"].as_str().map(String::from),
+        comments,
+    })
+}
+
+fn reddit_thread(config: &Discussion, permalink: &str) -> Result<Thread> {
+    let listings = get(
+        &format!("{REDDIT}{permalink}.json"),
+        &[
+            ("sort", "top"),
+            ("limit", &(config.comments + 5).to_string()),
+        ],
+    )?;
+    let post = &listings[0]["data"]["children"][0]["data"];
+    let comments = listings[1]["data"]["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        // Skipping "more comments" stubs and moderators' sticky notes.
+        .filter(|child| child["kind"] == "t1" && child["data"]["stickied"] != true)
+        .take(config.comments)
+        .map(|child| Comment {
+            author: str(&child["data"]["author"]),
+            html: unescape(&str(&child["data"]["body_html"])),
+            points: child["data"]["score"].as_i64(),
+        })
+        .collect();
+    Ok(Thread {
+        site: post["subreddit_name_prefixed"]
+            .as_str()
+            .unwrap_or("Reddit")
+            .to_string(),
+        url: format!("{REDDIT}{permalink}"),
+        story_url: (post["is_self"] != true)
+            .then(|| post["url"].as_str().map(String::from))
+            .flatten(),
+        comments,
+    })
+}
+
+fn get(url: &str, query: &[(&str, &str)]) -> Result<Value> {
+    blocklist::check(url)?;
+    let mut request = ureq::get(url)
+        .timeout(Duration::from_secs(30))
+        // Reddit rejects generic user agents.
+        .set(
+            "User-Agent",
+            &format!("{APP_NAME}/{}", env!("CARGO_PKG_VERSION")),
+        );
+    for (name, value) in query {
+        request = request.query(name, value);
+    }
+    Ok(request
+        .call()
+        .wrap_err_with(|| format!("Couldn't get {url}"))
+        .wrap_err(Failure::Network)?
+        .into_json()?)
+}
+
+fn hn_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if url.host_str()? != "news.ycombinator.com" || url.path() != "/item" {
+        return None;
+    }
+    url.query_pairs()
+        .find(|(name, _)| name == "id")
+        .map(|(_, id)| id.to_string())
+        .filter(|id| id.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// E.g. `/r/rust/comments/abc123/some_title/`.
+fn reddit_permalink(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if !(host == "reddit.com" || host.ends_with(".reddit.com"))
+        || !url.path().contains("/comments/")
+    {
+        return None;
+    }
+    Some(url.path().trim_end_matches('/').to_string() + "/")
+}
+
+/// Whether two URLs are the same page, give or take the scheme,
+/// a `www.`, or a trailing slash.
+fn same_url(a: &str, b: &str) -> bool {
+    let normalize = |url: &str| {
+        let url = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://");
+        url.trim_start_matches("www.")
+            .trim_end_matches('/')
+            .to_string()
+    };
+    normalize(a) == normalize(b)
+}
+
+fn str(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// Reddit escapes the HTML of comments.
+fn unescape(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
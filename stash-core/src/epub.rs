@@ -0,0 +1,672 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use epub_builder::{
+    EpubBuilder, EpubContent, EpubVersion, MetadataOpf, MetadataOpfV3, ReferenceType, TocElement,
+    ZipLibrary,
+};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    APP_NAME, Article,
+    config::{Collision, Config},
+    cover, date,
+    discussion::{Comment, Thread},
+    images, logging,
+    progress::Progress,
+    reading_time, style, template, typography, validate,
+    xhtml::{self, Heading},
+};
+
+const COLOPHON_TITLE: &str = "About this capture";
+const COMMENTS_TITLE: &str = "Comments";
+
+/// An EPUB to generate: its metadata plus one or more articles.
+///
+/// A single article is the book's only content,
+/// several (e.g. for a digest) each get their own chapter.
+pub struct Book<'a> {
+    pub title: String,
+    pub authors: String,
+    pub published_at: String,
+
+    /// Where the book comes from, for the cover;
+    /// the site's domain for single articles.
+    pub source: String,
+
+    /// Source URL, for single articles.
+    pub url: String,
+
+    pub description: String,
+
+    /// The language of the book, e.g. `en`; the EPUB default if empty.
+    pub lang: String,
+
+    /// Dublin Core metadata, for library managers.
+    pub publisher: String,
+    pub subjects: Vec<String>,
+    pub rights: String,
+
+    /// For digests of a feed, which issue of the feed's series this is.
+    pub series: Option<Series>,
+
+    pub articles: Vec<&'a Article>,
+}
+impl<'a> Book<'a> {
+    pub fn single(article: &'a Article) -> Self {
+        let source = Url::parse(&article.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default();
+        Self {
+            title: article.title.clone(),
+            authors: article.authors.clone(),
+            published_at: article.published_at.clone(),
+            source,
+            url: article.url.clone(),
+            description: article.summary.clone(),
+            lang: article.lang.clone(),
+            publisher: article.site_name.clone(),
+            subjects: article.keywords.clone(),
+            rights: article.rights.clone(),
+            series: None,
+            articles: vec![article],
+        }
+    }
+
+    /// Where to write the book under `output_dir`, according to the filename
+    /// template. Templates can use the placeholders `{title}`, `{authors}`,
+    /// `{domain}`, `{date}` (the publication date, or today if it's unknown),
+    /// and `{ext}`. Values are made filename-safe, but the template itself
+    /// may contain `/` to sort books into subdirectories.
+    pub fn path(&self, output_dir: &Path, config: &Config) -> PathBuf {
+        let date = date::parse(&self.published_at)
+            .unwrap_or_else(|| Utc::now().fixed_offset())
+            .format("%Y-%m-%d")
+            .to_string();
+        let domain = filename_safe(&self.source);
+        let mut title = slugify(&self.title, config.transliterate);
+        if title.is_empty() {
+            // E.g. a title of only punctuation or emoji; the hash keeps
+            // such articles from the same site and day apart.
+            let id = if self.url.is_empty() {
+                &self.title
+            } else {
+                &self.url
+            };
+            let hash = hex::encode(Sha256::digest(id.as_bytes()));
+            title = [domain.as_str(), &date, &hash[..8]]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("-");
+        }
+        let fname = template::render(
+            &config.filename,
+            &[
+                ("title", &title),
+                ("authors", &slugify(&self.authors, config.transliterate)),
+                ("domain", &domain),
+                ("date", &date),
+                ("ext", "epub"),
+            ],
+        );
+        output_dir.join(fname)
+    }
+
+    /// How many words of text the book's articles have.
+    pub fn word_count(&self) -> usize {
+        self.articles
+            .iter()
+            .map(|article| article.word_count())
+            .sum()
+    }
+
+    /// The book's title metadata, according to the title template.
+    /// Templates can use the placeholders `{title}`, `{authors}`,
+    /// `{site}` (the site's name, or its domain if it has none),
+    /// `{domain}`, `{date}` (the publication date, if it's known),
+    /// and `{reading_time}` (e.g. "~18 min read").
+    pub fn full_title(&self, config: &Config) -> String {
+        let date = date::parse(&self.published_at)
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let site = if self.publisher.is_empty() {
+            &self.source
+        } else {
+            &self.publisher
+        };
+        template::render(
+            &config.title,
+            &[
+                ("title", &self.title),
+                ("authors", &self.authors),
+                ("site", site),
+                ("domain", &self.source),
+                ("date", &date),
+                (
+                    "reading_time",
+                    &reading_time(self.word_count(), config.reading_speed),
+                ),
+            ],
+        )
+    }
+
+    /// Write the book to `path`. It's built (and validated) next to it
+    /// first and only then moved there, so a failure leaves whatever
+    /// was at `path` before as it was. Image downloads are shown in `progress`.
+    pub fn write(&self, path: &Path, config: &Config, progress: &Progress) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let partial = path.with_file_name(format!(".{name}.part"));
+        let built = self
+            .build(&partial, config, progress)
+            .and_then(|()| validate::check(&partial, path, config.validate));
+        if let Err(err) = built {
+            let _ = fs_err::remove_file(&partial);
+            return Err(err);
+        }
+        fs_err::rename(&partial, path)?;
+        Ok(())
+    }
+
+    fn build(&self, path: &Path, config: &Config, progress: &Progress) -> Result<()> {
+        let output = fs_err::File::create(path)?;
+
+        let mut builder = EpubBuilder::new(ZipLibrary::new()?)?;
+        let version = config.epub_version()?;
+        builder.epub_version(version);
+
+        style::apply(&mut builder, config)?;
+
+        if config.cover {
+            let cover = cover::render(self, config.cover_template().as_deref())?;
+            builder
+                .add_cover_image("cover.svg", cover.as_bytes(), "image/svg+xml")?
+                .add_content(
+                    EpubContent::new("cover.xhtml", cover::COVER_PAGE.as_bytes())
+                        .reftype(ReferenceType::Cover),
+                )?;
+        }
+
+        builder
+            .metadata("author", &self.authors)?
+            .metadata("title", self.full_title(config))?;
+        if !self.description.is_empty() {
+            builder.metadata("description", &self.description)?;
+        }
+        for subject in &self.subjects {
+            builder.metadata("subject", subject)?;
+        }
+        if !self.rights.is_empty() {
+            builder.metadata("license", &self.rights)?;
+        }
+        if !self.lang.is_empty() {
+            builder.metadata("lang", &self.lang)?;
+        }
+        // There's no standard property for this, so follow Calibre-style
+        // custom metadata, which works for both EPUB versions.
+        if let [article] = self.articles.as_slice()
+            && !article.translated_from.is_empty()
+        {
+            builder.add_metadata_opf(Box::new(MetadataOpf {
+                name: "stash:translated_from".to_string(),
+                content: article.translated_from.clone(),
+            }));
+        }
+
+        // The images packaged, by their hashes.
+        let mut packaged = HashMap::new();
+        match self.articles.as_slice() {
+            [article] => {
+                let body =
+                    article_content(&mut builder, article, config, "1", &mut packaged, progress)?;
+                // Its parts' headings all go under its entry in the table of contents.
+                let mut parts = vec![];
+                let mut headings = vec![];
+                for (i, part) in xhtml::split(&body, config.max_document_size * 1024)
+                    .iter()
+                    .enumerate()
+                {
+                    let href = match i {
+                        0 => "main.xhtml".to_string(),
+                        i => format!("main-{}.xhtml", i + 1),
+                    };
+                    let (content, part_headings) = xhtml::document(&article.title, part);
+                    headings.extend(
+                        part_headings
+                            .into_iter()
+                            .map(|heading| (href.clone(), heading)),
+                    );
+                    parts.push((href, content));
+                }
+                for (i, (href, content)) in parts.iter().enumerate() {
+                    let content = EpubContent::new(href.as_str(), content.as_bytes());
+                    builder.add_content(match i {
+                        0 => section_toc(content, &headings)
+                            .title(&article.title)
+                            .reftype(ReferenceType::Text),
+                        _ => content,
+                    })?;
+                }
+                if !article.comments.is_empty() {
+                    let body = format!("<h1>{COMMENTS_TITLE}</h1>{}", comments(&article.comments));
+                    builder.add_content(
+                        EpubContent::new(
+                            "comments.xhtml",
+                            xhtml::appendix(COMMENTS_TITLE, &body).as_bytes(),
+                        )
+                        .title(COMMENTS_TITLE),
+                    )?;
+                }
+                if let Some(thread) = &article.discussion {
+                    let title = format!("Discussion on {}", thread.site);
+                    let body = format!(
+                        "<h1>{}</h1>{}",
+                        xhtml::escape_text(&title),
+                        discussion(thread)
+                    );
+                    builder.add_content(
+                        EpubContent::new(
+                            "discussion.xhtml",
+                            xhtml::appendix(&title, &body).as_bytes(),
+                        )
+                        .title(title),
+                    )?;
+                }
+            }
+            articles => {
+                builder.inline_toc();
+                for (i, article) in articles.iter().enumerate() {
+                    let prefix = (i + 1).to_string();
+                    let content = article_content(
+                        &mut builder,
+                        article,
+                        config,
+                        &prefix,
+                        &mut packaged,
+                        progress,
+                    )?;
+                    let mut body = format!("{}{}", chapter_header(article), content);
+                    if !article.comments.is_empty() {
+                        body.push_str(&format!(
+                            "<hr/><h2>{COMMENTS_TITLE}</h2>{}",
+                            comments(&article.comments)
+                        ));
+                    }
+                    if let Some(thread) = &article.discussion {
+                        body.push_str(&format!(
+                            "<hr/><h2>Discussion on {}</h2>{}",
+                            xhtml::escape_text(&thread.site),
+                            discussion(thread)
+                        ));
+                    }
+                    let (content, headings) = xhtml::document(&article.title, &body);
+                    let href = format!("chapter-{:03}.xhtml", i + 1);
+                    let headings: Vec<_> = headings
+                        .into_iter()
+                        .map(|heading| (href.clone(), heading))
+                        .collect();
+                    let mut chapter = section_toc(
+                        EpubContent::new(href.clone(), content.as_bytes()),
+                        &headings,
+                    )
+                    .title(&article.title);
+                    if i == 0 {
+                        chapter = chapter.reftype(ReferenceType::Text);
+                    }
+                    builder.add_content(chapter)?;
+                }
+            }
+        }
+
+        builder.add_content(
+            EpubContent::new(
+                "colophon.xhtml",
+                xhtml::colophon(COLOPHON_TITLE, &self.colophon(config)).as_bytes(),
+            )
+            .title(COLOPHON_TITLE)
+            .reftype(ReferenceType::Colophon),
+        )?;
+
+        // One that can't be parsed is among the article's warnings.
+        if let Some(parsed) = date::parse(&self.published_at) {
+            builder.set_publication_date(parsed.to_utc());
+        }
+
+        if let Some(series) = &self.series {
+            series.apply(&mut builder, version);
+        }
+
+        // Accessibility, publisher and source metadata use EPUB3
+        // `<meta property>`s, which aren't valid in an EPUB2 package document.
+        if version != EpubVersion::V20 {
+            // What's in the book, not the pages, as their images may have
+            // been left out or failed to download.
+            self.accessibility(&mut builder, !packaged.is_empty());
+            self.provenance(&mut builder);
+        }
+
+        builder.generate(output)?;
+        Ok(())
+    }
+
+    /// The body of the "About this capture" page: where each article
+    /// came from, when and how it was captured.
+    fn colophon(&self, config: &Config) -> String {
+        let mut body = format!("<h1>{COLOPHON_TITLE}</h1>");
+        let several = self.articles.len() > 1;
+        for article in &self.articles {
+            if several {
+                body.push_str(&format!("<h2>{}</h2>", xhtml::escape_text(&article.title)));
+            }
+            let words = article.word_count();
+            body.push_str(&format!(
+                r#"<dl><dt>Source</dt><dd><a href="{}">{}</a></dd><dt>Captured</dt><dd>{}</dd><dt>Extraction</dt><dd>{}</dd><dt>Length</dt><dd>{words} words ({})</dd></dl>"#,
+                xhtml::escape_attr(&article.url),
+                xhtml::escape_text(&article.url),
+                article.fetched_at.format("%B %-d, %Y %H:%M UTC"),
+                xhtml::escape_text(&article.extraction),
+                reading_time(words, config.reading_speed),
+            ));
+            if !article.translated_from.is_empty() {
+                body.insert_str(
+                    body.len() - "</dl>".len(),
+                    &format!(
+                        "<dt>Machine-translated from</dt><dd>{}</dd>",
+                        xhtml::escape_text(&article.translated_from)
+                    ),
+                );
+            }
+            if !article.note.is_empty() {
+                body.insert_str(
+                    body.len() - "</dl>".len(),
+                    &format!(
+                        "<dt>Note</dt><dd>{}</dd>",
+                        xhtml::escape_text(&article.note)
+                    ),
+                );
+            }
+        }
+        body.push_str(&format!(
+            "<p>Generated by {APP_NAME} {}.</p>",
+            env!("CARGO_PKG_VERSION")
+        ));
+        body
+    }
+
+    /// Add the publisher and source URL as `dcterms` metadata.
+    fn provenance(&self, builder: &mut EpubBuilder<ZipLibrary>) {
+        // `MetadataOpfV3` doesn't escape the content itself.
+        if !self.publisher.is_empty() {
+            builder.add_metadata_opf(Box::new(MetadataOpfV3::new(
+                "dcterms:publisher".to_string(),
+                xhtml::escape_text(&self.publisher),
+            )));
+        }
+        if !self.url.is_empty() {
+            builder.add_metadata_opf(Box::new(MetadataOpfV3::new(
+                "dcterms:source".to_string(),
+                xhtml::escape_text(&self.url),
+            )));
+        }
+    }
+
+    /// Add schema.org accessibility metadata, as recommended by
+    /// EPUB Accessibility 1.1.
+    fn accessibility(&self, builder: &mut EpubBuilder<ZipLibrary>, has_images: bool) {
+        let mut metadata = vec![
+            ("schema:accessMode", "textual"),
+            ("schema:accessModeSufficient", "textual"),
+            ("schema:accessibilityFeature", "structuralNavigation"),
+            ("schema:accessibilityFeature", "tableOfContents"),
+            ("schema:accessibilityFeature", "readingOrder"),
+            ("schema:accessibilityHazard", "unknown"),
+            (
+                "schema:accessibilitySummary",
+                "Generated from a web page: the text is in reading order \
+                 and section headings are available for navigation.",
+            ),
+        ];
+        if has_images {
+            metadata.insert(1, ("schema:accessMode", "visual"));
+        }
+        for (property, content) in metadata {
+            builder.add_metadata_opf(Box::new(MetadataOpfV3::new(
+                property.to_string(),
+                content.to_string(),
+            )));
+        }
+    }
+}
+
+/// A book's place in a series.
+pub struct Series {
+    pub title: String,
+    pub index: u32,
+}
+impl Series {
+    /// Add both Calibre's series metadata, which is what most
+    /// library managers and readers understand, and (for EPUB3)
+    /// the standard `belongs-to-collection`.
+    fn apply(&self, builder: &mut EpubBuilder<ZipLibrary>, version: EpubVersion) {
+        builder
+            .add_metadata_opf(Box::new(MetadataOpf {
+                name: "calibre:series".to_string(),
+                content: self.title.clone(),
+            }))
+            .add_metadata_opf(Box::new(MetadataOpf {
+                name: "calibre:series_index".to_string(),
+                content: self.index.to_string(),
+            }));
+
+        if version != EpubVersion::V20 {
+            let refines = |property: &str, content: String| MetadataOpfV3 {
+                refines: Some("#series".to_string()),
+                ..MetadataOpfV3::new(property.to_string(), content)
+            };
+            builder
+                .add_metadata_opf(Box::new(MetadataOpfV3 {
+                    id: Some("series".to_string()),
+                    ..MetadataOpfV3::new(
+                        "belongs-to-collection".to_string(),
+                        xhtml::escape_text(&self.title),
+                    )
+                }))
+                .add_metadata_opf(Box::new(refines("collection-type", "series".to_string())))
+                .add_metadata_opf(Box::new(refines("group-position", self.index.to_string())));
+        }
+    }
+}
+
+/// Decide where to actually write a book meant for `path`,
+/// if it already exists. Returns `None` if it should be skipped.
+pub fn resolve_collision(path: PathBuf, collision: Collision) -> Option<PathBuf> {
+    if !path.exists() {
+        return Some(path);
+    }
+    match collision {
+        Collision::Overwrite => Some(path),
+        Collision::Skip => {
+            logging::warn(format!("{} already exists, skipping.", path.display()));
+            None
+        }
+        Collision::Number => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = path.extension().unwrap_or_default().to_string_lossy();
+            (2..)
+                .map(|n| path.with_file_name(format!("{stem}-{n}.{ext}")))
+                .find(|path| !path.exists())
+        }
+    }
+}
+
+/// Make a value filename-safe: transliterated into ASCII, or else lowercased
+/// with anything but letters and digits (in any script) replaced by dashes.
+fn slugify(value: &str, transliterate: bool) -> String {
+    if transliterate {
+        return slug::slugify(value);
+    }
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Domains are fine in filenames as they are,
+/// anything else (e.g. a feed's title) is slugified.
+fn filename_safe(value: &str) -> String {
+    if value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    {
+        value.to_lowercase()
+    } else {
+        slug::slugify(value)
+    }
+}
+
+/// Add section headings (with the content documents they're in)
+/// to the table of contents, nesting `<h3>`s under the preceding `<h2>`.
+fn section_toc<'a>(
+    mut content: EpubContent<&'a [u8]>,
+    headings: &[(String, Heading)],
+) -> EpubContent<&'a [u8]> {
+    let mut sections: Vec<TocElement> = vec![];
+    for (href, heading) in headings {
+        let elem = TocElement::new(format!("{href}#{}", heading.id), &heading.title);
+        match sections.pop() {
+            Some(section) if heading.level > 2 => sections.push(section.child(elem)),
+            last => {
+                sections.extend(last);
+                sections.push(elem);
+            }
+        }
+    }
+    for section in sections {
+        content = content.child(section);
+    }
+    content
+}
+
+/// The article's content, polished if configured,
+/// with its images packaged into the EPUB.
+///
+/// Images already packaged for another of the book's articles (by their
+/// hashes in `packaged`, e.g. a site's logo in each of a digest's
+/// chapters) point to that copy instead of being packaged again.
+fn article_content(
+    builder: &mut EpubBuilder<ZipLibrary>,
+    article: &Article,
+    config: &Config,
+    prefix: &str,
+    packaged: &mut HashMap<String, String>,
+    progress: &Progress,
+) -> Result<String> {
+    let mut content = article.content.clone();
+    if config.typography.enabled {
+        let lang = config
+            .typography
+            .language
+            .as_deref()
+            .unwrap_or(&article.lang);
+        content = typography::polish(&content, lang);
+    }
+
+    let settings = config.images.with(&article.images);
+    let (mut content, images) = images::embed(&content, &article.url, &settings, prefix, progress);
+    for image in images {
+        let hash = hex::encode(Sha256::digest(&image.data));
+        match packaged.get(&hash) {
+            Some(href) => {
+                content = content.replace(
+                    &format!("src=\"{}\"", image.href),
+                    &format!("src=\"{href}\""),
+                );
+            }
+            None => {
+                builder.add_resource(&image.href, image.data.as_slice(), image.mime)?;
+                packaged.insert(hash, image.href);
+            }
+        }
+    }
+    if article.summary.is_empty() {
+        return Ok(content);
+    }
+    Ok(format!("{}{content}", summary_section(&article.summary)))
+}
+
+/// The article's generated summary, to go before its content.
+fn summary_section(summary: &str) -> String {
+    let paragraphs: String = summary
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|para| !para.is_empty())
+        .map(|para| format!("<p>{}</p>", xhtml::escape_text(para)))
+        .collect();
+    format!(r#"<section class="summary"><h2>Summary</h2>{paragraphs}</section><hr/>"#)
+}
+
+/// A link to the thread, and its top comments.
+fn discussion(thread: &Thread) -> String {
+    let mut html = format!(
+        r#"<p class="discussion-source"><a href="{}">{}</a></p>"#,
+        xhtml::escape_attr(&thread.url),
+        xhtml::escape_text(&thread.url)
+    );
+    html.push_str(&comments(&thread.comments));
+    html
+}
+
+/// Comments, each with its author (and points, where there are any).
+fn comments(comments: &[Comment]) -> String {
+    let mut html = String::new();
+    for comment in comments {
+        let points = comment
+            .points
+            .map(|points| format!(" · {points} points"))
+            .unwrap_or_default();
+        html.push_str(&format!(
+            r#"<div class="comment"><p class="comment-author"><b>{}</b>{points}</p>{}</div>"#,
+            xhtml::escape_text(&comment.author),
+            comment.html
+        ));
+    }
+    html
+}
+
+/// Title and byline for an article's chapter in a multi-article book,
+/// since the extracted content often doesn't include them.
+fn chapter_header(article: &Article) -> String {
+    let mut byline = vec![];
+    if !article.authors.is_empty() {
+        byline.push(xhtml::escape_text(&article.authors));
+    }
+    if !article.published_at.is_empty() {
+        byline.push(xhtml::escape_text(&article.published_at));
+    }
+    if let Some(host) = Url::parse(&article.url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()))
+    {
+        byline.push(format!(
+            r#"<a href="{}">{}</a>"#,
+            xhtml::escape_attr(&article.url),
+            xhtml::escape_text(&host)
+        ));
+    }
+    format!(
+        r#"<h1>{}</h1><p class="byline">{}</p>"#,
+        xhtml::escape_text(&article.title),
+        byline.join(" · ")
+    )
+}
@@ -0,0 +1,132 @@
+use std::{fmt::Display, path::PathBuf, process::ExitCode};
+
+use color_eyre::Report;
+
+/// Broad classes of failure, each with its own exit code
+/// so that wrapper scripts can e.g. retry only network failures.
+///
+/// These are attached to errors as context (`.wrap_err(Failure::Network)`)
+/// and recovered in `main` to pick the exit code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// The page couldn't be fetched.
+    Network,
+
+    /// The page was fetched but no article could be extracted.
+    Extraction,
+
+    /// The user declined at the confirmation prompt.
+    Aborted,
+
+    /// The output file couldn't be written.
+    Output,
+}
+impl Failure {
+    /// Exit code for errors that don't fall into any class.
+    pub const GENERIC_CODE: u8 = 1;
+
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Network => 2,
+            Self::Extraction => 3,
+            Self::Aborted => 4,
+            Self::Output => 5,
+        }
+    }
+
+    /// The failure class of an error, if it has one.
+    pub fn of(err: &Report) -> Option<Self> {
+        err.downcast_ref::<Self>()
+            .copied()
+            .or_else(|| StashError::of(err).and_then(StashError::failure))
+    }
+
+    /// The exit code for an error.
+    pub fn exit_code(err: &Report) -> ExitCode {
+        ExitCode::from(Self::of(err).map_or(Self::GENERIC_CODE, Self::code))
+    }
+}
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::Network => "Network failure",
+            Self::Extraction => "Extraction failure",
+            Self::Aborted => "Aborted",
+            Self::Output => "Output failure",
+        };
+        write!(f, "{msg}")
+    }
+}
+
+/// What went wrong in the pipeline, for library users to branch on.
+///
+/// Like `Failure`, these are attached to errors as context, with the
+/// underlying cause (if any) further down the chain; recover them with
+/// `StashError::of`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StashError {
+    /// A page couldn't be fetched.
+    Fetch { url: String },
+
+    /// No article could be extracted from a page. Where it's known,
+    /// `field` is the part that couldn't be (e.g. `body`)
+    /// and `selector` the site config selector for it.
+    Extraction {
+        field: Option<&'static str>,
+        selector: Option<String>,
+    },
+
+    /// A page was fetched but isn't one, e.g. it's an image or JSON.
+    UnsupportedContent { url: String, content_type: String },
+
+    /// An output file couldn't be written.
+    Output { path: PathBuf },
+
+    /// A config file couldn't be read or is invalid.
+    Config { path: PathBuf },
+
+    /// A page wasn't fetched, as its site is on the blocklist.
+    Blocked { url: String, domain: String },
+}
+impl StashError {
+    /// The pipeline error of an error, if it has one.
+    pub fn of(err: &Report) -> Option<&Self> {
+        err.downcast_ref::<Self>()
+    }
+
+    /// The failure class (and so exit code) this falls under.
+    pub fn failure(&self) -> Option<Failure> {
+        match self {
+            Self::Fetch { .. } => Some(Failure::Network),
+            Self::Extraction { .. } | Self::UnsupportedContent { .. } => Some(Failure::Extraction),
+            Self::Output { .. } => Some(Failure::Output),
+            Self::Config { .. } | Self::Blocked { .. } => None,
+        }
+    }
+}
+impl Display for StashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fetch { url } => write!(f, "Couldn't fetch {url}"),
+            Self::Extraction {
+                field: Some(field),
+                selector: Some(selector),
+            } => write!(f, "Couldn't extract the {field} with `{selector}`"),
+            Self::Extraction {
+                field: Some(field),
+                selector: None,
+            } => write!(f, "Couldn't extract the {field}"),
+            Self::Extraction { field: None, .. } => write!(f, "Couldn't extract an article"),
+            Self::UnsupportedContent { url, content_type } => write!(
+                f,
+                "{url} is `{content_type}`, not a web page; only HTML and plain text can be stashed"
+            ),
+            Self::Output { path } => write!(f, "Couldn't write {}", path.display()),
+            Self::Config { path } => write!(f, "Invalid config: {}", path.display()),
+            Self::Blocked { url, domain } => write!(
+                f,
+                "Not fetching {url}, as `{domain}` is on the blocklist in config.toml"
+            ),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use color_eyre::eyre::{Result, WrapErr};
+
+use crate::{USER_AGENTS, blocklist, failure::StashError};
+
+/// The parts of an RSS/Atom feed stash cares about.
+pub struct Feed {
+    pub title: String,
+
+    /// Links to the feed's entries, newest first.
+    pub links: Vec<String>,
+}
+
+pub fn fetch(url: &str) -> Result<Feed> {
+    blocklist::check(url)?;
+    let resp = ureq::get(url)
+        .set("User-Agent", USER_AGENTS[0])
+        .call()
+        .wrap_err_with(|| StashError::Fetch {
+            url: url.to_string(),
+        })?;
+    let mut feed = feed_rs::parser::parse(resp.into_reader())
+        .wrap_err_with(|| format!("Couldn't parse feed: {url}"))?;
+
+    feed.entries
+        .sort_by_key(|entry| std::cmp::Reverse(entry.published.or(entry.updated)));
+    let links = feed
+        .entries
+        .iter()
+        .filter_map(|entry| {
+            // Prefer the entry's alternate (i.e. HTML) link.
+            entry
+                .links
+                .iter()
+                .find(|link| link.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+                .or(entry.links.first())
+                .map(|link| link.href.clone())
+        })
+        .collect();
+
+    Ok(Feed {
+        title: feed.title.map(|title| title.content).unwrap_or_default(),
+        links,
+    })
+}
@@ -0,0 +1,292 @@
+//! Site configs in the format of FiveFilters' Full-Text RSS
+//! (<https://github.com/fivefilters/ftr-site-config>), a `domain.txt` per site
+//! with XPath rules:
+//!
+//! ```text
+//! title: //h1[@class='headline']
+//! body: //div[@id='article-body']
+//! author: //span[contains(@class, 'byline')]
+//! date: //time/@datetime
+//! ```
+//!
+//! They're translated into `ExtractionMethod::Manual`s as they're loaded.
+//! Only rules whose XPath has a CSS equivalent are used; `strip` and other
+//! directives are ignored.
+
+use std::{collections::HashMap, path::Path};
+
+use color_eyre::eyre::Result;
+
+use crate::{ExtractionMethod, selector};
+
+/// Load the `*.txt` site configs in a directory, by the domain they're for.
+/// A leading dot (`.example.com`) is kept, for configs that apply to
+/// all of a domain's subdomains.
+pub fn load(dir: &Path) -> Result<HashMap<String, ExtractionMethod>> {
+    let mut configs = HashMap::new();
+    if !dir.exists() {
+        return Ok(configs);
+    }
+    for entry in fs_err::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "txt") {
+            continue;
+        }
+        let Some(domain) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        // Those without a usable body rule are left to automatic extraction.
+        if let Some(method) = translate(&fs_err::read_to_string(&path)?) {
+            configs.insert(domain.to_lowercase(), method);
+        }
+    }
+    Ok(configs)
+}
+
+/// The config for a domain: its own, or that for it without `www.`,
+/// or the nearest wildcard one for a parent domain.
+pub fn find<'a>(
+    configs: &'a HashMap<String, ExtractionMethod>,
+    domain: &str,
+) -> Option<&'a ExtractionMethod> {
+    let domain = domain.to_lowercase();
+    let bare = domain.strip_prefix("www.").unwrap_or(&domain);
+    if let Some(method) = configs.get(&domain).or_else(|| configs.get(bare)) {
+        return Some(method);
+    }
+    let mut parent = bare;
+    loop {
+        if let Some(method) = configs.get(&format!(".{parent}")) {
+            return Some(method);
+        }
+        parent = parent.split_once('.')?.1;
+    }
+}
+
+/// Translate a config file's rules into selectors,
+/// or `None` if it has no body rule that can be.
+fn translate(src: &str) -> Option<ExtractionMethod> {
+    let mut rules: HashMap<&str, Vec<String>> = HashMap::new();
+    for line in src.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, xpath)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if !matches!(key, "title" | "body" | "author" | "date") {
+            continue;
+        }
+        if let Some(css) = to_css(xpath.trim()).filter(|css| selector(css).is_ok()) {
+            rules.entry(key).or_default().push(css);
+        }
+    }
+    // Alternatives are tried in order by FiveFilters; here whichever
+    // comes first in the page is used, which is usually the same.
+    let mut rule = |key| rules.remove(key).map(|css| css.join(", "));
+    Some(ExtractionMethod::Manual {
+        body: rule("body")?,
+        title: rule("title").unwrap_or_else(|| "h1".to_string()),
+        authors: rule("author").unwrap_or_default(),
+        date: rule("date").unwrap_or_default(),
+    })
+}
+
+/// Translate the common subset of XPath used for site configs into CSS:
+/// paths of element steps with attribute, `contains`, `starts-with`,
+/// and position predicates. A trailing `/@attr` or `/text()` is dropped, as
+/// stash takes elements' text, except where that leaves a (textless) `meta`.
+fn to_css(xpath: &str) -> Option<String> {
+    let alternatives: Vec<_> = split_top(xpath, "|")
+        .into_iter()
+        .filter_map(|path| path_to_css(path.trim()))
+        .collect();
+    (!alternatives.is_empty()).then(|| alternatives.join(", "))
+}
+
+fn path_to_css(path: &str) -> Option<String> {
+    let mut css = String::new();
+    let mut rest = path.strip_prefix('.').unwrap_or(path);
+    let mut last_element = "";
+    while !rest.is_empty() {
+        let (descendant, step) = if let Some(step) = rest.strip_prefix("//") {
+            (true, step)
+        } else if let Some(step) = rest.strip_prefix('/') {
+            (false, step)
+        } else if css.is_empty() {
+            // A relative path, as if from anywhere.
+            (true, rest)
+        } else {
+            return None;
+        };
+        // Steps end at the next `/` outside brackets and quotes.
+        let (step, after) = step.split_at(split_top(step, "/")[0].len());
+        rest = after;
+
+        if step.starts_with('@') || step == "text()" || step == "node()" {
+            // Only as the last step, and not of a `meta`.
+            if !rest.is_empty() || last_element == "meta" || css.is_empty() {
+                return None;
+            }
+            break;
+        }
+        if !css.is_empty() {
+            css.push_str(if descendant { " " } else { " > " });
+        }
+        let (name, predicates) = match step.find('[') {
+            Some(i) => step.split_at(i),
+            None => (step, ""),
+        };
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "*-_".contains(c))
+        {
+            return None;
+        }
+        last_element = name;
+        css.push_str(name);
+        for predicate in split_predicates(predicates)? {
+            css.push_str(&predicate_to_css(predicate.trim())?);
+        }
+    }
+    (!css.is_empty()).then_some(css)
+}
+
+/// Split on a separator outside brackets and quotes.
+fn split_top<'a>(value: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    let mut chars = value.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => {}
+            (_, None) if depth == 0 && value[i..].starts_with(sep) => {
+                parts.push(&value[start..i]);
+                start = i + sep.len();
+                for _ in 1..sep.chars().count() {
+                    chars.next();
+                }
+            }
+            ('\'' | '"', None) => quote = Some(c),
+            ('[' | '(', None) => depth += 1,
+            (']' | ')', None) => depth -= 1,
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// `[a][b]` into `a` and `b`.
+fn split_predicates(predicates: &str) -> Option<Vec<&str>> {
+    let mut found = vec![];
+    let mut rest = predicates;
+    while !rest.is_empty() {
+        let inner = rest.strip_prefix('[')?;
+        let end = split_top(inner, "]").first()?.len();
+        found.push(&inner[..end]);
+        rest = inner.get(end + 1..)?;
+    }
+    Some(found)
+}
+
+fn predicate_to_css(predicate: &str) -> Option<String> {
+    let mut css = String::new();
+    for condition in split_top(predicate, " and ") {
+        css.push_str(&condition_to_css(condition.trim())?);
+    }
+    Some(css)
+}
+
+fn condition_to_css(condition: &str) -> Option<String> {
+    if let Ok(n) = condition.parse::<usize>() {
+        return Some(format!(":nth-of-type({n})"));
+    }
+    if condition == "last()" {
+        return Some(":last-of-type".to_string());
+    }
+    if let Some(inner) = condition
+        .strip_prefix("not(")
+        .and_then(|inner| inner.strip_suffix(')'))
+    {
+        return Some(format!(":not({})", condition_to_css(inner.trim())?));
+    }
+    if let Some(attr) = condition.strip_prefix('@') {
+        return Some(match attr.split_once('=') {
+            None => format!("[{}]", name(attr.trim())?),
+            Some((attr, value)) => format!(
+                "[{}=\"{}\"]",
+                name(attr.trim())?,
+                escape(literal(value.trim())?)
+            ),
+        });
+    }
+    for (function, operator) in [("contains", "*="), ("starts-with", "^=")] {
+        let Some(args) = condition
+            .strip_prefix(function)
+            .map(str::trim_start)
+            .and_then(|args| args.strip_prefix('('))
+            .and_then(|args| args.strip_suffix(')'))
+        else {
+            continue;
+        };
+        let [target, value] = split_top(args, ",")[..] else {
+            return None;
+        };
+        let value = literal(value.trim())?;
+        let target = target.trim();
+        // The idiom for matching a whole class name.
+        if target.starts_with("concat(") && target.contains("@class") && operator == "*=" {
+            let class = value.trim();
+            if class.contains(' ') || class.is_empty() {
+                return None;
+            }
+            return Some(format!(".{}", class_name(class)?));
+        }
+        let attr = target
+            .strip_prefix("normalize-space(")
+            .and_then(|target| target.strip_suffix(')'))
+            .unwrap_or(target)
+            .strip_prefix('@')?;
+        return Some(format!("[{}{operator}\"{}\"]", name(attr)?, escape(value)));
+    }
+    None
+}
+
+/// A quoted XPath string.
+fn literal(value: &str) -> Option<&str> {
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .or_else(|| {
+            value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+        })
+}
+
+fn name(name: &str) -> Option<&str> {
+    (!name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_:".contains(c)))
+    .then_some(name)
+}
+
+fn class_name(class: &str) -> Option<&str> {
+    (!class.starts_with(|c: char| c.is_ascii_digit())
+        && class
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_".contains(c)))
+    .then_some(class)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
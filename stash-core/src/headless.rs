@@ -0,0 +1,32 @@
+//! Rendering pages in a headless browser (`headless` in `config.toml`),
+//! for sites that only fill in their articles with JavaScript: pages
+//! that automatic extraction gets too few words from are rendered too,
+//! and whichever has more is kept.
+
+use std::process::{Command, Stdio};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+
+/// Render a page with the command, which gets its URL as the last
+/// argument and should print the page's HTML once its scripts have run
+/// (e.g. `chromium --headless --dump-dom`).
+pub fn render(command: &[String], url: &str) -> Result<String> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("There's no `headless` command");
+    };
+    let output = Command::new(program)
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .wrap_err_with(|| format!("Couldn't run `{program}`"))?;
+    if !output.status.success() {
+        bail!("`{program}` failed: {}", output.status);
+    }
+    let html = String::from_utf8_lossy(&output.stdout).into_owned();
+    if html.trim().is_empty() {
+        bail!("`{program}` printed nothing");
+    }
+    Ok(html)
+}
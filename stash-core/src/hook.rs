@@ -0,0 +1,36 @@
+use std::{path::Path, process::Command};
+
+use color_eyre::eyre::{Result, bail};
+
+use crate::logging;
+
+/// Run the post-build hook on a newly written file.
+///
+/// The command gets the file's path as its last argument, and it and
+/// the article's metadata as environment variables: `ARTICLE_PATH`,
+/// `ARTICLE_URL`, `ARTICLE_TITLE`, `ARTICLE_AUTHORS`, and
+/// `ARTICLE_PUBLISHED_AT`. Failures are only warned about,
+/// as the file's been written regardless.
+pub fn run(command: &[String], path: &Path, url: &str, title: &str, authors: &str, date: &str) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    let run = || -> Result<()> {
+        let status = Command::new(program)
+            .args(args)
+            .arg(path)
+            .env("ARTICLE_PATH", path)
+            .env("ARTICLE_URL", url)
+            .env("ARTICLE_TITLE", title)
+            .env("ARTICLE_AUTHORS", authors)
+            .env("ARTICLE_PUBLISHED_AT", date)
+            .status()?;
+        if !status.success() {
+            bail!("{status}");
+        }
+        Ok(())
+    };
+    if let Err(err) = run() {
+        logging::warn(format!("Post-build hook `{program}` failed: {err:#}"));
+    }
+}
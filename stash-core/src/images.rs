@@ -0,0 +1,364 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    sync::{
+        LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use color_eyre::eyre::{Result, WrapErr, bail};
+use image::{
+    DynamicImage, GrayImage, ImageFormat, Luma,
+    codecs::jpeg::JpegEncoder,
+    imageops::{self, ColorMap, FilterType},
+};
+use scraper::{Html, Node, Selector};
+use ureq::{Agent, AgentBuilder};
+use url::Url;
+
+use crate::{
+    MAX_BODY_SIZE, USER_AGENTS, blocklist, config::Images, logging, progress::Progress, throttle,
+};
+
+/// How many images are downloaded at once.
+const MAX_DOWNLOADS: usize = 6;
+
+/// An image to package into the EPUB.
+pub struct Image {
+    /// Path within the EPUB, e.g. `images/1-3.jpg`.
+    pub href: String,
+    pub mime: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Download the images in an HTML fragment (relative to `base_url`)
+/// so they can be packaged into the EPUB, and point the `<img>`s at them.
+/// They're downloaded several at a time, reusing connections to their hosts.
+///
+/// Images are downscaled to fit the configured max dimension and
+/// re-encoded, so long articles full of huge photos stay a reasonable
+/// size; WebP is converted to JPEG (or PNG, if it has transparency)
+/// for older readers. In e-ink mode they're instead converted
+/// to dithered grayscale. Images that can't be fetched are left as they
+/// are, with a warning. Inline (`data:`) images are packaged
+/// the same way. With images disabled, they're all removed instead.
+///
+/// `prefix` distinguishes the images of different articles,
+/// and the downloads are shown in `progress`.
+pub fn embed(
+    html: &str,
+    base_url: &str,
+    settings: &Images,
+    prefix: &str,
+    progress: &Progress,
+) -> (String, Vec<Image>) {
+    let base = Url::parse(base_url).ok();
+    let mut doc = Html::parse_fragment(html);
+    if !settings.enabled {
+        let sel = Selector::parse("img, figure, picture, svg").expect("Valid selector");
+        let ids: Vec<_> = doc.select(&sel).map(|el| el.id()).collect();
+        for id in ids {
+            if let Some(mut node) = doc.tree.get_mut(id) {
+                node.detach();
+            }
+        }
+        return (doc.root_element().inner_html(), vec![]);
+    }
+    let imgs: Vec<_> = doc
+        .select(&Selector::parse("img[src]").expect("Valid selector"))
+        .map(|el| (el.id(), el.attr("src").unwrap_or_default().to_string()))
+        .collect();
+
+    // `<source>`s would point to remote files.
+    let sources: Vec<_> = doc
+        .select(&Selector::parse("picture source").expect("Valid selector"))
+        .map(|el| el.id())
+        .collect();
+    for id in sources {
+        if let Some(mut node) = doc.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    // Each image once, however many times it's used.
+    // Those on blocklisted domains are left as they are.
+    let mut srcs: Vec<&str> = vec![];
+    for (_, src) in &imgs {
+        let blocked = match &base {
+            Some(base) => base.join(src).map(String::from),
+            None => Url::parse(src).map(String::from),
+        }
+        .is_ok_and(|url| blocklist::blocked(&url).is_some());
+        if !blocked && !srcs.contains(&src.as_str()) {
+            srcs.push(src);
+        }
+    }
+    let mut images = vec![];
+    let mut hrefs: HashMap<&str, String> = HashMap::new();
+    for (src, image) in
+        srcs.iter()
+            .zip(load_all(&srcs, base.as_ref(), base_url, settings, progress))
+    {
+        match image {
+            Ok((mime, ext, data)) => {
+                let href = format!("images/{prefix}-{}.{ext}", images.len() + 1);
+                images.push(Image {
+                    href: href.clone(),
+                    mime,
+                    data,
+                });
+                hrefs.insert(src, href);
+            }
+            Err(err) => {
+                let src = match src.starts_with("data:") {
+                    true => "(inline)",
+                    false => src,
+                };
+                logging::warn(format!("Couldn't embed image {src}: {err:#}"));
+            }
+        }
+    }
+
+    for (id, src) in &imgs {
+        let Some(href) = hrefs.get(src.as_str()) else {
+            continue;
+        };
+        if let Some(mut node) = doc.tree.get_mut(*id)
+            && let Node::Element(el) = node.value()
+        {
+            el.attrs
+                .retain(|(name, _)| !matches!(&*name.local, "srcset" | "sizes" | "loading"));
+            for (name, value) in el.attrs.iter_mut() {
+                if &*name.local == "src" {
+                    *value = href.as_str().into();
+                }
+            }
+        }
+    }
+    (doc.root_element().inner_html(), images)
+}
+
+/// Download (or decode) and process images, several at a time,
+/// giving the results in the same order.
+fn load_all(
+    srcs: &[&str],
+    base: Option<&Url>,
+    referer: &str,
+    settings: &Images,
+    progress: &Progress,
+) -> Vec<Result<(&'static str, &'static str, Vec<u8>)>> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..MAX_DOWNLOADS.min(srcs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(src) = srcs.get(i) else {
+                            break done;
+                        };
+                        done.push((i, load(src, base, referer, settings, progress)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Image worker panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn load(
+    src: &str,
+    base: Option<&Url>,
+    referer: &str,
+    settings: &Images,
+    progress: &Progress,
+) -> Result<(&'static str, &'static str, Vec<u8>)> {
+    // Inline images (e.g. from saved pages) are packaged like the rest.
+    let data = match src.strip_prefix("data:") {
+        Some(uri) => decode_data_uri(uri)?,
+        None => {
+            let url = match base {
+                Some(base) => base.join(src).map(|url| url.to_string()),
+                None => Ok(src.to_string()),
+            }
+            .wrap_err("Invalid image URL")?;
+            fetch(&url, referer, progress)?
+        }
+    };
+    process(data, settings)
+}
+
+/// An agent shared by asset downloads (images, and mirrored pages'
+/// assets), so that those from the same host reuse their connections
+/// rather than each opening (and negotiating TLS for) its own.
+pub(crate) fn agent() -> &'static Agent {
+    static AGENT: LazyLock<Agent> = LazyLock::new(|| {
+        AgentBuilder::new()
+            .max_idle_connections_per_host(MAX_DOWNLOADS)
+            .build()
+    });
+    &AGENT
+}
+
+fn fetch(url: &str, referer: &str, progress: &Progress) -> Result<Vec<u8>> {
+    let _download = throttle::download();
+    let resp = agent()
+        .get(url)
+        .set("User-Agent", USER_AGENTS[0])
+        .set("Referer", referer)
+        .call()?;
+    download(resp, url, progress)
+}
+
+/// Read an asset's response, showing its progress.
+pub(crate) fn download(resp: ureq::Response, url: &str, progress: &Progress) -> Result<Vec<u8>> {
+    let len = resp
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok());
+    let bar = progress.download(url, len);
+    let mut data = vec![];
+    let read = bar
+        .wrap_read(throttle::reader(url, resp.into_reader()).take(MAX_BODY_SIZE))
+        .read_to_end(&mut data);
+    bar.finish_and_clear();
+    read?;
+    Ok(data)
+}
+
+/// The data of a `data:` URI (without the scheme), base64 or percent-encoded.
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>> {
+    let Some((meta, data)) = uri.split_once(',') else {
+        bail!("Invalid data URI");
+    };
+    if meta.ends_with(";base64") {
+        let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+        return Ok(BASE64_STANDARD.decode(data)?);
+    }
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.bytes();
+    while let Some(byte) = bytes.next() {
+        let escaped = (byte == b'%')
+            .then(|| {
+                let hex = [bytes.clone().next()?, bytes.clone().nth(1)?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()
+            })
+            .flatten();
+        match escaped {
+            Some(escaped) => {
+                decoded.push(escaped);
+                bytes.nth(1);
+            }
+            None => decoded.push(byte),
+        }
+    }
+    Ok(decoded)
+}
+
+/// Downscale and re-encode an image,
+/// returning its mime type, extension, and data.
+fn process(data: Vec<u8>, settings: &Images) -> Result<(&'static str, &'static str, Vec<u8>)> {
+    let format = match image::guess_format(&data) {
+        Ok(format) => format,
+
+        // SVGs can't be (and don't need to be) resized.
+        Err(_) if is_svg(&data) => return Ok(("image/svg+xml", "svg", data)),
+        Err(_) => bail!("Unsupported image format"),
+    };
+
+    // Re-encoding would lose the animation.
+    if format == ImageFormat::Gif {
+        return Ok(("image/gif", "gif", data));
+    }
+
+    let img = image::load_from_memory_with_format(&data, format)?;
+    let max = settings.max_dimension;
+    let resized = img.width() > max || img.height() > max;
+    let img = if resized {
+        img.resize(max, max, FilterType::Lanczos3)
+    } else {
+        img
+    };
+
+    if settings.eink {
+        return Ok(("image/png", "png", encode_png(&eink(&img).into())?));
+    }
+
+    let (mime, ext, encoded) = match format {
+        ImageFormat::Png => ("image/png", "png", encode_png(&img)?),
+        _ if img.color().has_alpha() => ("image/png", "png", encode_png(&img)?),
+        _ => (
+            "image/jpeg",
+            "jpg",
+            encode_jpeg(&img, settings.jpeg_quality)?,
+        ),
+    };
+
+    // Re-encoding an image that was already small
+    // enough and in a supported format may not help.
+    let supported = matches!(format, ImageFormat::Png | ImageFormat::Jpeg);
+    if !resized && supported && encoded.len() >= data.len() {
+        return Ok((mime, ext, data));
+    }
+    Ok((mime, ext, encoded))
+}
+
+/// Gray levels e-paper displays can show.
+const EINK_LEVELS: u8 = 16;
+
+/// Convert an image for e-paper: grayscale (on white, for transparent
+/// images), with boosted contrast, dithered to the display's gray levels.
+fn eink(img: &DynamicImage) -> GrayImage {
+    let rgba = img.to_rgba8();
+    let mut gray = GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0.map(f32::from);
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        let alpha = a / 255.;
+        Luma([(luma * alpha + 255. * (1. - alpha)) as u8])
+    });
+    imageops::colorops::contrast_in_place(&mut gray, 20.);
+    imageops::dither(&mut gray, &GrayLevels(EINK_LEVELS));
+    gray
+}
+
+/// A color map of evenly spaced gray levels.
+struct GrayLevels(u8);
+impl ColorMap for GrayLevels {
+    type Color = Luma<u8>;
+
+    fn index_of(&self, color: &Luma<u8>) -> usize {
+        let steps = u16::from(self.0 - 1);
+        ((u16::from(color.0[0]) * steps + 127) / 255) as usize
+    }
+
+    fn map_color(&self, color: &mut Luma<u8>) {
+        let steps = usize::from(self.0 - 1);
+        color.0[0] = (self.index_of(color) * 255 / steps) as u8;
+    }
+}
+
+fn encode_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    img.to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))?;
+    Ok(out)
+}
+
+fn encode_png(img: &DynamicImage) -> Result<Vec<u8>> {
+    let mut out = Cursor::new(vec![]);
+    img.write_to(&mut out, ImageFormat::Png)?;
+    Ok(out.into_inner())
+}
+
+fn is_svg(data: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&data[..data.len().min(512)]);
+    start.contains("<svg")
+}
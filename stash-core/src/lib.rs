@@ -0,0 +1,1262 @@
+//! Stash's extraction pipeline: fetching web articles,
+//! extracting their content, and building EPUBs from them.
+
+pub mod annotations;
+pub mod archive;
+pub mod blocklist;
+pub mod byline;
+pub mod calibre;
+pub mod comments;
+pub mod config;
+mod cover;
+pub mod crypt;
+pub mod date;
+pub mod discussion;
+pub mod epub;
+pub mod failure;
+pub mod feed;
+mod fivefilters;
+mod headless;
+pub mod hook;
+mod images;
+pub mod library;
+pub mod links;
+pub mod logging;
+pub mod merge;
+pub mod mirror;
+mod newsletter;
+mod pages;
+pub mod plugin;
+pub mod progress;
+mod remote;
+pub mod retry;
+pub mod saved;
+pub mod script;
+pub mod sites;
+mod sources;
+mod style;
+pub mod summary;
+mod template;
+pub mod throttle;
+pub mod translate;
+mod typography;
+pub mod upload;
+pub mod usage;
+mod user_agents;
+pub mod validate;
+pub mod warning;
+pub mod webhook;
+pub mod xhtml;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use dom_smoothie::{Article as ExtractArticle, Config as ExtractConfig, Readability};
+use encoding_rs::Encoding;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use byline::Byline;
+use comments::Comments;
+use config::{Collision, Config, ImageOverrides, Length, RemoteExtractor, Validation, Wikipedia};
+use epub::Book;
+use failure::StashError;
+use library::Library;
+use logging::Event;
+use plugin::Plugin;
+use progress::Progress;
+use sources::Source;
+use warning::Warning;
+
+pub const APP_NAME: &str = "stash";
+/// The largest page to fetch, unless the extractor's given another limit.
+const MAX_BODY_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Articles shorter than this aren't checked for copies under other URLs;
+/// too many stubs and error pages would match each other.
+const MIN_HASHED_WORDS: usize = 50;
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
+const USER_AGENTS: &[&str] = &["curl/8.11", BROWSER_USER_AGENT];
+/// Query parameters that only track where a click came from,
+/// besides any starting with `utm_`.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_hsenc",
+    "_hsmi", "mkt_tok",
+];
+
+/// A site's entry in `sites.toml`: how to extract its articles,
+/// and any settings for its books other than `config.toml`'s.
+/// An entry with only settings is extracted automatically.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(try_from = "SiteEntry")]
+pub struct Site {
+    #[serde(flatten)]
+    pub method: ExtractionMethod,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub images: ImageOverrides,
+
+    /// Saved pages to check its extraction against, see `SiteTest`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<SiteTest>,
+
+    /// Whether (and how) to keep pages' comment sections, see `comments`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Comments>,
+
+    /// How automatic extraction finds its byline, instead of `[byline]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byline: Option<Byline>,
+
+    /// The profile (see `config::apply_profile`) to make its books with,
+    /// unless `--profile` picks one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+/// A saved page of a site, and what should be extracted from it;
+/// each expectation that's left out isn't checked.
+/// Run by `stash sites test`, to catch a change of selectors
+/// (or of automatic extraction) breaking a site.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SiteTest {
+    /// The saved HTML, relative to the config dir.
+    pub fixture: PathBuf,
+
+    /// The page's URL, for resolving its links; defaults to the domain's root.
+    pub url: Option<String>,
+
+    pub title: Option<String>,
+    pub authors: Option<String>,
+    pub date: Option<String>,
+
+    /// See `Article::body_hash`.
+    pub body_hash: Option<String>,
+}
+impl SiteTest {
+    /// Extract the fixture with the site's config, returning how it
+    /// differs from what's expected; nothing if the test passes.
+    pub fn run(&self, domain: &str, site: &Site, tz: Tz) -> Result<Vec<String>> {
+        let html = fs_err::read_to_string(&self.fixture)?;
+        let url = match &self.url {
+            Some(url) => normalize_url(url),
+            None => format!("https://{domain}/"),
+        };
+        let mut article = Method::Site(&site.method).extract(&url, &html)?;
+        article.normalize_date(tz);
+        let body_hash = article.body_hash();
+        Ok([
+            ("title", &self.title, &article.title),
+            ("authors", &self.authors, &article.authors),
+            ("date", &self.date, &article.published_at),
+            ("body_hash", &self.body_hash, &body_hash),
+        ]
+        .into_iter()
+        .filter_map(|(field, expected, got)| {
+            let expected = expected.as_ref()?;
+            (expected != got).then(|| format!("{field}: expected {expected:?}, got {got:?}"))
+        })
+        .collect())
+    }
+}
+
+/// A `Site` as it's written, before its extraction keys are
+/// told apart (which `ExtractionMethod` can't do for none at all).
+#[derive(Deserialize)]
+struct SiteEntry {
+    #[serde(default)]
+    images: ImageOverrides,
+
+    #[serde(default)]
+    tests: Vec<SiteTest>,
+
+    #[serde(default)]
+    comments: Option<Comments>,
+
+    #[serde(default)]
+    byline: Option<Byline>,
+
+    #[serde(default)]
+    profile: Option<String>,
+
+    #[serde(flatten)]
+    method: toml::Table,
+}
+impl TryFrom<SiteEntry> for Site {
+    type Error = toml::de::Error;
+
+    fn try_from(entry: SiteEntry) -> Result<Self, Self::Error> {
+        let method = if entry.method.is_empty() {
+            ExtractionMethod::Auto
+        } else {
+            toml::Value::Table(entry.method).try_into()?
+        };
+        Ok(Self {
+            method,
+            images: entry.images,
+            tests: entry.tests,
+            comments: entry.comments,
+            byline: entry.byline,
+            profile: entry.profile,
+        })
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(untagged)]
+pub enum ExtractionMethod {
+    #[default]
+    Auto,
+    Manual {
+        title: String,
+        body: String,
+        authors: String,
+        date: String,
+    },
+
+    /// A Rhai script, see `script::extract`.
+    Script { script: PathBuf },
+}
+impl ExtractionMethod {
+    /// Keys required for a manual extraction config.
+    pub const MANUAL_FIELDS: &[&str] = &["title", "body", "authors", "date"];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Manual { .. } => "manual",
+            Self::Script { .. } => "script",
+        }
+    }
+
+    fn extract(&self, uri: &str, html: &str) -> Result<Article> {
+        match self {
+            Self::Auto => auto_extract(uri, html),
+            Self::Manual {
+                title,
+                body,
+                authors,
+                date,
+            } => manual_extract(uri, html, title, body, authors, date),
+            Self::Script { script } => script::extract(script, uri, html),
+        }
+    }
+}
+
+fn auto_extract(url: &str, html: &str) -> Result<Article> {
+    let cfg = ExtractConfig::default();
+    let mut readability = Readability::new(html, Some(url), Some(cfg))?;
+    let article: ExtractArticle = readability.parse()?;
+    let mut entry = Article {
+        url: url.to_string(),
+        title: article.title,
+        authors: article.byline.unwrap_or_default(),
+        published_at: article.published_time.unwrap_or_default(),
+        content: article.content.to_string(), // HTML content
+        ..Default::default()
+    };
+    page_metadata(&Html::parse_document(html), &mut entry);
+    if let Some(site_name) = article.site_name {
+        entry.site_name = site_name;
+    }
+    if let Some(lang) = article.lang {
+        entry.lang = lang;
+    }
+    Ok(entry)
+}
+
+/// Fill in the language, and the publisher, keywords and rights
+/// from the page's `<meta>` tags.
+fn page_metadata(doc: &Html, entry: &mut Article) {
+    let meta = |attr: &str, names: &[&str]| {
+        doc.select(&Selector::parse("meta[content]").expect("Valid selector"))
+            .filter(|el| {
+                el.value()
+                    .attr(attr)
+                    .is_some_and(|name| names.iter().any(|n| name.eq_ignore_ascii_case(n)))
+            })
+            .filter_map(|el| el.value().attr("content"))
+            .map(str::trim)
+            .filter(|content| !content.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+
+    let site_name = meta("property", &["og:site_name"])
+        .into_iter()
+        .chain(meta("name", &["application-name"]))
+        .next();
+    entry.site_name = site_name.unwrap_or_default();
+
+    let mut keywords = meta("property", &["article:tag"]);
+    for list in meta("name", &["keywords", "news_keywords"]) {
+        keywords.extend(list.split(',').map(|kw| kw.trim().to_string()));
+    }
+    let mut seen = std::collections::HashSet::new();
+    keywords.retain(|kw| !kw.is_empty() && seen.insert(kw.to_lowercase()));
+    entry.keywords = keywords;
+
+    entry.lang = doc
+        .root_element()
+        .attr("lang")
+        .unwrap_or_default()
+        .to_string();
+
+    entry.rights = meta("name", &["copyright", "dcterms.rights", "dc.rights"])
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+
+    let base = Url::parse(&entry.url).ok();
+    entry.feeds = doc
+        .select(&Selector::parse("link[rel~=alternate][href]").expect("Valid selector"))
+        .filter(|el| {
+            el.value().attr("type").is_some_and(|kind| {
+                [
+                    "application/rss+xml",
+                    "application/atom+xml",
+                    "application/feed+json",
+                ]
+                .contains(&kind.trim().to_lowercase().as_str())
+            })
+        })
+        .filter_map(|el| el.value().attr("href"))
+        .filter_map(|href| match &base {
+            Some(base) => base.join(href.trim()).ok(),
+            None => Url::parse(href.trim()).ok(),
+        })
+        .map(String::from)
+        // Comment feeds aren't what's wanted for following a site.
+        .filter(|feed| !feed.contains("/comments/feed"))
+        .collect();
+    let mut seen = std::collections::HashSet::new();
+    entry.feeds.retain(|feed| seen.insert(feed.clone()));
+}
+
+pub fn selector(sel: &str) -> Result<Selector> {
+    Selector::parse(sel).map_err(|err| eyre!(err.to_string()))
+}
+
+fn manual_extract(
+    url: &str,
+    html: &str,
+    title_sel: &str,
+    body_sel: &str,
+    authors_sel: &str,
+    date_sel: &str,
+) -> Result<Article> {
+    let doc = Html::parse_document(html);
+    let extraction_error = |field, sel: &str| StashError::Extraction {
+        field: Some(field),
+        selector: Some(sel.to_string()),
+    };
+    let parse = |field, sel: &str| selector(sel).wrap_err_with(|| extraction_error(field, sel));
+    // The authors and date can be left out with an empty selector.
+    let optional = |field, sel: &str| (!sel.is_empty()).then(|| parse(field, sel)).transpose();
+    let title = parse("title", title_sel)?;
+    let body = parse("body", body_sel)?;
+    let authors = optional("authors", authors_sel)?;
+    let date = optional("date", date_sel)?;
+
+    let mut entry = Article {
+        url: url.to_string(),
+        ..Default::default()
+    };
+
+    let missing = |field, selector: &str| Warning::MissingElement {
+        field,
+        selector: selector.to_string(),
+    };
+    if let Some(el) = doc.select(&title).next() {
+        entry.title = el.text().collect::<Vec<_>>().join("");
+    } else {
+        entry.warnings.push(missing("title", title_sel));
+    }
+
+    if let Some(authors) = &authors {
+        if let Some(el) = doc.select(authors).next() {
+            entry.authors = el.text().collect::<Vec<_>>().join("");
+        } else {
+            entry.warnings.push(missing("authors", authors_sel));
+        }
+    }
+
+    if let Some(date) = &date {
+        if let Some(el) = doc.select(date).next() {
+            entry.published_at = el.text().collect::<Vec<_>>().join("");
+        } else {
+            entry.warnings.push(missing("date", date_sel));
+        }
+    }
+
+    if let Some(el) = doc.select(&body).next() {
+        entry.content = el.inner_html();
+    } else {
+        return Err(eyre!("Could not find main content element."))
+            .wrap_err(extraction_error("body", body_sel));
+    }
+    if entry.content.is_empty() {
+        return Err(eyre!("Main content element is empty."))
+            .wrap_err(extraction_error("body", body_sel));
+    }
+    page_metadata(&doc, &mut entry);
+
+    Ok(entry)
+}
+
+/// See `Article::stats`.
+pub struct Stats {
+    pub words: usize,
+    pub paragraphs: usize,
+    pub images: usize,
+}
+
+#[derive(Serialize, Default)]
+pub struct Article {
+    pub url: String,
+    pub title: String,
+    pub content: String,
+    pub authors: String,
+    pub published_at: String,
+
+    /// The language the page declares, e.g. `en-US`.
+    pub lang: String,
+
+    /// The site's name, e.g. from `og:site_name`.
+    pub site_name: String,
+    pub keywords: Vec<String>,
+
+    /// The page's copyright statement, if it has one.
+    pub rights: String,
+
+    /// The feeds the page links to (`<link rel="alternate">`).
+    pub feeds: Vec<String>,
+
+    /// A generated abstract, see `summary::summarize`.
+    pub summary: String,
+
+    /// The language the article was machine-translated from, if it was.
+    pub translated_from: String,
+
+    /// Why it was stashed, e.g. from `--note`.
+    pub note: String,
+
+    /// Its discussion, to append to the book, see `discussion::find`.
+    pub discussion: Option<discussion::Thread>,
+
+    /// Its page's own comments, to append to the book, see `comments`.
+    pub comments: Vec<discussion::Comment>,
+
+    /// How the article was extracted (`auto`, `manual`, `plugin:<name>`,
+    /// or the source or platform it's of, like `youtube` or `substack`).
+    pub extraction: String,
+    pub fetched_at: DateTime<Utc>,
+
+    /// What may have gone wrong extracting it, see `Warning`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+
+    /// Its site's `[images]` settings in `sites.toml`, if it has any.
+    #[serde(skip)]
+    pub images: ImageOverrides,
+
+    /// Its site's profile in `sites.toml`, if it has one.
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+impl Article {
+    /// How many words of text the article has.
+    pub fn word_count(&self) -> usize {
+        self.stats().words
+    }
+
+    /// What's suspicious about the article's length, if anything.
+    pub fn length_problem(&self, length: &Length) -> Option<String> {
+        if length.check == Validation::Off {
+            return None;
+        }
+        let words = self.word_count();
+        if length.min_words > 0 && words < length.min_words {
+            Some(format!(
+                "Only {words} words were extracted, so the extraction may have failed"
+            ))
+        } else if length.max_words > 0 && words > length.max_words {
+            Some(format!(
+                "{words} words were extracted, so it may be more than the article"
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// A hash of the article's words, ignoring case, punctuation, and markup,
+    /// to recognize copies of it syndicated under other URLs.
+    /// `None` if it's too short for a match to mean much.
+    pub fn content_hash(&self) -> Option<String> {
+        let words = self.words();
+        if words.len() < MIN_HASHED_WORDS {
+            return None;
+        }
+        Some(hex::encode(Sha256::digest(words.join(" ").as_bytes())))
+    }
+
+    /// A hash of the article's text, as with `content_hash`,
+    /// however short it is.
+    pub fn body_hash(&self) -> String {
+        hex::encode(Sha256::digest(self.words().join(" ").as_bytes()))
+    }
+
+    /// The article's words, lowercased, without punctuation or markup.
+    fn words(&self) -> Vec<String> {
+        let text = Html::parse_fragment(&self.content)
+            .root_element()
+            .text()
+            .collect::<String>();
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    /// The article's text, a line per paragraph (or heading, list item, etc.),
+    /// for comparing captures of it.
+    pub fn plain_text(&self) -> String {
+        let doc = Html::parse_fragment(&self.content);
+        let blocks = Selector::parse("p, h1, h2, h3, h4, h5, h6, li, pre, figcaption, td")
+            .expect("valid selector");
+        let mut text = format!("# {}\n\n", self.title);
+        for block in doc.select(&blocks) {
+            let line = block
+                .text()
+                .flat_map(str::split_whitespace)
+                .collect::<Vec<_>>();
+            if !line.is_empty() {
+                text.push_str(&line.join(" "));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Counts of what's in the article, to judge whether all of it was extracted.
+    pub fn stats(&self) -> Stats {
+        let doc = Html::parse_fragment(&self.content);
+        let count = |sel: &str| {
+            doc.select(&Selector::parse(sel).expect("valid selector"))
+                .count()
+        };
+        Stats {
+            words: doc
+                .root_element()
+                .text()
+                .map(|text| text.split_whitespace().count())
+                .sum(),
+            paragraphs: count("p"),
+            images: count("img"),
+        }
+    }
+
+    /// Normalize the publication date (see `date::normalize`),
+    /// leaving it as is if it can't be parsed.
+    pub fn normalize_date(&mut self, tz: Tz) {
+        if let Some(date) = date::normalize(&self.published_at, tz) {
+            self.published_at = date;
+        } else if !self.published_at.is_empty() {
+            self.warn(Warning::UnparsableDate {
+                date: self.published_at.clone(),
+            });
+        }
+    }
+
+    /// Note something that may have gone wrong extracting the article,
+    /// unless it's been noted already.
+    pub fn warn(&mut self, warning: Warning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Returns `None` if an existing file was left alone.
+    pub fn build_epub(
+        &self,
+        output_dir: &Path,
+        config: &Config,
+        progress: &Progress,
+    ) -> Result<Option<PathBuf>> {
+        let book = Book::single(self);
+        let host = Url::parse(&self.url).ok();
+        let host = host.as_ref().and_then(Url::host_str);
+        let output_dir = match config
+            .routes
+            .iter()
+            .find(|route| route.matches(host, &self.keywords))
+        {
+            Some(route) => output_dir.join(route.dir()),
+            None => output_dir.to_path_buf(),
+        };
+        let path = book.path(&output_dir, config);
+        let collision = match config.on_collision {
+            // Rather than overwrite an earlier capture of the same article,
+            // keep both, e.g. to compare them with `stash diff`.
+            Collision::Overwrite if Library::has_capture(&self.url, &path) => Collision::Number,
+            collision => collision,
+        };
+        let Some(path) = epub::resolve_collision(path, collision) else {
+            return Ok(None);
+        };
+        book.write(&path, config, progress)
+            .wrap_err_with(|| StashError::Output { path: path.clone() })?;
+        Ok(Some(path))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub struct Extractor {
+    configs: HashMap<String, Site>,
+
+    /// Used for domains without a config.
+    #[serde(skip)]
+    plugins: Vec<Plugin>,
+
+    /// Tried when local extraction fails.
+    #[serde(skip)]
+    remote: Option<RemoteExtractor>,
+
+    /// Used for domains without a config or plugin, see `fivefilters`.
+    #[serde(skip)]
+    fivefilters: HashMap<String, ExtractionMethod>,
+
+    /// The largest page to fetch, in bytes.
+    #[serde(skip)]
+    max_body_size: Option<u64>,
+
+    /// A Nitter instance to fetch Twitter/X threads through.
+    #[serde(skip)]
+    nitter: Option<String>,
+
+    #[serde(skip)]
+    wikipedia: Wikipedia,
+
+    /// How automatic extraction finds bylines, for sites without their own.
+    #[serde(skip)]
+    byline: Byline,
+
+    /// A command to render pages headlessly with, see `headless`.
+    #[serde(skip)]
+    headless: Vec<String>,
+
+    /// Pages extracted automatically to fewer words than this are
+    /// rendered headlessly too.
+    #[serde(skip)]
+    thin_words: usize,
+
+    /// Extract pages as they were last fetched, rather than fetching them.
+    #[serde(skip)]
+    offline: bool,
+}
+impl Extractor {
+    /// Load the site configs in `sites.toml`; without one,
+    /// every site is extracted automatically.
+    pub fn load(path: &Path) -> Result<Self> {
+        let src = match fs_err::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut extractor: Self = toml::from_str(&src)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for site in extractor.configs.values_mut() {
+            if let ExtractionMethod::Script { script: path } = &mut site.method {
+                *path = script::resolve(dir, &path.to_string_lossy());
+            }
+            for test in &mut site.tests {
+                test.fixture = dir.join(&test.fixture);
+            }
+        }
+        Ok(extractor)
+    }
+
+    /// Also use the plugins in this directory.
+    pub fn with_plugins(mut self, dir: &Path) -> Result<Self> {
+        self.plugins = Plugin::load_all(dir)?;
+        Ok(self)
+    }
+
+    /// Fall back to the community's site configs (see `sites`)
+    /// for domains these don't configure.
+    pub fn with_community_sites(mut self, config_dir: &Path) -> Result<Self> {
+        let community = Self::load(&sites::dir(config_dir).join("sites.toml"))
+            .wrap_err("Invalid community site configs, try `stash sites update`")?;
+        for (domain, site) in community.configs {
+            self.configs.entry(domain).or_insert(site);
+        }
+        Ok(self)
+    }
+
+    /// Fall back to the FiveFilters site configs in `dir`
+    /// for domains without a config or plugin.
+    pub fn with_fivefilters(mut self, dir: &Path) -> Result<Self> {
+        self.fivefilters = fivefilters::load(dir)?;
+        Ok(self)
+    }
+
+    /// The domains with site configs.
+    pub fn domains(&self) -> impl Iterator<Item = &str> {
+        self.configs.keys().map(String::as_str)
+    }
+
+    /// The profiles site configs use, with the first domain using each.
+    pub fn profiles(&self) -> BTreeMap<&str, &str> {
+        let mut profiles = BTreeMap::new();
+        for (domain, site) in &self.configs {
+            if let Some(profile) = &site.profile {
+                profiles.entry(profile.as_str()).or_insert(domain.as_str());
+            }
+        }
+        profiles
+    }
+
+    /// The site configs, by their domains.
+    pub fn sites(&self) -> impl Iterator<Item = (&str, &Site)> {
+        self.configs
+            .iter()
+            .map(|(domain, site)| (domain.as_str(), site))
+    }
+
+    /// Fall back to an external extraction service
+    /// when a page can't be extracted locally.
+    pub fn with_remote(mut self, remote: Option<RemoteExtractor>) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Fetch Twitter/X threads through a Nitter instance,
+    /// rather than (tweet by tweet) from Twitter's embeds.
+    pub fn with_nitter(mut self, instance: Option<String>) -> Self {
+        self.nitter = instance;
+        self
+    }
+
+    /// Whether to keep the images of Wikipedia articles, and at what size.
+    pub fn with_wikipedia(mut self, wikipedia: Wikipedia) -> Self {
+        self.wikipedia = wikipedia;
+        self
+    }
+
+    /// Tune how automatic extraction finds bylines and dates (see `byline`),
+    /// for sites that don't in `sites.toml`.
+    pub fn with_byline(mut self, byline: Byline) -> Self {
+        self.byline = byline;
+        self
+    }
+
+    /// Render pages that automatic extraction gets fewer than `min_words`
+    /// words from with this command too, keeping whichever has more
+    /// (see `headless`); 0 never does.
+    pub fn with_headless(mut self, command: Vec<String>, min_words: usize) -> Self {
+        self.headless = command;
+        self.thin_words = min_words;
+        self
+    }
+
+    /// Extract pages from the ones fetched before (see `pages`),
+    /// failing for those that weren't, rather than going online.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Refuse pages larger than this many bytes.
+    pub fn with_max_body_size(mut self, size: u64) -> Self {
+        self.max_body_size = Some(size);
+        self
+    }
+
+    pub fn fetch_article(&self, url: &str, progress: &Progress) -> Result<Article> {
+        let url = &normalize_url(url);
+        let mut article = self.fetch(url, progress)?;
+        article.images = self.images(url);
+        article.profile = self.profile(url);
+        Ok(article)
+    }
+
+    fn fetch(&self, url: &str, progress: &Progress) -> Result<Article> {
+        blocklist::check(url)?;
+        let method = self.method(url)?;
+        let max_size = self.max_body_size.unwrap_or(MAX_BODY_SIZE);
+
+        logging::emit(Event::FetchStarted { url });
+        if self.offline {
+            let Some((kind, body)) = pages::load(url).wrap_err_with(|| fetch_error(url))? else {
+                return Err(
+                    eyre!("It hasn't been fetched before, so can't be stashed offline")
+                        .wrap_err(fetch_error(url)),
+                );
+            };
+            return match kind {
+                ContentKind::Html => self.extract(&method, url, &body),
+                ContentKind::Text => Ok(text_article(url, &body)),
+            };
+        }
+        if let Method::Source(source) = method {
+            return source.fetch(&Url::parse(url)?, self, progress);
+        }
+        for ua in user_agents::ordered(url) {
+            let download = throttle::download();
+            let bar = progress.download(&format!("{url} [{ua}]"), None);
+            let resp = ureq::get(url).set("User-Agent", ua).call();
+            bar.finish_and_clear();
+            let error = match resp {
+                Err(ureq::Error::Status(code, resp)) => {
+                    let err = format!("{code} {:?}", resp.status_text());
+                    let body = read_body(resp, url, max_size, &Progress::hidden())
+                        .wrap_err_with(|| fetch_error(url))?;
+                    match write_error_log(&body) {
+                        Some(log_path) => format!(
+                            "{}\nResponse content written to `{}`.",
+                            err,
+                            log_path.display()
+                        ),
+                        None => err,
+                    }
+                }
+                Err(err) => err.to_string(),
+                Ok(resp) => {
+                    logging::emit(Event::UserAgentTried {
+                        url,
+                        user_agent: ua,
+                        error: None,
+                    });
+                    user_agents::worked(url, ua);
+                    let kind = content_kind(resp.header("Content-Type"), url)?;
+                    let body = read_body(resp, &format!("{url} [{ua}]"), max_size, progress)
+                        .wrap_err_with(|| fetch_error(url))?;
+                    drop(download);
+                    pages::store(url, &kind, &body);
+                    return match kind {
+                        ContentKind::Html => self.extract_fetched(&method, url, &body),
+                        ContentKind::Text => Ok(text_article(url, &body)),
+                    };
+                }
+            };
+            logging::emit(Event::UserAgentTried {
+                url,
+                user_agent: ua,
+                error: Some(error),
+            });
+        }
+
+        Err(eyre!("All user-agents failed.").wrap_err(fetch_error(url)))
+    }
+
+    /// Extract an article from HTML that's already been fetched,
+    /// e.g. a page as captured by a browser.
+    pub fn extract_article(&self, url: &str, html: &str) -> Result<Article> {
+        let url = &normalize_url(url);
+        let mut article = self.extract(&self.method(url)?, url, html)?;
+        article.images = self.images(url);
+        article.profile = self.profile(url);
+        Ok(article)
+    }
+
+    /// Extract an article from a page saved by a browser (see `saved`),
+    /// keeping the page as if it had been fetched.
+    pub fn extract_saved(&self, path: &Path) -> Result<Article> {
+        let page = saved::load(path)?;
+        let article = self.extract_article(&page.url, &page.html)?;
+        pages::store(&article.url, &ContentKind::Html, &page.html);
+        Ok(article)
+    }
+
+    /// The `[images]` settings of a URL's site config.
+    fn images(&self, url: &str) -> ImageOverrides {
+        self.site(url).map(|site| site.images).unwrap_or_default()
+    }
+
+    /// The profile of a URL's site config, if it has one.
+    fn profile(&self, url: &str) -> Option<String> {
+        self.site(url).and_then(|site| site.profile.clone())
+    }
+
+    /// A URL's site config, if it has one.
+    fn site(&self, url: &str) -> Option<&Site> {
+        Url::parse(url)
+            .ok()
+            .as_ref()
+            .and_then(Url::host_str)
+            .and_then(|domain| self.configs.get(domain))
+    }
+
+    /// Extract an article, with its comments if its site config keeps them,
+    /// and (if it's extracted automatically) its byline as configured.
+    fn extract(&self, method: &Method, url: &str, html: &str) -> Result<Article> {
+        let mut article = self.extract_with_fallbacks(method, url, html)?;
+        let site = self.site(url);
+        if let Some(settings) = site.and_then(|site| site.comments.as_ref()) {
+            article.comments = comments::find(settings, url, html, self.offline);
+        }
+        if article.extraction == "auto" {
+            let settings = site.and_then(|site| site.byline.as_ref());
+            byline::apply(settings.unwrap_or(&self.byline), html, &mut article);
+        }
+        Ok(article)
+    }
+
+    /// Extract a page that was just fetched; if it's extracted automatically
+    /// to too few words, render it headlessly and keep whichever has more,
+    /// keeping the rendered page in `pages` if that's the one.
+    fn extract_fetched(&self, method: &Method, url: &str, html: &str) -> Result<Article> {
+        let article = self.extract(method, url, html)?;
+        let words = article.word_count();
+        if self.headless.is_empty() || article.extraction != "auto" || words >= self.thin_words {
+            return Ok(article);
+        }
+        let rendered = headless::render(&self.headless, url)
+            .and_then(|rendered| Ok((self.extract(method, url, &rendered)?, rendered)));
+        let (mut rendered, page) = match rendered {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                logging::warn(format!("Couldn't render {url} headlessly: {err:#}"));
+                return Ok(article);
+            }
+        };
+        let rendered_words = rendered.word_count();
+        let won = rendered_words > words;
+        logging::emit(Event::Rendered {
+            url,
+            fetched_words: words,
+            rendered_words,
+            kept: if won { "rendered" } else { "fetched" },
+        });
+        if !won {
+            return Ok(article);
+        }
+        pages::store(url, &ContentKind::Html, &page);
+        rendered.extraction = "auto (headless)".to_string();
+        Ok(rendered)
+    }
+
+    /// Extract with the chosen method; if that fails and there's a remote
+    /// extractor to fall back to, try automatic extraction (unless that's
+    /// what failed) and then the remote extractor.
+    fn extract_with_fallbacks(&self, method: &Method, url: &str, html: &str) -> Result<Article> {
+        let err = match method.extract(url, html) {
+            Ok(article) => return Ok(article),
+            Err(err) => err,
+        };
+        let Some(remote) = self.remote.as_ref().filter(|_| !self.offline) else {
+            return Err(err);
+        };
+        let mut failed = vec![Warning::Fallback {
+            method: method.name(),
+            error: format!("{err:#}"),
+        }];
+        // The warnings of a fallback's article, after why it was fallen back to.
+        let fell_back = |mut article: Article, failed: Vec<Warning>| {
+            article.warnings.splice(0..0, failed);
+            article
+        };
+        let auto = Method::Site(&ExtractionMethod::Auto);
+        if method.name() != auto.name() {
+            match auto.extract(url, html) {
+                Ok(article) => return Ok(fell_back(article, failed)),
+                Err(err) => failed.push(Warning::Fallback {
+                    method: auto.name(),
+                    error: format!("{err:#}"),
+                }),
+            }
+        }
+        logging::warn(format!("Trying the remote extractor for {url}"));
+        match remote::extract(remote, url, Some(html)) {
+            Ok(article) => Ok(fell_back(article, failed)),
+            Err(remote_err) => {
+                for warning in &failed[1..] {
+                    logging::warn(warning.to_string());
+                }
+                logging::warn(format!("{remote_err:#}"));
+                Err(err)
+            }
+        }
+    }
+
+    /// Pick how to extract an article: its site config,
+    /// else a plugin for its domain, else a source that's fetched
+    /// its own way (e.g. YouTube), else automatically.
+    fn method(&self, url: &str) -> Result<Method<'_>> {
+        let url_parsed = Url::parse(url)?;
+        let domain = url_parsed.host_str();
+        let config = domain
+            .and_then(|domain| self.configs.get(domain))
+            .map(|site| &site.method);
+        let plugin = domain
+            .filter(|_| config.is_none())
+            .and_then(|domain| self.plugins.iter().find(|plugin| plugin.handles(domain)));
+        let method = match (config, plugin, sources::find(&url_parsed)) {
+            (Some(config), _, _) => Method::Site(config),
+            (None, Some(plugin), _) => Method::Plugin(plugin),
+            (None, None, Some(source)) => Method::Source(source),
+            (None, None, None) => Method::Site(
+                domain
+                    .and_then(|domain| fivefilters::find(&self.fivefilters, domain))
+                    .unwrap_or(&ExtractionMethod::Auto),
+            ),
+        };
+        logging::emit(Event::ExtractionMethod {
+            url,
+            domain,
+            method: &method.name(),
+        });
+        Ok(method)
+    }
+}
+
+enum Method<'a> {
+    Site(&'a ExtractionMethod),
+    Plugin(&'a Plugin),
+    /// Fetched its own way, rather than extracted from the page.
+    Source(&'static Source),
+}
+impl Method<'_> {
+    fn name(&self) -> String {
+        match self {
+            Self::Site(method) => method.name().to_string(),
+            Self::Plugin(plugin) => format!("plugin:{}", plugin.name),
+            Self::Source(source) => source.name.to_string(),
+        }
+    }
+
+    fn extract(&self, url: &str, html: &str) -> Result<Article> {
+        let mut article = match self {
+            // Newsletter platforms are recognized by their pages. A source's
+            // page that's already been fetched is extracted like any other.
+            Self::Site(ExtractionMethod::Auto) | Self::Source(_) => {
+                match newsletter::extract(url, html) {
+                    Some(article) => Ok(article),
+                    None => ExtractionMethod::Auto.extract(url, html),
+                }
+            }
+            Self::Site(method) => method.extract(url, html),
+            Self::Plugin(plugin) => plugin.extract(url, html),
+        }
+        .map_err(|err| match StashError::of(&err) {
+            Some(_) => err,
+            None => err.wrap_err(StashError::Extraction {
+                field: None,
+                selector: None,
+            }),
+        })?;
+        if article.extraction.is_empty() {
+            article.extraction = self.name();
+        }
+        article.fetched_at = Utc::now();
+        Ok(article)
+    }
+}
+
+enum ContentKind {
+    Html,
+    Text,
+}
+
+/// What to make of a response, going by its `Content-Type`: pages are
+/// extracted as usual and plain text is wrapped as is, but anything else
+/// (JSON, images, PDFs...) would only make for a nonsense book.
+/// Responses without a type are assumed to be pages.
+fn content_kind(content_type: Option<&str>, url: &str) -> Result<ContentKind> {
+    let Some(content_type) = content_type else {
+        return Ok(ContentKind::Html);
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match mime.as_str() {
+        "" | "text/html" | "application/xhtml+xml" => Ok(ContentKind::Html),
+        "text/plain" => Ok(ContentKind::Text),
+        _ => Err(eyre!(StashError::UnsupportedContent {
+            url: url.to_string(),
+            content_type: mime,
+        })),
+    }
+}
+
+/// Make an article of a plain text file, with a paragraph per block of
+/// lines. A short first block is taken as the title, else the filename.
+fn text_article(url: &str, text: &str) -> Article {
+    let text = text.replace("\r\n", "\n");
+    let mut paras: Vec<String> = text
+        .split("\n\n")
+        .map(|para| para.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|para| !para.is_empty())
+        .collect();
+    let title = match paras.first() {
+        Some(first) if paras.len() > 1 && first.chars().count() <= 120 => paras.remove(0),
+        _ => Url::parse(url)
+            .ok()
+            .and_then(|url| {
+                url.path_segments()?
+                    .next_back()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| url.to_string()),
+    };
+    Article {
+        url: url.to_string(),
+        title,
+        content: paras
+            .iter()
+            .map(|para| format!("<p>{}</p>\n", xhtml::escape_text(para)))
+            .collect(),
+        extraction: "text".into(),
+        fetched_at: Utc::now(),
+        ..Default::default()
+    }
+}
+
+/// An estimate of how long some text takes to read,
+/// e.g. "~18 min read", at `speed` words per minute.
+pub fn reading_time(words: usize, speed: usize) -> String {
+    let minutes = (words as f64 / speed.max(1) as f64).round().max(1.0);
+    format!("~{minutes} min read")
+}
+
+/// Normalize a URL so the same page always has the same URL: without
+/// tracking parameters, its fragment, or a default port, and with its host
+/// lowercased. Anything that isn't a URL is left as is.
+pub fn normalize_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url.trim()) else {
+        return url.to_string();
+    };
+    // Parsing already drops default ports and lowercases hosts.
+    // Fragments are kept for hash-bang routes, which are separate pages.
+    if parsed
+        .fragment()
+        .is_some_and(|fragment| !fragment.starts_with('!'))
+    {
+        parsed.set_fragment(None);
+    }
+    if let Some(query) = parsed.query() {
+        // Filtering the raw pairs keeps the rest of the query encoded as it was.
+        let query = query
+            .split('&')
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or_default();
+                !name.is_empty() && !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&name)
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        parsed.set_query(
+            Some(&query)
+                .filter(|query| !query.is_empty())
+                .map(|q| q.as_str()),
+        );
+    }
+    parsed.into()
+}
+
+fn fetch_error(url: &str) -> StashError {
+    StashError::Fetch {
+        url: url.to_string(),
+    }
+}
+
+/// Keep an error response's body around for debugging,
+/// returning where it was written (if it could be).
+fn write_error_log(body: &str) -> Option<PathBuf> {
+    let dir = dirs::cache_dir()?;
+    fs_err::create_dir_all(&dir).ok()?;
+    let path = dir.join("stash-error.log");
+    crypt::write(&path, body).ok()?;
+    Some(path)
+}
+
+/// Read a response body, showing download progress. Bodies over
+/// `max_size` bytes are refused rather than truncated.
+fn read_body(
+    resp: ureq::Response,
+    label: &str,
+    max_size: u64,
+    progress: &Progress,
+) -> Result<String> {
+    let too_large = || {
+        eyre!(
+            "The page is over the {} MB limit (`max_page_size` in config.toml)",
+            max_size / 1024 / 1024
+        )
+    };
+    let len = resp
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok());
+    if len.is_some_and(|len| len > max_size) {
+        return Err(too_large());
+    }
+    let content_type = resp.header("Content-Type").map(str::to_string);
+    let bar = progress.download(label, len);
+    // Reading a byte past the limit tells a body that's too large
+    // from one that's exactly the limit.
+    let url = resp.get_url().to_string();
+    let mut body = vec![];
+    let read = bar
+        .wrap_read(throttle::reader(&url, resp.into_reader()).take(max_size + 1))
+        .read_to_end(&mut body);
+    bar.finish_and_clear();
+    read?;
+    if body.len() as u64 > max_size {
+        return Err(too_large());
+    }
+    Ok(decode(&body, content_type.as_deref()))
+}
+
+/// Decode a page according to its byte order mark, the charset in its
+/// `Content-Type` header or `<meta>` tags, or failing those, a guess.
+fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = declared_encoding(body, content_type).unwrap_or_else(|| {
+        let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+        detector.feed(body, true);
+        detector.guess(None, Utf8Detection::Allow)
+    });
+    // This prefers a BOM over the encoding given, as it should.
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+/// The encoding a page declares in its `Content-Type` header or,
+/// as browsers do, `<meta>` tags near the start.
+fn declared_encoding(body: &[u8], content_type: Option<&str>) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(1024)]).to_ascii_lowercase();
+    content_type
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+        .and_then(charset)
+        .or_else(|| charset(&head))
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+}
+
+/// The value of the first `charset=` in a (lowercased) header or page.
+fn charset(s: &str) -> Option<String> {
+    let start = s.find("charset")? + "charset".len();
+    let value = s[start..].trim_start().strip_prefix('=')?.trim_start();
+    let value = value.trim_start_matches(['"', '\'']);
+    let end = value
+        .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '>' || c.is_whitespace())
+        .unwrap_or(value.len());
+    Some(value[..end].to_string()).filter(|value| !value.is_empty())
+}
@@ -0,0 +1,310 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    Article,
+    annotations::{self, Annotations, Highlight},
+    config, crypt,
+    links::LinkCheck,
+};
+
+/// A record of a stashed article.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub url: String,
+    pub title: String,
+    pub authors: String,
+    pub published_at: String,
+    pub stashed_at: DateTime<Utc>,
+
+    /// Where the generated file was written.
+    pub path: PathBuf,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub summary: String,
+
+    /// Its length, for estimating reading time.
+    #[serde(default)]
+    pub words: usize,
+
+    /// See `Article::content_hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+
+    /// The URL this is a copy of, if the same article was stashed
+    /// under another URL first; `path` is then that article's book.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same_as: Option<String>,
+
+    /// The book's `dc:identifier`, to match it to e-readers' annotations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+
+    /// Highlights made on the book, see `stash annotations import`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<Highlight>,
+
+    /// Why it was stashed, see `Article::note`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+
+    /// Its tags (keywords), as with `[[routes]]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// What was last found at its URL, see `stash check-links`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<LinkCheck>,
+}
+impl Entry {
+    pub fn domain(&self) -> Option<String> {
+        Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|domain| domain.to_string()))
+    }
+
+    /// Its note, cut short (at a word) to fit on a line with it,
+    /// e.g. in search results; `None` if it has none.
+    pub fn short_note(&self) -> Option<String> {
+        const MAX_CHARS: usize = 60;
+        let note = self.note.split_whitespace().collect::<Vec<_>>().join(" ");
+        if note.is_empty() {
+            return None;
+        }
+        if note.chars().count() <= MAX_CHARS {
+            return Some(note);
+        }
+        let mut short = String::new();
+        for word in note.split(' ') {
+            if short.chars().count() + word.chars().count() + 1 > MAX_CHARS {
+                break;
+            }
+            if !short.is_empty() {
+                short.push(' ');
+            }
+            short.push_str(word);
+        }
+        if short.is_empty() {
+            short = note.chars().take(MAX_CHARS).collect();
+        }
+        Some(format!("{short}…"))
+    }
+
+    /// Add highlights that aren't already recorded, returning how many were new.
+    pub fn annotate(&mut self, highlights: Vec<Highlight>) -> usize {
+        let before = self.highlights.len();
+        for highlight in highlights {
+            if !self.highlights.contains(&highlight) {
+                self.highlights.push(highlight);
+            }
+        }
+        self.highlights.len() - before
+    }
+
+    /// Where the capture's text is kept, see `Article::plain_text`.
+    pub fn text_path(&self) -> PathBuf {
+        let url = hex::encode(Sha256::digest(self.url.as_bytes()));
+        config::data_dir()
+            .join("captures")
+            .join(&url[..16])
+            .join(format!("{}.txt", self.stashed_at.timestamp_millis()))
+    }
+}
+
+/// The index of everything that's been stashed,
+/// kept as `library.json` in the data dir.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub struct Library {
+    pub entries: Vec<Entry>,
+}
+impl Library {
+    pub fn path() -> PathBuf {
+        config::data_dir().join("library.json")
+    }
+
+    pub fn load() -> Result<Self> {
+        match crypt::read_to_string(&Self::path()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Record what was found at some URLs, for all their captures.
+    pub fn record_links(checks: &[(String, LinkCheck)]) -> Result<()> {
+        let _lock = config::lock_data();
+        let mut library = Self::load()?;
+        for entry in &mut library.entries {
+            if let Some((_, check)) = checks.iter().find(|(url, _)| *url == entry.url) {
+                entry.link = Some(check.clone());
+            }
+        }
+        library.save()
+    }
+
+    /// Whether the file at `path` is a capture of `url`.
+    pub fn has_capture(url: &str, path: &Path) -> bool {
+        path.exists()
+            && Self::load().is_ok_and(|library| {
+                library
+                    .entries
+                    .iter()
+                    .any(|entry| entry.url == url && entry.path == path)
+            })
+    }
+
+    /// Every capture of a URL, oldest first.
+    pub fn captures(&self, url: &str) -> Vec<&Entry> {
+        let mut captures: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.url == url)
+            .collect();
+        captures.sort_by_key(|entry| entry.stashed_at);
+        captures
+    }
+
+    /// The entries matching a search, best first: those with every word of
+    /// the query in their title, authors, note, URL, or summary, ranked by where
+    /// the words are (a title's count most), then by how recently
+    /// they were stashed. Copies are left out, as their originals match.
+    pub fn search(&self, query: &str) -> Vec<&Entry> {
+        let words: Vec<_> = query.split_whitespace().map(str::to_lowercase).collect();
+        let mut found: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.same_as.is_none())
+            .filter_map(|entry| {
+                let fields = [
+                    (&entry.title, 4),
+                    (&entry.authors, 2),
+                    (&entry.note, 2),
+                    (&entry.url, 1),
+                    (&entry.summary, 1),
+                ]
+                .map(|(field, weight)| (field.to_lowercase(), weight));
+                let mut score = 0;
+                for word in &words {
+                    let weights = fields
+                        .iter()
+                        .filter(|(field, _)| field.contains(word.as_str()))
+                        .map(|(_, weight)| weight);
+                    match weights.max() {
+                        Some(weight) => score += weight,
+                        None => return None,
+                    }
+                }
+                Some((score, entry))
+            })
+            .collect();
+        found.sort_by_key(|(score, entry)| Reverse((*score, entry.stashed_at)));
+        // Captures that overwrote the same book are one match.
+        let mut books = HashSet::new();
+        found
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| books.insert(&entry.path))
+            .collect()
+    }
+
+    /// The latest article stashed with this content under another URL
+    /// whose book is still there.
+    pub fn copy_of(&self, article: &Article) -> Option<&Entry> {
+        let hash = article.content_hash()?;
+        self.entries.iter().rev().find(|entry| {
+            entry.url != article.url
+                && entry.same_as.is_none()
+                && entry.content_hash.as_ref() == Some(&hash)
+                && entry.path.exists()
+        })
+    }
+
+    /// The stashed book some annotations were made on: by its identifier,
+    /// or failing that (e.g. for books stashed before identifiers were
+    /// recorded), the filename it has on the device.
+    pub fn book_of(&mut self, annotations: &Annotations) -> Option<&mut Entry> {
+        let by_identifier = self.entries.iter().rposition(|entry| {
+            entry.same_as.is_none()
+                && entry
+                    .identifier
+                    .as_ref()
+                    .is_some_and(|id| annotations.is_of(id))
+        });
+        let by_filename = || {
+            let name = Path::new(annotations.book_path.as_deref()?).file_name()?;
+            self.entries
+                .iter()
+                .rposition(|entry| entry.same_as.is_none() && entry.path.file_name() == Some(name))
+        };
+        let i = by_identifier.or_else(by_filename)?;
+        self.entries.get_mut(i)
+    }
+
+    /// Add a newly stashed article to the index,
+    /// keeping its text to compare later captures with.
+    pub fn record(article: &Article, path: &Path) -> Result<()> {
+        Self::add(article, path, None)
+    }
+
+    /// Record an article as a copy of one already stashed under another URL,
+    /// linked to its book rather than stored twice.
+    pub fn link(article: &Article, original: &Entry) -> Result<()> {
+        let linked = Self::load()?
+            .entries
+            .iter()
+            .any(|entry| entry.url == article.url && entry.same_as.as_ref() == Some(&original.url));
+        if linked {
+            return Ok(());
+        }
+        Self::add(article, &original.path, Some(original.url.clone()))
+    }
+
+    fn add(article: &Article, path: &Path, same_as: Option<String>) -> Result<()> {
+        let _lock = config::lock_data();
+        let mut library = Self::load()?;
+        let entry = Entry {
+            url: article.url.clone(),
+            title: article.title.clone(),
+            authors: article.authors.clone(),
+            published_at: article.published_at.clone(),
+            stashed_at: Utc::now(),
+            path: path.to_path_buf(),
+            summary: article.summary.clone(),
+            words: article.word_count(),
+            content_hash: article.content_hash(),
+            same_as,
+            identifier: annotations::identifier(path).ok(),
+            highlights: vec![],
+            note: article.note.clone(),
+            tags: article.keywords.clone(),
+            link: None,
+        };
+        let text_path = entry.text_path();
+        if let Some(parent) = text_path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&text_path, article.plain_text())?;
+        library.entries.push(entry);
+        library.save()
+    }
+}
@@ -0,0 +1,94 @@
+//! Whether stashed articles' pages are still there, for
+//! `stash check-links`: what's found is kept with their library
+//! entries (`Entry::link`).
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{blocklist, normalize_url, user_agents};
+
+/// What was found at an article's URL, and when.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkCheck {
+    pub checked_at: DateTime<Utc>,
+
+    #[serde(flatten)]
+    pub status: LinkStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LinkStatus {
+    Ok,
+
+    /// It redirects elsewhere, e.g. the article moved.
+    Redirected {
+        to: String,
+    },
+
+    /// It's gone, or fails to load.
+    Dead {
+        error: String,
+    },
+}
+
+/// Check a URL with a HEAD request (or a GET, for servers that refuse
+/// those), following redirects, and trying the user agents in turn as
+/// when fetching pages. URLs on blocklisted domains aren't checked.
+pub fn check(url: &str) -> Result<LinkCheck> {
+    blocklist::check(url)?;
+    let mut status = None;
+    for ua in user_agents::ordered(url) {
+        let (found, refused) = request(url, ua);
+        status = Some(found);
+        // Only some sites refuse some user agents.
+        if !refused {
+            break;
+        }
+    }
+    Ok(LinkCheck {
+        checked_at: Utc::now(),
+        status: status.expect("A user agent"),
+    })
+}
+
+/// What's at a URL, and whether it was an HTTP error,
+/// which might not be with another user agent.
+fn request(url: &str, ua: &str) -> (LinkStatus, bool) {
+    let request = |method: &str| {
+        ureq::request(method, url)
+            .timeout(Duration::from_secs(30))
+            .set("User-Agent", ua)
+    };
+    let resp = match request("HEAD").call() {
+        Err(ureq::Error::Status(405 | 501, _)) => request("GET").call(),
+        resp => resp,
+    };
+    match resp {
+        Ok(resp) if normalize_url(resp.get_url()) != normalize_url(url) => (
+            LinkStatus::Redirected {
+                to: resp.get_url().to_string(),
+            },
+            false,
+        ),
+        Ok(_) => (LinkStatus::Ok, false),
+        Err(ureq::Error::Status(code, resp)) => (
+            LinkStatus::Dead {
+                error: format!("{code} {}", resp.status_text()),
+            },
+            true,
+        ),
+        Err(ureq::Error::Transport(err)) => (
+            LinkStatus::Dead {
+                error: match err.message() {
+                    Some(message) => format!("{}: {message}", err.kind()),
+                    None => err.kind().to_string(),
+                },
+            },
+            false,
+        ),
+    }
+}
@@ -0,0 +1,157 @@
+use std::{path::Path, str::FromStr, sync::OnceLock};
+
+use color_eyre::eyre::{Error, bail};
+use serde::Serialize;
+
+use crate::warning::Warning;
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// How diagnostics are written to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable messages.
+    #[default]
+    Text,
+
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown log format `{s}`, expected `text` or `json`."),
+        }
+    }
+}
+
+/// Set the log format for the rest of the run.
+pub fn init(format: LogFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> LogFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    FetchStarted {
+        url: &'a str,
+    },
+    UserAgentTried {
+        url: &'a str,
+        user_agent: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    ExtractionMethod {
+        url: &'a str,
+        domain: Option<&'a str>,
+        method: &'a str,
+    },
+    OutputWritten {
+        url: &'a str,
+        path: &'a Path,
+    },
+    Failed {
+        url: &'a str,
+        error: String,
+    },
+    Warning {
+        message: String,
+    },
+
+    /// A thinly extracted page was rendered headlessly (see `headless`),
+    /// and which of the two was kept.
+    Rendered {
+        url: &'a str,
+        fetched_words: usize,
+        rendered_words: usize,
+        kept: &'a str,
+    },
+
+    /// What may have gone wrong extracting an article, all together.
+    Warnings {
+        url: &'a str,
+        warnings: &'a [Warning],
+    },
+}
+impl Event<'_> {
+    /// The human-readable version of the event, if it's worth showing.
+    fn text(&self) -> Option<String> {
+        match self {
+            // Failures are reported by the caller.
+            Self::FetchStarted { .. } | Self::OutputWritten { .. } | Self::Failed { .. } => None,
+            Self::UserAgentTried {
+                user_agent, error, ..
+            } => error.as_ref().map(|err| format!("[{user_agent}]: {err}")),
+            Self::ExtractionMethod { domain, .. } => {
+                domain.map(|domain| format!("Domain: {domain}"))
+            }
+            Self::Warning { message } => Some(format!("WARN: {message}")),
+            Self::Rendered {
+                url,
+                fetched_words,
+                rendered_words,
+                kept,
+            } => Some(format!(
+                "Rendered {url} headlessly: {rendered_words} words against \
+                 {fetched_words} as fetched, so kept the {kept} page"
+            )),
+            Self::Warnings { url, warnings } => (!warnings.is_empty()).then(|| {
+                warnings
+                    .iter()
+                    .map(|warning| format!("WARN: {url}: {warning}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+        }
+    }
+}
+
+/// Log an event to stderr in the configured format.
+pub fn emit(event: Event) {
+    match format() {
+        LogFormat::Text => {
+            if let Some(text) = event.text() {
+                eprintln!("{text}");
+            }
+        }
+        LogFormat::Json => {
+            #[derive(Serialize)]
+            struct Line<'a> {
+                timestamp: String,
+                #[serde(flatten)]
+                event: Event<'a>,
+            }
+            let line = Line {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                event,
+            };
+            if let Ok(json) = serde_json::to_string(&line) {
+                eprintln!("{json}");
+            }
+        }
+    }
+}
+
+/// Shorthand for emitting a warning.
+pub fn warn(message: impl Into<String>) {
+    emit(Event::Warning {
+        message: message.into(),
+    });
+}
+
+/// Shorthand for emitting a failure for a URL.
+pub fn failed(url: &str, err: &color_eyre::Report) {
+    emit(Event::Failed {
+        url,
+        error: format!("{err:#}"),
+    });
+}
@@ -0,0 +1,294 @@
+//! Reading back the EPUBs stash has made, to merge them into one
+//! (`stash merge`). Each book's articles come back with their content
+//! as it was written, images inlined as `data:` URIs so they're packaged
+//! again, and their metadata from the package document and colophon.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::Path,
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use quick_xml::{Reader, XmlVersion, events::Event};
+use scraper::{ElementRef, Html, Selector};
+use zip::ZipArchive;
+
+use crate::{
+    Article,
+    annotations::{attributes, entry, first, predefined_entity},
+    date,
+};
+
+/// Read the articles of an EPUB made by stash: one for a single article's
+/// book, or one per chapter for a digest.
+pub fn articles(path: &Path) -> Result<Vec<Article>> {
+    read(path).wrap_err_with(|| format!("Couldn't read {}", path.display()))
+}
+
+fn read(path: &Path) -> Result<Vec<Article>> {
+    let mut zip = ZipArchive::new(fs_err::File::open(path)?)?;
+    let container = entry(&mut zip, "META-INF/container.xml")?;
+    let opf_path = first(&container, |name, attrs| {
+        (name == "rootfile").then(|| attrs.get("full-path").cloned())?
+    })
+    .ok_or_else(|| eyre!("There's no package document"))?;
+    let package = Package::parse(&entry(&mut zip, &opf_path)?)?;
+    let dir = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let within = |href: &str| match dir {
+        "" => href.to_string(),
+        dir => format!("{dir}/{href}"),
+    };
+
+    // Images by the paths they're referred to by, as `data:` URIs.
+    let mut images = vec![];
+    for (href, mime) in package.manifest.values() {
+        if mime.starts_with("image/") && !href.starts_with("cover.") {
+            let mut data = vec![];
+            zip.by_name(&within(href))?.read_to_end(&mut data)?;
+            let uri = format!("data:{mime};base64,{}", BASE64_STANDARD.encode(data));
+            images.push((format!("src=\"{href}\""), format!("src=\"{uri}\"")));
+        }
+    }
+    let inline = |body: String| {
+        images
+            .iter()
+            .fold(body, |body, (src, uri)| body.replace(src, uri))
+    };
+
+    let mut colophon = vec![];
+    let mut articles: Vec<Article> = vec![];
+    for idref in &package.spine {
+        let Some((href, _)) = package.manifest.get(idref) else {
+            continue;
+        };
+        let doc = Html::parse_document(&entry(&mut zip, &within(href))?);
+        match href.as_str() {
+            "main.xhtml" => {
+                let mut article = package.article();
+                article.title = title(&doc).unwrap_or(article.title);
+                article.content = inline(body(&doc));
+                articles.push(article);
+            }
+            // The rest of a long article, see `xhtml::split`.
+            href if href.starts_with("main-") => {
+                if let Some(article) = articles.last_mut() {
+                    article.content.push_str(&inline(body(&doc)));
+                }
+            }
+            // Appended as it would be to a digest's chapter.
+            "comments.xhtml" | "discussion.xhtml" => {
+                if let Some(article) = articles.last_mut() {
+                    let body = body(&doc)
+                        .replacen("<h1", "<h2", 1)
+                        .replacen("</h1>", "</h2>", 1);
+                    article.content.push_str(&format!("<hr/>{}", inline(body)));
+                }
+            }
+            "colophon.xhtml" => colophon = captures(&doc),
+            href if href.starts_with("chapter-") => {
+                let mut article = chapter(&doc);
+                article.content = inline(article.content);
+                article.lang = package.first("language");
+                articles.push(article);
+            }
+            _ => {}
+        }
+    }
+    if articles.is_empty() {
+        bail!("It has no articles; is it an EPUB made by stash?");
+    }
+    for (article, (url, fetched_at, extraction)) in articles.iter_mut().zip(colophon) {
+        if article.url.is_empty() {
+            article.url = url;
+        }
+        if let Some(fetched_at) = fetched_at {
+            article.fetched_at = fetched_at;
+        }
+        article.extraction = extraction;
+    }
+    Ok(articles)
+}
+
+/// What's needed of an EPUB's package document.
+#[derive(Default)]
+struct Package {
+    /// Metadata values by element name (e.g. `title`),
+    /// or by property for `<meta>`s (e.g. `dcterms:source`).
+    metadata: HashMap<String, Vec<String>>,
+
+    /// Items' hrefs and media types, by ID.
+    manifest: BTreeMap<String, (String, String)>,
+
+    /// The IDs of the content documents in reading order.
+    spine: Vec<String>,
+}
+impl Package {
+    fn parse(opf: &str) -> Result<Self> {
+        let mut package = Self::default();
+        let mut reader = Reader::from_str(opf);
+        let mut key: Option<String> = None;
+        let mut text = String::new();
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) | Event::Empty(start) => {
+                    let name = start.local_name();
+                    let name: &str = AsRef::<str>::as_ref(&name);
+                    let mut attrs = attributes(&start)?;
+                    let mut attr = |name: &str| attrs.remove(name).unwrap_or_default();
+                    match name {
+                        "item" => {
+                            let item = (attr("href"), attr("media-type"));
+                            package.manifest.insert(attr("id"), item);
+                        }
+                        "itemref" => package.spine.push(attr("idref")),
+                        "meta" => key = Some(attr("property")).filter(|key| !key.is_empty()),
+                        name => key = Some(name.to_string()),
+                    }
+                    text.clear();
+                }
+                Event::Text(content) => {
+                    text.push_str(&content.xml_content(XmlVersion::Implicit1_0))
+                }
+                Event::CData(content) => text.push_str(content.as_ref()),
+                Event::GeneralRef(entity) => {
+                    let name: &str = entity.as_ref();
+                    if let Some(c) = entity.resolve_char_ref().ok().flatten() {
+                        text.push(c);
+                    } else if let Some(c) = predefined_entity(name) {
+                        text.push_str(c);
+                    }
+                }
+                Event::End(_) => {
+                    if let Some(key) = key.take() {
+                        let value = text.trim().to_string();
+                        package.metadata.entry(key).or_default().push(value);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(package)
+    }
+
+    fn first(&self, key: &str) -> String {
+        self.metadata
+            .get(key)
+            .and_then(|values| values.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The article of a single article's book, without its content.
+    fn article(&self) -> Article {
+        Article {
+            url: self.first("dcterms:source"),
+            title: self.first("title"),
+            authors: self.first("creator"),
+            published_at: self.first("date"),
+            lang: self.first("language"),
+            site_name: self.first("dcterms:publisher"),
+            keywords: self.metadata.get("subject").cloned().unwrap_or_default(),
+            rights: self.first("rights"),
+            fetched_at: Utc::now(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A content document's `<title>`.
+fn title(doc: &Html) -> Option<String> {
+    let sel = Selector::parse("head > title").expect("Valid selector");
+    doc.select(&sel)
+        .next()
+        .map(|el| el.text().collect::<String>().trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+/// A content document's body, without the section stash wraps it in.
+fn body(doc: &Html) -> String {
+    let section = Selector::parse("body > section").expect("Valid selector");
+    let body = Selector::parse("body").expect("Valid selector");
+    doc.select(&section)
+        .next()
+        .or_else(|| doc.select(&body).next())
+        .map(|el| el.inner_html().trim().to_string())
+        .unwrap_or_default()
+}
+
+/// A digest's chapter, without the title and byline it starts with,
+/// which are added again when it's merged.
+fn chapter(doc: &Html) -> Article {
+    let mut article = Article {
+        title: title(doc).unwrap_or_default(),
+        fetched_at: Utc::now(),
+        ..Default::default()
+    };
+    let mut content = body(doc);
+    let header = Selector::parse("section > p.byline").expect("Valid selector");
+    if let Some(byline) = doc.select(&header).next() {
+        // e.g. "Ann Author · 2024-03-05 · <a>example.com</a>", each part optional.
+        let mut text = String::new();
+        for child in byline.children() {
+            match (child.value().as_text(), ElementRef::wrap(child)) {
+                (Some(part), _) => text.push_str(part),
+                (None, Some(link)) if link.value().name() == "a" => {
+                    article.url = link.attr("href").unwrap_or_default().to_string();
+                }
+                _ => {}
+            }
+        }
+        let parts: Vec<_> = text
+            .split(" · ")
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect();
+        match parts.as_slice() {
+            [authors, published_at] => {
+                article.authors = authors.clone();
+                article.published_at = published_at.clone();
+            }
+            [one] if date::parse(one).is_some() => article.published_at = one.clone(),
+            [one] => article.authors = one.clone(),
+            _ => {}
+        }
+        let end = byline.html();
+        if let Some(at) = content.find(&end) {
+            content = content[at + end.len()..].trim_start().to_string();
+        }
+    }
+    article.content = content;
+    article
+}
+
+/// The sources, capture times, and extraction methods
+/// in a book's colophon, in the order of its articles.
+fn captures(doc: &Html) -> Vec<(String, Option<DateTime<Utc>>, String)> {
+    let lists = Selector::parse("dl").expect("Valid selector");
+    let details = Selector::parse("dd").expect("Valid selector");
+    let link = Selector::parse("a[href]").expect("Valid selector");
+    doc.select(&lists)
+        .map(|list| {
+            let dds: Vec<_> = list.select(&details).collect();
+            let url = dds
+                .first()
+                .and_then(|dd| dd.select(&link).next())
+                .and_then(|a| a.attr("href"))
+                .unwrap_or_default()
+                .to_string();
+            let text = |i: usize| {
+                dds.get(i)
+                    .map(|dd| dd.text().collect::<String>())
+                    .unwrap_or_default()
+            };
+            let fetched_at = NaiveDateTime::parse_from_str(&text(1), "%B %d, %Y %H:%M UTC")
+                .ok()
+                .map(|at| at.and_utc());
+            (url, fetched_at, text(2))
+        })
+        .collect()
+}
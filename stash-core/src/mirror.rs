@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Result, eyre};
+use scraper::{Html, Node, Selector};
+use url::Url;
+
+use crate::{
+    Article, ContentKind, USER_AGENTS, blocklist, config::Config, crypt, images, logging, pages,
+    progress::Progress, throttle,
+};
+
+/// Attributes pointing at assets, by the elements that have them.
+const ASSET_ATTRS: &[(&str, &str, Kind)] = &[
+    ("link[rel~=stylesheet][href]", "href", Kind::Stylesheet(0)),
+    ("link[rel~=icon][href]", "href", Kind::Other),
+    ("script[src]", "src", Kind::Script),
+    ("img[src]", "src", Kind::Other),
+    ("input[type=image][src]", "src", Kind::Other),
+    ("source[src]", "src", Kind::Other),
+    ("video[src]", "src", Kind::Other),
+    ("video[poster]", "poster", Kind::Other),
+    ("audio[src]", "src", Kind::Other),
+    ("track[src]", "src", Kind::Other),
+    ("embed[src]", "src", Kind::Other),
+];
+
+/// How deeply stylesheets' `@import`s are followed.
+const MAX_IMPORT_DEPTH: usize = 4;
+
+/// Save the page an article was extracted from as it looked, next to its
+/// book (`title.html`, with its assets in `title_files/`): its stylesheets,
+/// images, fonts, and scripts are downloaded and pointed to locally, and
+/// its links made absolute. For pages whose layout matters (infographics,
+/// interactive pages) and doesn't survive extraction. Failures are only
+/// warned about, as the book's been written regardless. The downloads
+/// are shown in `progress`.
+pub fn add(config: &Config, article: &Article, book: &Path, progress: &Progress) {
+    if !config.mirror {
+        return;
+    }
+    // Articles from sources (e.g. GitHub's API) have no page of their own.
+    let (kind, html) = match pages::load(&article.url) {
+        Ok(Some(page)) => page,
+        Ok(None) => {
+            logging::warn(format!("There's no page to mirror for {}", article.url));
+            return;
+        }
+        Err(err) => {
+            logging::warn(format!("Couldn't mirror the page: {err}"));
+            return;
+        }
+    };
+    let ContentKind::Html = kind else {
+        return;
+    };
+    if let Err(err) = save(&html, &article.url, book, progress) {
+        logging::warn(format!("Couldn't mirror the page: {err:#}"));
+    }
+}
+
+fn save(html: &str, url: &str, book: &Path, progress: &Progress) -> Result<PathBuf> {
+    let page = book.with_extension("html");
+    let stem = book
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| eyre!("Invalid book path: {}", book.display()))?;
+    let files = format!("{stem}_files");
+
+    let mut doc = Html::parse_document(html);
+    let base_sel = Selector::parse("base[href]").expect("Valid selector");
+    let base = doc
+        .select(&base_sel)
+        .next()
+        .and_then(|el| el.attr("href"))
+        .and_then(|href| Url::parse(url).ok()?.join(href).ok())
+        .map_or_else(|| Url::parse(url), Ok)?;
+    // Local paths are relative to the page, not the site.
+    let bases: Vec<_> = doc.select(&base_sel).map(|el| el.id()).collect();
+    for id in bases {
+        if let Some(mut node) = doc.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    let mut assets = Assets {
+        dir: book.with_file_name(&files),
+        referer: url.to_string(),
+        progress,
+        saved: HashMap::new(),
+    };
+    let local = |name: &str| format!("{files}/{name}");
+
+    let mut rewrites: Vec<(ego_tree::NodeId, &str, String)> = vec![];
+    for (selector, attr, kind) in ASSET_ATTRS {
+        for el in doc.select(&Selector::parse(selector).expect("Valid selector")) {
+            let value = el.attr(attr).unwrap_or_default();
+            // Those that couldn't be fetched can still load online.
+            let value = match assets.get(&base, value, *kind) {
+                Some(name) => local(&name),
+                None if value.starts_with("data:") => continue,
+                None => match base.join(value) {
+                    Ok(absolute) => absolute.to_string(),
+                    Err(_) => continue,
+                },
+            };
+            rewrites.push((el.id(), attr, value));
+        }
+    }
+    let srcsets = Selector::parse("img[srcset], source[srcset]").expect("Valid selector");
+    for el in doc.select(&srcsets) {
+        let srcset = el.attr("srcset").unwrap_or_default();
+        rewrites.push((
+            el.id(),
+            "srcset",
+            srcset_of(srcset, &base, &mut assets, &files),
+        ));
+    }
+    let styled = Selector::parse("[style]").expect("Valid selector");
+    for el in doc.select(&styled) {
+        let style = el.attr("style").unwrap_or_default();
+        let style = css(style, &base, &mut assets, &format!("{files}/"), 0);
+        rewrites.push((el.id(), "style", style));
+    }
+    // Links still go to the site, rather than to missing local pages.
+    let links = Selector::parse("a[href], area[href]").expect("Valid selector");
+    for el in doc.select(&links) {
+        let href = el.attr("href").unwrap_or_default();
+        if !href.starts_with('#')
+            && let Ok(absolute) = base.join(href)
+        {
+            rewrites.push((el.id(), "href", absolute.to_string()));
+        }
+    }
+
+    let styles: Vec<_> = doc
+        .select(&Selector::parse("style").expect("Valid selector"))
+        .flat_map(|el| el.children().map(|child| child.id()).collect::<Vec<_>>())
+        .collect();
+    for id in styles {
+        if let Some(mut node) = doc.tree.get_mut(id)
+            && let Node::Text(text) = node.value()
+        {
+            let rewritten = css(&text.text, &base, &mut assets, &format!("{files}/"), 0);
+            text.text = rewritten.into();
+        }
+    }
+    for (id, attr, value) in rewrites {
+        if let Some(mut node) = doc.tree.get_mut(id)
+            && let Node::Element(el) = node.value()
+        {
+            // Rewritten stylesheets wouldn't match their hashes.
+            el.attrs.retain(|(name, _)| &*name.local != "integrity");
+            for (name, old) in el.attrs.iter_mut() {
+                if &*name.local == attr {
+                    *old = value.as_str().into();
+                }
+            }
+        }
+    }
+
+    crypt::write(&page, doc.html())?;
+    Ok(page)
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// A stylesheet, and how many `@import`s deep it is.
+    Stylesheet(usize),
+    Script,
+    Other,
+}
+
+/// The assets downloaded for a page, all in one dir.
+struct Assets<'a> {
+    dir: PathBuf,
+    referer: String,
+    progress: &'a Progress,
+
+    /// Their filenames by URL, or `None` for those that couldn't be fetched.
+    saved: HashMap<String, Option<String>>,
+}
+impl Assets<'_> {
+    /// Download an asset (relative to `base`) unless it's been already,
+    /// returning its filename; `None` if it's inline or couldn't be fetched.
+    fn get(&mut self, base: &Url, href: &str, kind: Kind) -> Option<String> {
+        let href = href.trim();
+        if href.is_empty() || href.starts_with("data:") || href.starts_with('#') {
+            return None;
+        }
+        let url = base.join(href).ok()?;
+        // Blocked assets are left pointing at their sites.
+        if !matches!(url.scheme(), "http" | "https") || blocklist::blocked(url.as_str()).is_some() {
+            return None;
+        }
+        let key = url.to_string();
+        if let Some(name) = self.saved.get(&key) {
+            return name.clone();
+        }
+
+        let name = filename(&url, self.saved.len() + 1, kind);
+        // Claimed before fetching, so stylesheets importing each other don't loop.
+        self.saved.insert(key.clone(), Some(name.clone()));
+        match self.fetch(&url, &name, kind) {
+            Ok(()) => Some(name),
+            Err(err) => {
+                logging::warn(format!("Couldn't mirror {url}: {err:#}"));
+                self.saved.insert(key, None);
+                None
+            }
+        }
+    }
+
+    fn fetch(&mut self, url: &Url, name: &str, kind: Kind) -> Result<()> {
+        let mut data = {
+            // Given up before the stylesheet's own assets are fetched.
+            let _download = throttle::download();
+            let resp = images::agent()
+                .get(url.as_str())
+                .set("User-Agent", USER_AGENTS[0])
+                .set("Referer", &self.referer)
+                .call()?;
+            images::download(resp, url.as_str(), self.progress)?
+        };
+        if let Kind::Stylesheet(depth) = kind {
+            // Stylesheets' fonts and images are made local too.
+            // They're in the same dir, so the paths are just their names.
+            let text = String::from_utf8_lossy(&data).into_owned();
+            data = css(&text, url, self, "", depth).into_bytes();
+        }
+        fs_err::create_dir_all(&self.dir)?;
+        crypt::write(&self.dir.join(name), data)?;
+        Ok(())
+    }
+}
+
+/// A name for an asset, keeping its extension so it opens as the right type.
+fn filename(url: &Url, n: usize, kind: Kind) -> String {
+    let ext = match kind {
+        Kind::Stylesheet(_) => Some("css".to_string()),
+        Kind::Script => Some("js".to_string()),
+        Kind::Other => url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, ext)| ext.to_lowercase())
+            .filter(|ext| ext.len() <= 5 && ext.chars().all(|c| c.is_ascii_alphanumeric())),
+    };
+    match ext {
+        Some(ext) => format!("{n}.{ext}"),
+        None => n.to_string(),
+    }
+}
+
+/// Point a `srcset`'s candidates at their local copies.
+fn srcset_of(srcset: &str, base: &Url, assets: &mut Assets<'_>, files: &str) -> String {
+    srcset
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            let (src, descriptor) = candidate.split_once(' ').unwrap_or((candidate, ""));
+            match assets.get(base, src, Kind::Other) {
+                Some(name) => format!("{files}/{name} {descriptor}")
+                    .trim_end()
+                    .to_string(),
+                None => candidate.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Download the assets a stylesheet refers to, with `url(...)` and
+/// `@import "..."`, and point it at them (prefixed with `prefix`).
+fn css(src: &str, base: &Url, assets: &mut Assets<'_>, prefix: &str, depth: usize) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some((start, is_import)) = next_reference(rest) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let (reference, len) = if is_import {
+            let quoted = rest["@import".len()..].trim_start();
+            let skipped = rest.len() - quoted.len();
+            match quoted_string(quoted) {
+                Some((value, len)) => (value, skipped + len),
+                // e.g. `@import url(...)`, handled as a `url(`.
+                None => {
+                    out.push_str(&rest[..skipped]);
+                    rest = &rest[skipped..];
+                    continue;
+                }
+            }
+        } else {
+            let Some(end) = rest.find(')') else {
+                break;
+            };
+            let inner = rest["url(".len()..end].trim();
+            let inner = quoted_string(inner).map_or(inner, |(value, _)| value);
+            (inner, end + 1)
+        };
+        // Imported stylesheets have assets of their own.
+        let imported = is_import || out.trim_end().ends_with("@import");
+        let local = match imported {
+            true if depth >= MAX_IMPORT_DEPTH => None,
+            true => assets.get(base, reference, Kind::Stylesheet(depth + 1)),
+            false => assets.get(base, reference, Kind::Other),
+        };
+        match local {
+            Some(name) if is_import => out.push_str(&format!("@import \"{prefix}{name}\"")),
+            Some(name) => out.push_str(&format!("url(\"{prefix}{name}\")")),
+            None => out.push_str(&rest[..len]),
+        }
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Where the next `url(` or `@import` is, and whether it's an `@import`.
+fn next_reference(css: &str) -> Option<(usize, bool)> {
+    let url = css.find("url(").map(|i| (i, false));
+    let import = css.find("@import").map(|i| (i, true));
+    match (url, import) {
+        (Some(url), Some(import)) => Some(url.min(import)),
+        (url, import) => url.or(import),
+    }
+}
+
+/// The value of a quoted CSS string at the start of `s`, and its length.
+fn quoted_string(s: &str) -> Option<(&str, usize)> {
+    let quote = s.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let end = s[1..].find(quote)? + 1;
+    Some((&s[1..end], end + 1))
+}
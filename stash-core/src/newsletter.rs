@@ -0,0 +1,184 @@
+//! Posts on newsletter platforms (Substack, Ghost, Buttondown), which are
+//! recognized by their pages rather than their domains, as they're often
+//! on the newsletter's own. Their markup is consistent enough to take the
+//! whole post as it is, footnotes and all, without the subscribe forms and
+//! paywall prompts that automatic extraction keeps.
+
+use scraper::{ElementRef, Html};
+use serde_json::Value;
+use url::Url;
+
+use crate::{Article, logging, page_metadata, selector};
+
+struct Platform {
+    /// Recorded as how the article was extracted.
+    name: &'static str,
+    detect: fn(&Html, &Url) -> bool,
+    title: &'static str,
+    /// Set below the title, as its standfirst.
+    subtitle: &'static str,
+    body: &'static str,
+    /// Removed from the body.
+    strip: &'static str,
+    /// Where it's there, what's left is only the free part of the post.
+    paywall: &'static str,
+}
+
+const PLATFORMS: &[Platform] = &[
+    Platform {
+        name: "substack",
+        detect: |doc, url| {
+            url.host_str()
+                .is_some_and(|host| host.ends_with(".substack.com"))
+                || has(
+                    doc,
+                    "link[href*='substackcdn.com'], script[src*='substackcdn.com']",
+                )
+        },
+        title: "h1.post-title",
+        subtitle: "h3.subtitle",
+        body: ".available-content .body.markup, .body.markup",
+        strip: ".subscription-widget-wrap, .subscription-widget-wrap-editor, \
+                .subscribe-widget, [data-component-name='SubscribeWidget'], \
+                .captioned-button-wrap, [data-component-name='ButtonCreateButton'], \
+                .button-wrapper, .paywall, .paywall-jump, .image-link-expand, \
+                .share-dialog, .post-ufi",
+        paywall: ".paywall, [data-testid='paywall']",
+    },
+    Platform {
+        name: "ghost",
+        detect: |doc, _| has(doc, "meta[name='generator'][content^='Ghost']"),
+        title: "h1.gh-article-title, h1.article-title, h1.post-full-title, h1.post-title",
+        subtitle: ".gh-article-excerpt, .article-excerpt, .post-full-custom-excerpt",
+        body: ".gh-content, .post-full-content, .post-content, .article-content",
+        strip: ".kg-signup-card, .gh-post-upgrade-cta, .post-upgrade-cta, \
+                [data-members-form], .subscribe-form, .gh-subscribe, .footer-cta",
+        paywall: ".gh-post-upgrade-cta, .post-upgrade-cta",
+    },
+    Platform {
+        name: "buttondown",
+        detect: |doc, url| {
+            url.host_str().is_some_and(|host| {
+                ["buttondown.email", "buttondown.com"]
+                    .iter()
+                    .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+            }) || has(doc, "meta[name='generator'][content^='Buttondown']")
+        },
+        title: "h1",
+        subtitle: "",
+        body: ".email-body-content, .email-body",
+        strip: "form, .subscribe-form, .email-footer",
+        paywall: ".paywall",
+    },
+];
+
+/// Extract a post if the page is of a newsletter platform,
+/// or `None` if it isn't (or it's not a post, e.g. the newsletter's archive).
+pub fn extract(url: &str, html: &str) -> Option<Article> {
+    let parsed = Url::parse(url).ok()?;
+    let doc = Html::parse_document(html);
+    let platform = PLATFORMS
+        .iter()
+        .find(|platform| (platform.detect)(&doc, &parsed))?;
+    let body = doc.select(&selector(platform.body).ok()?).next()?;
+
+    let mut content = String::new();
+    if let Some(subtitle) = first_text(&doc, platform.subtitle) {
+        content.push_str(&format!(
+            "<p><em>{}</em></p>",
+            crate::xhtml::escape_text(&subtitle)
+        ));
+    }
+    content.push_str(&strip(body, platform.strip));
+    if has(&doc, platform.paywall) {
+        logging::warn("The post is paywalled; only its free part is kept.");
+    }
+
+    let ld = linked_data(&doc);
+    let mut article = Article {
+        url: url.to_string(),
+        content,
+        ..Default::default()
+    };
+    page_metadata(&doc, &mut article);
+    article.title = first_text(&doc, platform.title)
+        .or_else(|| meta(&doc, "meta[property='og:title']"))
+        .unwrap_or_default();
+    article.authors = ld
+        .as_ref()
+        .and_then(|ld| authors(&ld["author"]))
+        .or_else(|| meta(&doc, "meta[name='author']"))
+        .unwrap_or_default();
+    article.published_at = ld
+        .as_ref()
+        .and_then(|ld| ld["datePublished"].as_str().map(String::from))
+        .or_else(|| meta(&doc, "meta[property='article:published_time']"))
+        .or_else(|| {
+            let time = doc.select(&selector("time[datetime]").ok()?).next()?;
+            time.value().attr("datetime").map(String::from)
+        })
+        .unwrap_or_default();
+    article.extraction = platform.name.to_string();
+    Some(article)
+}
+
+fn has(doc: &Html, sel: &str) -> bool {
+    selector(sel).is_ok_and(|sel| doc.select(&sel).next().is_some())
+}
+
+fn first_text(doc: &Html, sel: &str) -> Option<String> {
+    let el = doc.select(&selector(sel).ok()?).next()?;
+    let text = el.text().collect::<String>().trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn meta(doc: &Html, sel: &str) -> Option<String> {
+    let el = doc.select(&selector(sel).ok()?).next()?;
+    let content = el.value().attr("content")?.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+/// The body's HTML, without the elements matching `sel`.
+fn strip(body: ElementRef, sel: &str) -> String {
+    let mut fragment = Html::parse_fragment(&body.inner_html());
+    let Ok(sel) = selector(sel) else {
+        return body.inner_html();
+    };
+    let ids: Vec<_> = fragment.select(&sel).map(|el| el.id()).collect();
+    for id in ids {
+        if let Some(mut node) = fragment.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+    fragment.root_element().inner_html()
+}
+
+/// The page's JSON-LD for the post, i.e. that with a publication date.
+fn linked_data(doc: &Html) -> Option<Value> {
+    let sel = selector("script[type='application/ld+json']").ok()?;
+    doc.select(&sel)
+        .filter_map(|script| serde_json::from_str::<Value>(&script.text().collect::<String>()).ok())
+        .flat_map(|ld| match ld {
+            Value::Array(items) => items,
+            ld => match ld.get("@graph") {
+                Some(Value::Array(items)) => items.clone(),
+                _ => vec![ld],
+            },
+        })
+        .find(|ld| ld.get("datePublished").is_some())
+}
+
+/// The names of JSON-LD authors, which can be a name,
+/// a person with a `name`, or a list of either.
+fn authors(value: &Value) -> Option<String> {
+    let names: Vec<_> = match value {
+        Value::Array(items) => items.iter().filter_map(name).collect(),
+        value => name(value).into_iter().collect(),
+    };
+    (!names.is_empty()).then(|| names.join(", "))
+}
+
+fn name(value: &Value) -> Option<String> {
+    let name = value.as_str().or_else(|| value["name"].as_str())?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
@@ -0,0 +1,50 @@
+//! The pages fetched for articles, kept as they were downloaded so that
+//! they can be extracted again offline: to try out a site config's
+//! selectors without a connection, or once the page has been taken down.
+//! Kept in `pages/` in the cache dir, named by a hash of their URLs.
+
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{ContentKind, config, crypt, logging};
+
+fn path(url: &str, kind: &ContentKind) -> PathBuf {
+    let ext = match kind {
+        ContentKind::Html => "html",
+        ContentKind::Text => "txt",
+    };
+    config::cache_dir().join("pages").join(format!(
+        "{}.{ext}",
+        hex::encode(Sha256::digest(url.as_bytes()))
+    ))
+}
+
+/// Keep a fetched page, replacing what was kept of it before.
+/// Failures are only warned about, as the page's been fetched regardless.
+pub fn store(url: &str, kind: &ContentKind, body: &str) {
+    let path = path(url, kind);
+    let stored = path
+        .parent()
+        .map_or(Ok(()), fs_err::create_dir_all)
+        .and_then(|_| crypt::write(&path, body));
+    if let Err(err) = stored {
+        logging::warn(format!("Couldn't cache the page: {err}"));
+    }
+}
+
+/// The page last fetched from a URL, if there is one.
+/// Errors reading one that's there (e.g. the wrong passphrase) are returned.
+pub fn load(url: &str) -> io::Result<Option<(ContentKind, String)>> {
+    for kind in [ContentKind::Html, ContentKind::Text] {
+        match crypt::read_to_string(&path(url, &kind)) {
+            Ok(body) => return Ok(Some((kind, body))),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
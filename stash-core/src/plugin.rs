@@ -0,0 +1,128 @@
+use std::{
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use scraper::Html;
+use serde::Deserialize;
+
+use crate::{Article, page_metadata};
+
+/// An external extractor for some domains.
+///
+/// Each plugin is described by a `<name>.toml` manifest in the plugins dir:
+///
+/// ```toml
+/// domains = ["example.com"]
+/// command = ["python3", "example.py"]
+/// ```
+///
+/// The command is run from the plugins dir with the article's URL
+/// as its last argument and the page's HTML on stdin, and should print
+/// a JSON object with the article's `title` and `content` (HTML),
+/// and optionally its `authors` and `published_at`.
+#[derive(Deserialize, Debug)]
+pub struct Plugin {
+    #[serde(skip)]
+    pub name: String,
+
+    #[serde(skip)]
+    dir: PathBuf,
+
+    domains: Vec<String>,
+    command: Vec<String>,
+}
+
+/// What a plugin prints.
+#[derive(Deserialize)]
+struct Output {
+    title: String,
+    content: String,
+    #[serde(default)]
+    authors: String,
+    #[serde(default)]
+    published_at: String,
+}
+
+impl Plugin {
+    /// Load all plugin manifests in the directory, if it exists.
+    pub fn load_all(dir: &Path) -> Result<Vec<Self>> {
+        let entries = match fs_err::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut plugins = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            let mut plugin: Self = toml::from_str(&fs_err::read_to_string(&path)?)
+                .wrap_err_with(|| format!("Invalid plugin manifest: {}", path.display()))?;
+            if plugin.command.is_empty() {
+                bail!("Plugin manifest has an empty command: {}", path.display());
+            }
+            plugin.name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            plugin.dir = dir.to_path_buf();
+            plugins.push(plugin);
+        }
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(plugins)
+    }
+
+    pub fn handles(&self, domain: &str) -> bool {
+        self.domains.iter().any(|d| d == domain)
+    }
+
+    pub fn extract(&self, url: &str, html: &str) -> Result<Article> {
+        let mut child = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .arg(url)
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Couldn't run plugin `{}`", self.name))?;
+
+        // Write on another thread, so a plugin that prints
+        // before reading all its input can't deadlock us.
+        let mut stdin = child.stdin.take().expect("Stdin is piped");
+        let input = html.to_string();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        // A plugin may not need the HTML at all and exit without reading it.
+        let _ = writer.join();
+
+        if !output.status.success() {
+            return Err(eyre!(
+                "{}",
+                String::from_utf8_lossy(&output.stderr).trim().to_string()
+            )
+            .wrap_err(format!("Plugin `{}` failed ({})", self.name, output.status)));
+        }
+        let output: Output = serde_json::from_slice(&output.stdout)
+            .wrap_err_with(|| format!("Plugin `{}` printed invalid output", self.name))?;
+        if output.content.is_empty() {
+            bail!("Plugin `{}` returned no content.", self.name);
+        }
+        let mut article = Article {
+            url: url.to_string(),
+            title: output.title,
+            content: output.content,
+            authors: output.authors,
+            published_at: output.published_at,
+            ..Default::default()
+        };
+        page_metadata(&Html::parse_document(html), &mut article);
+        Ok(article)
+    }
+}
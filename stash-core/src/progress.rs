@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Progress display for a run: an overall bar for multi-URL batches
+/// and transient bars for the individual downloads within them.
+#[derive(Clone, Default)]
+pub struct Progress {
+    multi: MultiProgress,
+}
+impl Progress {
+    /// Progress that is never drawn.
+    pub fn hidden() -> Self {
+        Self {
+            multi: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        }
+    }
+
+    /// Overall bar for a batch of `len` items.
+    /// Hidden for single-item runs since the download bars are enough.
+    pub fn batch(&self, len: usize) -> ProgressBar {
+        if len <= 1 {
+            return ProgressBar::hidden();
+        }
+        let bar = self.multi.add(ProgressBar::new(len as u64));
+        bar.set_style(
+            ProgressStyle::with_template("{bar:30.cyan/blue} {pos}/{len} {wide_msg}")
+                .expect("Valid template")
+                .progress_chars("=> "),
+        );
+        bar
+    }
+
+    /// Bar for a single download, e.g. a page or an image.
+    /// If the size isn't known ahead of time this is a spinner instead.
+    pub fn download(&self, label: &str, len: Option<u64>) -> ProgressBar {
+        let bar = match len {
+            Some(len) => {
+                let bar = ProgressBar::new(len);
+                bar.set_style(
+                    ProgressStyle::with_template(
+                        "  {bar:30.green/white} {bytes}/{total_bytes} {wide_msg}",
+                    )
+                    .expect("Valid template")
+                    .progress_chars("=> "),
+                );
+                bar
+            }
+            None => {
+                let bar = ProgressBar::new_spinner();
+                bar.set_style(
+                    ProgressStyle::with_template("  {spinner} {bytes} {wide_msg}")
+                        .expect("Valid template"),
+                );
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar
+            }
+        };
+        bar.set_message(label.to_string());
+        self.multi.add(bar)
+    }
+
+    /// Run `f` with the bars hidden, so that whatever
+    /// it prints or prompts for isn't drawn over.
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        self.multi.suspend(f)
+    }
+
+    /// Print a per-item status line above the bars.
+    pub fn status(&self, msg: impl AsRef<str>) {
+        self.suspend(|| println!("{}", msg.as_ref()));
+    }
+}
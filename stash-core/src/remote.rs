@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use scraper::Html;
+use serde_json::{Value, json};
+
+use crate::{Article, config::RemoteExtractor, failure::Failure, page_metadata};
+
+/// Services fetch the page themselves, which can take a while.
+const TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Extract an article with an external service, posting it the URL
+/// (and the page's HTML, if it's been fetched) as JSON. The service should
+/// respond with the article as JSON: its `title` and `content` (HTML), and
+/// optionally its authors and publication date, under the names used by
+/// Readability (`byline`, `published_time`), Mercury/Postlight
+/// (`author`, `date_published`), or stash itself (`authors`, `published_at`).
+pub fn extract(config: &RemoteExtractor, url: &str, html: Option<&str>) -> Result<Article> {
+    let mut req = ureq::post(&config.url).timeout(TIMEOUT);
+    if let Some(key) = &config.api_key {
+        req = req.set("Authorization", &format!("Bearer {key}"));
+    }
+    let resp: Value = req
+        .send_json(json!({ "url": url, "html": html }))
+        .wrap_err_with(|| format!("The remote extractor at {} failed", config.url))
+        .wrap_err(Failure::Network)?
+        .into_json()
+        .wrap_err("The remote extractor's response isn't JSON")?;
+
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| resp[name].as_str().filter(|value| !value.trim().is_empty()))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let content = field(&["content", "html"]);
+    if content.is_empty() {
+        bail!("The remote extractor found no content: {resp}");
+    }
+    let mut article = Article {
+        url: url.to_string(),
+        content,
+        ..Default::default()
+    };
+    if let Some(html) = html {
+        page_metadata(&Html::parse_document(html), &mut article);
+    }
+    article.title = field(&["title"]);
+    article.authors = field(&["authors", "author", "byline"]);
+    article.published_at = field(&["published_at", "date_published", "published_time", "date"]);
+    for (name, value) in [
+        ("site_name", &mut article.site_name),
+        ("lang", &mut article.lang),
+    ] {
+        let found = field(&[name]);
+        if !found.is_empty() {
+            *value = found;
+        }
+    }
+    article.extraction = "remote".into();
+    article.fetched_at = Utc::now();
+    Ok(article)
+}
@@ -0,0 +1,82 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{config, crypt, normalize_url};
+
+/// A URL that couldn't be stashed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Failed {
+    pub url: String,
+
+    /// The most recent error.
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+    pub attempts: u32,
+}
+
+/// URLs that failed to be stashed, to try again with `stash retry`,
+/// kept as `retry.json` in the data dir.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub struct RetryQueue {
+    pub entries: Vec<Failed>,
+}
+impl RetryQueue {
+    pub fn path() -> PathBuf {
+        config::data_dir().join("retry.json")
+    }
+
+    pub fn load() -> Result<Self> {
+        match crypt::read_to_string(&Self::path()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Queue a failed URL, or count another attempt if it's already queued.
+    pub fn add(url: &str, error: &str) -> Result<()> {
+        let url = normalize_url(url);
+        let _lock = config::lock_data();
+        let mut queue = Self::load()?;
+        match queue.entries.iter_mut().find(|entry| entry.url == url) {
+            Some(entry) => {
+                entry.error = error.to_string();
+                entry.failed_at = Utc::now();
+                entry.attempts += 1;
+            }
+            None => queue.entries.push(Failed {
+                url,
+                error: error.to_string(),
+                failed_at: Utc::now(),
+                attempts: 1,
+            }),
+        }
+        queue.save()
+    }
+
+    /// Take a URL out of the queue, e.g. once it's been stashed.
+    pub fn remove(url: &str) -> Result<()> {
+        let url = normalize_url(url);
+        let _lock = config::lock_data();
+        let mut queue = Self::load()?;
+        let len = queue.entries.len();
+        queue.entries.retain(|entry| entry.url != url);
+        if queue.entries.len() < len {
+            queue.save()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Pages saved from browsers, stashed from the file instead of a URL:
+//! SingleFile's `.html` files, which have their resources inlined as
+//! `data:` URIs, and `.mhtml` archives (e.g. Chrome's "Webpage, Single
+//! File"), which have them as MIME parts. Their images are packaged
+//! from the file rather than fetched again, so pages that have since
+//! been taken down (or were behind a login) still come out whole.
+
+use std::{collections::HashMap, path::Path};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use mail_parser::{MessageParser, MimeHeaders};
+use scraper::{Html, Node, Selector};
+use url::Url;
+
+/// The extensions of files taken to be saved pages.
+const EXTENSIONS: &[&str] = &["html", "htm", "mhtml", "mht"];
+
+/// A saved page, with the URL it was saved from.
+pub struct SavedPage {
+    pub url: String,
+    pub html: String,
+}
+
+/// Whether an input (as given on the command line) is a saved page,
+/// rather than a URL.
+pub fn is_page(input: &str) -> bool {
+    let path = Path::new(input);
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        && path.is_file()
+}
+
+/// Read a saved page. Those that don't say where they're from
+/// are given a `file://` URL.
+pub fn load(path: &Path) -> Result<SavedPage> {
+    let data = fs_err::read(path)?;
+    let is_mhtml = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "mhtml" | "mht"));
+    let page = if is_mhtml {
+        mhtml(&data).wrap_err_with(|| format!("Invalid MHTML file: {}", path.display()))?
+    } else {
+        let html = String::from_utf8_lossy(&data).into_owned();
+        SavedPage {
+            url: singlefile_url(&html).unwrap_or_default(),
+            html,
+        }
+    };
+    if !page.url.is_empty() {
+        return Ok(page);
+    }
+    let url = fs_err::canonicalize(path)
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok())
+        .ok_or_else(|| eyre!("Couldn't make a URL for {}", path.display()))?;
+    Ok(SavedPage {
+        url: url.to_string(),
+        ..page
+    })
+}
+
+/// The URL SingleFile notes in the comment it starts pages with:
+///
+/// ```html
+/// <!--
+///  Page saved with SingleFile
+///  url: https://example.com/post
+///  saved date: ...
+/// -->
+/// ```
+fn singlefile_url(html: &str) -> Option<String> {
+    let start = html.find("Page saved with SingleFile")?;
+    let comment = &html[start..];
+    let comment = &comment[..comment.find("-->")?];
+    comment
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("url:"))
+        .map(|url| url.trim().to_string())
+        .filter(|url| Url::parse(url).is_ok())
+}
+
+/// The page of an MHTML archive, with its images inlined
+/// as `data:` URIs from the archive's other parts.
+fn mhtml(data: &[u8]) -> Result<SavedPage> {
+    let message = MessageParser::default()
+        .parse(data)
+        .ok_or_else(|| eyre!("It isn't a MIME message"))?;
+    let Some(page) = message.html_part(0) else {
+        bail!("It has no HTML part");
+    };
+    let html = page.text_contents().unwrap_or_default();
+    let url = message
+        .header_raw("Snapshot-Content-Location")
+        .or(page.content_location())
+        .map(|url| url.trim().to_string())
+        .unwrap_or_default();
+
+    // Resources by their locations, and their content IDs for `cid:` URLs.
+    let mut resources = HashMap::new();
+    for part in &message.parts {
+        let Some(mime) = part
+            .content_type()
+            .filter(|ctype| ctype.ctype() == "image")
+            .map(|ctype| format!("image/{}", ctype.subtype().unwrap_or_default()))
+        else {
+            continue;
+        };
+        let uri = format!(
+            "data:{mime};base64,{}",
+            BASE64_STANDARD.encode(part.contents())
+        );
+        if let Some(location) = part.content_location() {
+            resources.insert(location.trim().to_string(), uri.clone());
+        }
+        if let Some(id) = part.content_id() {
+            resources.insert(format!("cid:{}", id.trim_matches(['<', '>'])), uri);
+        }
+    }
+
+    Ok(SavedPage {
+        html: inline_images(html, &url, &resources),
+        url,
+    })
+}
+
+/// Point `<img>`s (relative to `base`) at the matching resources.
+fn inline_images(html: &str, base: &str, resources: &HashMap<String, String>) -> String {
+    if resources.is_empty() {
+        return html.to_string();
+    }
+    let base = Url::parse(base).ok();
+    let mut doc = Html::parse_document(html);
+    let sel = Selector::parse("img[src]").expect("Valid selector");
+    let rewrites: Vec<_> = doc
+        .select(&sel)
+        .filter_map(|el| {
+            let src = el.attr("src")?;
+            let absolute = base
+                .as_ref()
+                .and_then(|base| base.join(src).ok())
+                .map(String::from);
+            resources
+                .get(src)
+                .or_else(|| resources.get(&absolute?))
+                .map(|uri| (el.id(), uri))
+        })
+        .collect();
+    for (id, uri) in rewrites {
+        if let Some(mut node) = doc.tree.get_mut(id)
+            && let Node::Element(el) = node.value()
+        {
+            // Otherwise readers could pick a candidate that isn't in the archive.
+            el.attrs
+                .retain(|(name, _)| !matches!(&*name.local, "srcset" | "sizes"));
+            for (name, value) in el.attrs.iter_mut() {
+                if &*name.local == "src" {
+                    *value = uri.as_str().into();
+                }
+            }
+        }
+    }
+    doc.html()
+}
@@ -0,0 +1,162 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use ego_tree::NodeId;
+use rhai::{AST, Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use scraper::{ElementRef, Html};
+
+use crate::{Article, page_metadata, selector};
+
+/// Upper bound on the work a script can do,
+/// so a buggy one can't hang stash.
+const MAX_OPERATIONS: u64 = 50_000_000;
+
+/// Where a site config's script is: paths are
+/// relative to the directory `sites.toml` is in.
+pub fn resolve(sites_dir: &Path, script: &str) -> PathBuf {
+    sites_dir.join(shellexpand::tilde(script).as_ref())
+}
+
+/// Check that a script parses.
+pub fn check(path: &Path) -> Result<()> {
+    compile(&engine(), path).map(|_| ())
+}
+
+/// Extract an article by running a [Rhai](https://rhai.rs) script.
+///
+/// The script has `url` and the parsed page as `doc` in scope.
+/// Elements (including `doc`) have `select(selector)` (an array of
+/// elements), `select_one(selector)` (an element or `()`), `text()`,
+/// `html()` (the inner HTML), `outer_html()`, and `attr(name)`
+/// (a string or `()`); Rhai's `parse_json` helps with data embedded
+/// in `<script>` tags.
+///
+/// It should evaluate to a map with the article's `title` and `content`
+/// (HTML), and optionally its `authors` and `published_at`:
+///
+/// ```rhai
+/// let body = doc.select(".part").map(|part| part.html());
+/// #{ title: doc.select_one("h1").text(), content: body.reduce(|a, b| a + b) }
+/// ```
+pub fn extract(path: &Path, url: &str, html: &str) -> Result<Article> {
+    let engine = engine();
+    let ast = compile(&engine, path)?;
+
+    let doc = Rc::new(Html::parse_document(html));
+    let mut scope = Scope::new();
+    scope.push_constant("url", url.to_string());
+    scope.push_constant(
+        "doc",
+        Element {
+            id: doc.root_element().id(),
+            doc: doc.clone(),
+        },
+    );
+    let out: Map = engine
+        .eval_ast_with_scope(&mut scope, &ast)
+        .map_err(|err| eyre!("{err}"))
+        .wrap_err_with(|| format!("Script failed: {}", path.display()))?;
+
+    let field = |name: &str| {
+        out.get(name)
+            .filter(|value| !value.is_unit())
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+    };
+    let mut article = Article {
+        url: url.to_string(),
+        title: field("title"),
+        content: field("content"),
+        authors: field("authors"),
+        published_at: field("published_at"),
+        ..Default::default()
+    };
+    if article.content.is_empty() {
+        bail!("Script returned no content: {}", path.display());
+    }
+    page_metadata(&doc, &mut article);
+    Ok(article)
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<AST> {
+    let src = fs_err::read_to_string(path)?;
+    engine
+        .compile(src)
+        .map_err(|err| eyre!("{err}"))
+        .wrap_err_with(|| format!("Invalid script: {}", path.display()))
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine
+        .register_type_with_name::<Element>("Element")
+        .register_fn("select", Element::select)
+        .register_fn("select_one", Element::select_one)
+        .register_fn("text", Element::text)
+        .register_fn("html", Element::html)
+        .register_fn("outer_html", Element::outer_html)
+        .register_fn("attr", Element::attr);
+    engine
+}
+
+/// An element of the page, as exposed to scripts.
+#[derive(Clone)]
+struct Element {
+    doc: Rc<Html>,
+    id: NodeId,
+}
+impl Element {
+    fn element(&self) -> ElementRef<'_> {
+        self.doc
+            .tree
+            .get(self.id)
+            .and_then(ElementRef::wrap)
+            .expect("Element is in the document")
+    }
+
+    fn matches(&mut self, sel: &str) -> Result<Vec<Self>, Box<EvalAltResult>> {
+        let sel = selector(sel).map_err(|err| err.to_string())?;
+        Ok(self
+            .element()
+            .select(&sel)
+            .map(|el| Self {
+                doc: self.doc.clone(),
+                id: el.id(),
+            })
+            .collect())
+    }
+
+    fn select(&mut self, sel: &str) -> Result<Array, Box<EvalAltResult>> {
+        Ok(self.matches(sel)?.into_iter().map(Dynamic::from).collect())
+    }
+
+    fn select_one(&mut self, sel: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        Ok(self
+            .matches(sel)?
+            .into_iter()
+            .next()
+            .map_or(Dynamic::UNIT, Dynamic::from))
+    }
+
+    fn text(&mut self) -> String {
+        self.element().text().collect()
+    }
+
+    fn html(&mut self) -> String {
+        self.element().inner_html()
+    }
+
+    fn outer_html(&mut self) -> String {
+        self.element().html()
+    }
+
+    fn attr(&mut self, name: &str) -> Dynamic {
+        self.element()
+            .attr(name)
+            .map_or(Dynamic::UNIT, |value| value.to_string().into())
+    }
+}
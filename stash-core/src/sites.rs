@@ -0,0 +1,59 @@
+//! Community-maintained site configs, shared so that everyone needn't
+//! write the same selectors for the same sites. They're kept in
+//! `community/` in the config dir and used for any domain that
+//! `sites.toml` doesn't configure itself.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+
+use crate::{Extractor, archive::git, failure::Failure};
+
+/// Where the community's site configs are kept.
+pub fn dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("community")
+}
+
+/// Download the community's site configs from `source`: a git repo
+/// (cloned, then pulled) with a `sites.toml` (and any scripts it refers to)
+/// at its root, or the URL of a single `sites.toml`.
+/// Returns the updated configs.
+pub fn update(source: &str, dir: &Path) -> Result<Extractor> {
+    let path = dir.join("sites.toml");
+    if is_git(source) {
+        if dir.join(".git").exists() {
+            git(dir, &["pull", "--quiet", "--ff-only"])?;
+        } else {
+            if dir.exists() {
+                fs_err::remove_dir_all(dir)?;
+            }
+            let parent = dir.parent().unwrap_or(Path::new("."));
+            fs_err::create_dir_all(parent)?;
+            let dir = dir.to_string_lossy();
+            git(parent, &["clone", "--quiet", "--depth", "1", source, &dir])?;
+        }
+        if !path.exists() {
+            bail!("{source} has no `sites.toml` at its root.");
+        }
+    } else {
+        let src = ureq::get(source)
+            .call()
+            .wrap_err_with(|| format!("Couldn't get {source}"))
+            .wrap_err(Failure::Network)?
+            .into_string()?;
+        // Check they parse before replacing any that do.
+        toml::from_str::<Extractor>(&src)
+            .wrap_err_with(|| format!("{source} isn't a valid sites.toml"))?;
+        if dir.join(".git").exists() {
+            fs_err::remove_dir_all(dir)?;
+        }
+        fs_err::create_dir_all(dir)?;
+        fs_err::write(&path, src)?;
+    }
+    Extractor::load(&path).wrap_err_with(|| format!("{source} isn't a valid sites.toml"))
+}
+
+/// Whether the source is a git repo rather than a file to download.
+fn is_git(source: &str) -> bool {
+    source.ends_with(".git") || source.starts_with("git@") || source.starts_with("git://")
+}
@@ -0,0 +1,211 @@
+//! Sites whose pages don't have the article in them (or not all of it),
+//! which are fetched through their APIs or some other way instead.
+//! A site config or plugin for the domain takes precedence.
+
+mod arxiv;
+mod github;
+mod google_docs;
+mod mastodon;
+mod twitter;
+mod wikipedia;
+mod youtube;
+
+use std::{io::Read, time::Duration};
+
+use chrono::Utc;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use scraper::{Html, Node, Selector};
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    Article, BROWSER_USER_AGENT, Extractor, MAX_BODY_SIZE, failure::StashError, fetch_error,
+    progress::Progress, read_body, throttle,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Source {
+    /// Recorded as how the article was extracted.
+    pub name: &'static str,
+    matches: fn(&Url) -> bool,
+    fetch: fn(&Url, &Client) -> Result<Article>,
+}
+
+/// What sources fetch with: the extractor, for its settings,
+/// and where to show the progress of downloads.
+pub struct Client<'a> {
+    pub extractor: &'a Extractor,
+    progress: &'a Progress,
+}
+
+const SOURCES: &[Source] = &[
+    Source {
+        name: "youtube",
+        matches: youtube::matches,
+        fetch: youtube::fetch,
+    },
+    Source {
+        name: "twitter",
+        matches: twitter::matches,
+        fetch: twitter::fetch,
+    },
+    Source {
+        name: "mastodon",
+        matches: mastodon::matches,
+        fetch: mastodon::fetch,
+    },
+    Source {
+        name: "wikipedia",
+        matches: wikipedia::matches,
+        fetch: wikipedia::fetch,
+    },
+    Source {
+        name: "arxiv",
+        matches: arxiv::matches,
+        fetch: arxiv::fetch,
+    },
+    Source {
+        name: "github",
+        matches: github::matches,
+        fetch: github::fetch,
+    },
+    Source {
+        name: "google-docs",
+        matches: google_docs::matches,
+        fetch: google_docs::fetch,
+    },
+];
+
+/// The source for a URL, if it's of one.
+pub fn find(url: &Url) -> Option<&'static Source> {
+    SOURCES.iter().find(|source| (source.matches)(url))
+}
+
+impl Source {
+    pub fn fetch(&self, url: &Url, extractor: &Extractor, progress: &Progress) -> Result<Article> {
+        let client = Client {
+            extractor,
+            progress,
+        };
+        let mut article = (self.fetch)(url, &client).map_err(|err| match StashError::of(&err) {
+            Some(_) => err,
+            None => err.wrap_err(StashError::Extraction {
+                field: None,
+                selector: None,
+            }),
+        })?;
+        article.url = url.to_string();
+        article.extraction = self.name.to_string();
+        article.fetched_at = Utc::now();
+        Ok(article)
+    }
+}
+
+impl Client<'_> {
+    fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String> {
+        let mut req = ureq::get(url)
+            .timeout(TIMEOUT)
+            .set("User-Agent", BROWSER_USER_AGENT);
+        for (name, value) in headers {
+            req = req.set(name, value);
+        }
+        let _download = throttle::download();
+        let resp = req.call().wrap_err_with(|| fetch_error(url))?;
+        self.read(resp, url)
+    }
+
+    fn get_json(&self, url: &str, headers: &[(&str, &str)]) -> Result<Value> {
+        serde_json::from_str(&self.get(url, headers)?)
+            .wrap_err_with(|| format!("The response from {url} isn't JSON"))
+    }
+
+    /// Read a response's text as pages are: rate-limited, showing
+    /// its progress, and refused if it's over the size limit.
+    /// The request should hold a `throttle::download`.
+    fn read(&self, resp: ureq::Response, url: &str) -> Result<String> {
+        read_body(resp, url, self.max_size(), self.progress).wrap_err_with(|| fetch_error(url))
+    }
+
+    /// Like `read`, for a binary response (e.g. a PDF).
+    fn read_bytes(&self, resp: ureq::Response, url: &str) -> Result<Vec<u8>> {
+        let max_size = self.max_size();
+        let len = resp
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok());
+        let bar = self.progress.download(url, len);
+        let mut data = vec![];
+        let read = bar
+            .wrap_read(throttle::reader(url, resp.into_reader()).take(max_size + 1))
+            .read_to_end(&mut data);
+        bar.finish_and_clear();
+        read.wrap_err_with(|| fetch_error(url))?;
+        if data.len() as u64 > max_size {
+            bail!(
+                "{url} is over the {} MB limit (`max_page_size` in config.toml)",
+                max_size / 1024 / 1024
+            );
+        }
+        Ok(data)
+    }
+
+    fn max_size(&self) -> u64 {
+        self.extractor.max_body_size.unwrap_or(MAX_BODY_SIZE)
+    }
+}
+
+fn str(value: &Value) -> String {
+    value.as_str().unwrap_or_default().trim().to_string()
+}
+
+/// The start of a post's first line, up to 80 characters, as its title.
+fn headline(text: &str) -> Option<String> {
+    let line = text.lines().find(|line| !line.trim().is_empty())?;
+    let mut title = String::new();
+    for word in line.split_whitespace() {
+        if title.chars().count() + word.chars().count() > 80 {
+            return Some(format!("{title}…"));
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+    Some(title)
+}
+
+fn detach(doc: &mut Html, sel: &str) {
+    let sel = Selector::parse(sel).expect("Valid selector");
+    let ids: Vec<_> = doc.select(&sel).map(|el| el.id()).collect();
+    for id in ids {
+        if let Some(mut node) = doc.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+}
+
+/// Make relative image sources and links absolute, against different bases
+/// where need be (e.g. raw files for images and pages for links).
+/// Links within the document (`#…`) are kept as they are.
+fn absolutize(doc: &mut Html, src_base: &Url, href_base: &Url) {
+    let sel = Selector::parse("img[src], a[href]").expect("Valid selector");
+    let ids: Vec<_> = doc.select(&sel).map(|el| el.id()).collect();
+    for id in ids {
+        let Some(mut node) = doc.tree.get_mut(id) else {
+            continue;
+        };
+        let Node::Element(el) = node.value() else {
+            continue;
+        };
+        for (name, value) in el.attrs.iter_mut() {
+            let base = match &*name.local {
+                "src" => src_base,
+                "href" if !value.starts_with('#') => href_base,
+                _ => continue,
+            };
+            if let Ok(absolute) = base.join(value) {
+                *value = absolute.as_str().into();
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! arXiv papers, whatever the link is to (the abstract, the PDF, or the
+//! HTML): the paper's HTML rendition, made with LaTeXML, which keeps its
+//! math (as MathML) and figures, from arXiv itself or else ar5iv. Papers
+//! without one get the text of their PDF. The title, authors, abstract,
+//! and dates come from the arXiv API either way.
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use scraper::{Html, Selector};
+use url::Url;
+
+use super::{Client, TIMEOUT, absolutize, detach};
+use crate::{Article, BROWSER_USER_AGENT, fetch_error, logging, throttle, xhtml};
+
+/// The parts of the HTML renditions that the book has elsewhere
+/// (the title and authors) or that are only of use on the site.
+const STRIP: &str = ".ltx_title_document, .ltx_authors, .ltx_dates, .ltx_page_header, \
+    .ltx_page_footer, .ltx_page_logo, .ltx_role_pageheader, nav, header, footer, \
+    button, .package-alerts, .ltx_ERROR";
+
+pub fn matches(url: &Url) -> bool {
+    paper_id(url).is_some()
+}
+
+/// The paper's ID, with its version if the link has one,
+/// from `arxiv.org/abs/2401.01234v2`, `arxiv.org/pdf/2401.01234.pdf`,
+/// `arxiv.org/html/…`, or `ar5iv.labs.arxiv.org/html/…`.
+/// Older IDs with the archive's name (`hep-th/9901001`) work too.
+fn paper_id(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    if !matches!(
+        host,
+        "arxiv.org" | "export.arxiv.org" | "ar5iv.org" | "ar5iv.labs.arxiv.org"
+    ) {
+        return None;
+    }
+    let path = url.path().trim_matches('/');
+    let (kind, id) = path.split_once('/')?;
+    if !matches!(kind, "abs" | "pdf" | "html") {
+        return None;
+    }
+    let id = id.strip_suffix(".pdf").unwrap_or(id).trim_end_matches('/');
+    let valid = match id.split_once('/') {
+        // e.g. `hep-th/9901001v1`
+        Some((archive, number)) => {
+            archive
+                .chars()
+                .all(|c| c.is_ascii_alphabetic() || matches!(c, '-' | '.'))
+                && number.len() >= 7
+                && number[..7].chars().all(|c| c.is_ascii_digit())
+        }
+        // e.g. `2401.01234v2`
+        None => id.split_once('.').is_some_and(|(month, number)| {
+            let number = number.split('v').next().unwrap_or_default();
+            month.len() == 4
+                && month.chars().all(|c| c.is_ascii_digit())
+                && (4..=5).contains(&number.len())
+                && number.chars().all(|c| c.is_ascii_digit())
+        }),
+    };
+    valid.then(|| id.to_string())
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let id = paper_id(url).ok_or_else(|| eyre!("Not an arXiv paper: {url}"))?;
+    let mut article = metadata(client, &id)?;
+
+    let renditions = [
+        format!("https://arxiv.org/html/{id}"),
+        format!("https://ar5iv.labs.arxiv.org/html/{id}"),
+    ];
+    let body = renditions.iter().find_map(|url| match html(client, url) {
+        Ok(body) => body,
+        Err(err) => {
+            logging::warn(format!("{err:#}"));
+            None
+        }
+    });
+    let body = match body {
+        Some(body) => body,
+        None => {
+            logging::warn(format!(
+                "{id} has no HTML rendition, so the text of its PDF is used."
+            ));
+            pdf_text(client, &id)?
+        }
+    };
+    article.content.push_str(&body);
+    Ok(article)
+}
+
+/// The paper's title, authors, and dates, and its abstract
+/// (as the start of its content), from the arXiv API.
+fn metadata(client: &Client, id: &str) -> Result<Article> {
+    let api = format!("https://export.arxiv.org/api/query?id_list={id}");
+    let feed = feed_rs::parser::parse(client.get(&api, &[])?.as_bytes())
+        .wrap_err("Couldn't parse the arXiv API's response")?;
+    let Some(entry) = feed.entries.into_iter().next() else {
+        bail!("arXiv has no paper {id}");
+    };
+    let title = entry
+        .title
+        .map(|title| {
+            title
+                .content
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+    // The API's response for a missing paper is an entry titled "Error".
+    if title.is_empty() || title == "Error" {
+        bail!("arXiv has no paper {id}");
+    }
+    let summary = entry
+        .summary
+        .map(|summary| {
+            summary
+                .content
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+    Ok(Article {
+        title,
+        authors: entry
+            .authors
+            .iter()
+            .filter_map(|author| author.name.as_deref())
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(", "),
+        published_at: entry
+            .published
+            .or(entry.updated)
+            .map(|date| date.to_rfc3339())
+            .unwrap_or_default(),
+        content: format!(
+            "<section class=\"abstract\"><h2>Abstract</h2><p>{}</p></section>",
+            xhtml::escape_text(&summary)
+        ),
+        site_name: "arXiv".into(),
+        keywords: entry
+            .categories
+            .into_iter()
+            .map(|category| category.term)
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// A paper's HTML rendition, or `None` if there isn't one there
+/// (arXiv 404s, ar5iv redirects to the abstract page).
+fn html(client: &Client, url: &str) -> Result<Option<String>> {
+    let download = throttle::download();
+    let resp = match ureq::get(url)
+        .timeout(TIMEOUT)
+        .set("User-Agent", BROWSER_USER_AGENT)
+        .call()
+    {
+        Ok(resp) => resp,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(err) => return Err(err).wrap_err_with(|| fetch_error(url)),
+    };
+    let page_url = Url::parse(resp.get_url())?;
+    let body = client.read(resp, url)?;
+    drop(download);
+    let mut doc = Html::parse_document(&body);
+    let main = Selector::parse("article.ltx_document, .ltx_page_main").expect("Valid selector");
+    if doc.select(&main).next().is_none() {
+        return Ok(None);
+    }
+
+    let base = doc
+        .select(&Selector::parse("base[href]").expect("Valid selector"))
+        .next()
+        .and_then(|base| page_url.join(base.value().attr("href")?).ok())
+        .unwrap_or(page_url);
+    detach(&mut doc, &format!("{STRIP}, .ltx_abstract"));
+    // Figures' and links' paths are relative to the rendition,
+    // not the abstract page the book's for.
+    absolutize(&mut doc, &base, &base);
+
+    let main = doc
+        .select(&main)
+        .next()
+        .map(|main| main.inner_html())
+        .unwrap_or_default();
+    Ok(Some(main))
+}
+
+/// The text of a paper's PDF, as paragraphs.
+fn pdf_text(client: &Client, id: &str) -> Result<String> {
+    let url = format!("https://arxiv.org/pdf/{id}");
+    let download = throttle::download();
+    let resp = ureq::get(&url)
+        .timeout(TIMEOUT)
+        .set("User-Agent", BROWSER_USER_AGENT)
+        .call()
+        .wrap_err_with(|| fetch_error(&url))?;
+    let pdf = client.read_bytes(resp, &url)?;
+    drop(download);
+    let text = pdf_extract::extract_text_from_mem(&pdf)
+        .map_err(|err| eyre!("Couldn't read the text of {url}: {err}"))?;
+
+    // Words hyphenated across lines are put back together.
+    let text = text.replace("-\n", "");
+    Ok(text
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .map(|para| para.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|para| !para.is_empty())
+        .map(|para| format!("<p>{}</p>\n", xhtml::escape_text(&para)))
+        .collect())
+}
@@ -0,0 +1,243 @@
+//! GitHub READMEs, Markdown (and other markup) files, and wiki pages, as
+//! rendered by GitHub's API rather than scraped from its web UI. Relative
+//! images are pointed at the raw files and relative links at GitHub.
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use scraper::{Html, Node, Selector};
+use serde_json::json;
+use url::Url;
+
+use super::{Client, TIMEOUT, absolutize, detach, str};
+use crate::{Article, BROWSER_USER_AGENT, fetch_error, throttle, xhtml};
+
+const API: &str = "https://api.github.com";
+
+/// The API's rendered HTML, rather than JSON.
+const HTML: &str = "application/vnd.github.html+json";
+
+/// First path segments of GitHub's own pages, which aren't users' repos.
+const RESERVED: &[&str] = &[
+    "about",
+    "apps",
+    "collections",
+    "enterprise",
+    "explore",
+    "features",
+    "login",
+    "marketplace",
+    "notifications",
+    "orgs",
+    "pricing",
+    "security",
+    "settings",
+    "site",
+    "sponsors",
+    "topics",
+    "users",
+];
+
+/// Files rendered as documents (rather than code).
+const MARKUP: &[&str] = &[
+    "md", "markdown", "mdown", "mkd", "rst", "adoc", "asciidoc", "org", "textile", "rdoc",
+];
+
+enum Page {
+    /// The README of the repo, or of a directory in it.
+    Readme {
+        dir: Option<String>,
+        reference: Option<String>,
+    },
+    File {
+        reference: String,
+        path: String,
+    },
+    Wiki {
+        page: String,
+    },
+}
+
+pub fn matches(url: &Url) -> bool {
+    page(url).is_some()
+}
+
+/// The repo's owner and name, and the page of it.
+fn page(url: &Url) -> Option<(String, String, Page)> {
+    let host = url.host_str()?;
+    if host != "github.com" && host != "www.github.com" {
+        return None;
+    }
+    let segments: Vec<_> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let (owner, repo, rest) = match &segments[..] {
+        [owner, repo, rest @ ..] if !RESERVED.contains(owner) => (owner, repo, rest),
+        _ => return None,
+    };
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    let page = match rest {
+        [] => Page::Readme {
+            dir: None,
+            reference: None,
+        },
+        ["tree", reference, dir @ ..] => Page::Readme {
+            dir: (!dir.is_empty()).then(|| dir.join("/")),
+            reference: Some(reference.to_string()),
+        },
+        ["blob", reference, path @ ..] if !path.is_empty() => {
+            let path = path.join("/");
+            let ext = path.rsplit_once('.')?.1.to_lowercase();
+            if !MARKUP.contains(&ext.as_str()) {
+                return None;
+            }
+            Page::File {
+                reference: reference.to_string(),
+                path,
+            }
+        }
+        ["wiki"] => Page::Wiki {
+            page: "Home".into(),
+        },
+        ["wiki", page] => Page::Wiki {
+            page: page.to_string(),
+        },
+        _ => return None,
+    };
+    Some((owner.to_string(), repo.to_string(), page))
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let (owner, name, page) = page(url).ok_or_else(|| eyre!("Not a GitHub page: {url}"))?;
+    let repo = client.get_json(&format!("{API}/repos/{owner}/{name}"), &[])?;
+    let full_name = str(&repo["full_name"]);
+    let description = str(&repo["description"]);
+
+    let (title, html, src_base, href_base) = match &page {
+        Page::Readme { dir, reference } => {
+            let mut api = format!("{API}/repos/{owner}/{name}/readme");
+            if let Some(dir) = dir {
+                api.push_str(&format!("/{dir}"));
+            }
+            if let Some(reference) = reference {
+                api.push_str(&format!("?ref={reference}"));
+            }
+            let (html, src_base, href_base) = rendered(client, &api)?;
+            let title = match dir {
+                Some(dir) => format!("{full_name}: {dir}"),
+                None => full_name.clone(),
+            };
+            (title, html, src_base, href_base)
+        }
+        Page::File { reference, path } => {
+            let api = format!("{API}/repos/{owner}/{name}/contents/{path}?ref={reference}");
+            let (html, src_base, href_base) = rendered(client, &api)?;
+            (format!("{full_name}: {path}"), html, src_base, href_base)
+        }
+        Page::Wiki { page } => {
+            let raw = format!("https://raw.githubusercontent.com/wiki/{owner}/{name}/");
+            let markdown = client.get(&format!("{raw}{page}.md"), &[])?;
+            let html = markdown_to_html(client, &wiki_links(&markdown), &full_name)?;
+            (
+                format!("{} · {full_name} wiki", page.replace('-', " ")),
+                html,
+                Url::parse(&raw)?,
+                Url::parse(&format!("https://github.com/{owner}/{name}/wiki/"))?,
+            )
+        }
+    };
+
+    let mut content = String::new();
+    if matches!(page, Page::Readme { dir: None, .. }) && !description.is_empty() {
+        content.push_str(&format!(
+            "<p><em>{}</em></p>",
+            xhtml::escape_text(&description)
+        ));
+    }
+    content.push_str(&clean(&html, &src_base, &href_base));
+    Ok(Article {
+        title,
+        authors: str(&repo["owner"]["login"]),
+        published_at: str(&repo["pushed_at"]),
+        content,
+        site_name: "GitHub".into(),
+        keywords: repo["topics"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(str)
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// A README's or file's rendered HTML, and what its relative images and
+/// links are relative to: its raw file, and its page on GitHub.
+fn rendered(client: &Client, api: &str) -> Result<(String, Url, Url)> {
+    let file = client.get_json(api, &[])?;
+    let html = client.get(api, &[("Accept", HTML)])?;
+    let base = |field| {
+        Url::parse(&str(&file[field]))
+            .wrap_err_with(|| format!("The API gave no {field} for {api}"))
+    };
+    Ok((html, base("download_url")?, base("html_url")?))
+}
+
+/// Render Markdown as GitHub does, with `context` (`owner/repo`)
+/// for references to issues and the like.
+fn markdown_to_html(client: &Client, markdown: &str, context: &str) -> Result<String> {
+    let api = format!("{API}/markdown");
+    let _download = throttle::download();
+    let resp = ureq::post(&api)
+        .timeout(TIMEOUT)
+        .set("User-Agent", BROWSER_USER_AGENT)
+        .send_json(json!({ "text": markdown, "mode": "gfm", "context": context }))
+        .wrap_err_with(|| fetch_error(&api))?;
+    client.read(resp, &api)
+}
+
+/// Turn wiki links (`[[Page Name]]` or `[[Text|Page-Name]]`),
+/// which the Markdown API doesn't know, into Markdown ones.
+fn wiki_links(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut rest = markdown;
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start..].find("]]") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 2..start + end];
+        let (text, page) = inner.split_once('|').unwrap_or((inner, inner));
+        out.push_str(&format!(
+            "[{}]({})",
+            text.trim(),
+            page.trim().replace(' ', "-")
+        ));
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Drop the headings' icons and point links to headings at their ids,
+/// which GitHub prefixes with `user-content-` (and fixes up with JS).
+fn clean(html: &str, src_base: &Url, href_base: &Url) -> String {
+    let mut doc = Html::parse_fragment(html);
+    detach(&mut doc, "svg.octicon");
+    let ids: Vec<String> = doc
+        .select(&Selector::parse("[id]").expect("Valid selector"))
+        .filter_map(|el| el.value().attr("id").map(String::from))
+        .collect();
+    let sel = Selector::parse("a[href^='#']").expect("Valid selector");
+    let links: Vec<_> = doc.select(&sel).map(|el| el.id()).collect();
+    for id in links {
+        if let Some(mut node) = doc.tree.get_mut(id)
+            && let Node::Element(el) = node.value()
+        {
+            for (name, value) in el.attrs.iter_mut() {
+                let target = format!("user-content-{}", value.trim_start_matches('#'));
+                if &*name.local == "href" && ids.contains(&target) {
+                    *value = format!("#{target}").as_str().into();
+                }
+            }
+        }
+    }
+    absolutize(&mut doc, src_base, href_base);
+    doc.root_element().inner_html()
+}
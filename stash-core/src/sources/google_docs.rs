@@ -0,0 +1,227 @@
+//! Google Docs, published to the web (`docs.google.com/document/d/e/…/pub`)
+//! or shared publicly. Their HTML styles everything through generated
+//! classes (`.c3{font-weight:700}`), so it's rewritten as plain markup,
+//! with the classes' bold, italics, and superscripts kept as elements.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::{Result, eyre};
+use ego_tree::NodeRef;
+use scraper::{Html, Node, Selector};
+use url::Url;
+
+use super::Client;
+use crate::{Article, xhtml};
+
+/// Attributes worth keeping; the rest are styling.
+const ATTRS: &[&str] = &["href", "src", "alt", "title", "colspan", "rowspan", "id"];
+
+pub fn matches(url: &Url) -> bool {
+    export_url(url).is_some()
+}
+
+/// Where to get a document's HTML: the body of its published page,
+/// or the export of a document that's shared but not published.
+fn export_url(url: &Url) -> Option<String> {
+    if url.host_str()? != "docs.google.com" {
+        return None;
+    }
+    let segments: Vec<_> = url.path_segments()?.collect();
+    match segments[..] {
+        ["document", "d", "e", id, ..] => Some(format!(
+            "https://docs.google.com/document/d/e/{id}/pub?embedded=true"
+        )),
+        ["document", "d", id, "pub"] => Some(format!(
+            "https://docs.google.com/document/d/{id}/pub?embedded=true"
+        )),
+        ["document", "d", id, ..] if id.len() > 20 => Some(format!(
+            "https://docs.google.com/document/d/{id}/export?format=html"
+        )),
+        _ => None,
+    }
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let export = export_url(url).ok_or_else(|| eyre!("Not a Google Doc: {url}"))?;
+    let doc = Html::parse_document(&client.get(&export, &[])?);
+    let styles = class_styles(&doc);
+
+    let text_of = |sel: &str| {
+        doc.select(&Selector::parse(sel).expect("Valid selector"))
+            .next()
+            .map(|el| el.text().collect::<String>().trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    // The document's own title, if it uses the Title style,
+    // else the name of the file.
+    let title = text_of("p.title")
+        .or_else(|| text_of("head title"))
+        .map(|title| title.trim_end_matches(" - Google Docs").to_string())
+        .unwrap_or_default();
+    let authors = doc
+        .select(&Selector::parse("meta[name='author']").expect("Valid selector"))
+        .next()
+        .and_then(|meta| meta.value().attr("content"))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let body = doc
+        .select(&Selector::parse("body").expect("Valid selector"))
+        .next()
+        .ok_or_else(|| eyre!("The document has no body"))?;
+    let mut content = String::new();
+    for child in body.children() {
+        write(child, &styles, &mut content);
+    }
+    Ok(Article {
+        title,
+        authors,
+        content,
+        site_name: "Google Docs".into(),
+        lang: doc
+            .root_element()
+            .attr("lang")
+            .unwrap_or_default()
+            .to_string(),
+        ..Default::default()
+    })
+}
+
+#[derive(Default, Clone, Copy)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    superscript: bool,
+    subscript: bool,
+}
+
+/// What the stylesheet's classes do, of what's kept: `.c1{font-weight:700}`.
+fn class_styles(doc: &Html) -> HashMap<String, Style> {
+    let css: String = doc
+        .select(&Selector::parse("style").expect("Valid selector"))
+        .flat_map(|style| style.text())
+        .collect();
+    let mut styles = HashMap::new();
+    for rule in css.split('}') {
+        let Some((selectors, declarations)) = rule.split_once('{') else {
+            continue;
+        };
+        let declarations = declarations.replace(' ', "");
+        let style = Style {
+            bold: declarations.contains("font-weight:700")
+                || declarations.contains("font-weight:bold"),
+            italic: declarations.contains("font-style:italic"),
+            superscript: declarations.contains("vertical-align:super"),
+            subscript: declarations.contains("vertical-align:sub"),
+        };
+        for selector in selectors.split(',') {
+            if let Some(class) = selector.trim().strip_prefix('.')
+                && class
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            {
+                let entry: &mut Style = styles.entry(class.to_string()).or_default();
+                entry.bold |= style.bold;
+                entry.italic |= style.italic;
+                entry.superscript |= style.superscript;
+                entry.subscript |= style.subscript;
+            }
+        }
+    }
+    styles
+}
+
+fn write(node: NodeRef<Node>, styles: &HashMap<String, Style>, out: &mut String) {
+    match node.value() {
+        Node::Text(text) => out.push_str(&xhtml::escape_text(text)),
+        Node::Element(el) => {
+            let name = el.name();
+            if matches!(name, "style" | "script" | "meta" | "link" | "title") {
+                return;
+            }
+            // The title's the book's.
+            if el.has_class("title", scraper::CaseSensitivity::CaseSensitive) {
+                return;
+            }
+            let mut style = Style::default();
+            for class in el.classes() {
+                if let Some(found) = styles.get(class) {
+                    style.bold |= found.bold;
+                    style.italic |= found.italic;
+                    style.superscript |= found.superscript;
+                    style.subscript |= found.subscript;
+                }
+            }
+            // Headings are bold already.
+            let is_heading = |node: NodeRef<Node>| {
+                node.value().as_element().is_some_and(|el| {
+                    matches!(el.name(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th")
+                })
+            };
+            style.bold &= !is_heading(node) && !node.ancestors().any(is_heading);
+
+            let mut wrappers = vec![];
+            if name != "span" {
+                wrappers.push(name);
+            }
+            for (applies, tag) in [
+                (style.superscript, "sup"),
+                (style.subscript, "sub"),
+                (style.bold, "strong"),
+                (style.italic, "em"),
+            ] {
+                if applies {
+                    wrappers.push(tag);
+                }
+            }
+            let void = matches!(name, "img" | "br" | "hr" | "col" | "wbr");
+            if let Some((first, rest)) = wrappers.split_first() {
+                out.push('<');
+                out.push_str(first);
+                for (attr, value) in el.attrs() {
+                    if !ATTRS.contains(&attr) {
+                        continue;
+                    }
+                    let value = match attr {
+                        "href" => unwrap_redirect(value),
+                        _ => value.to_string(),
+                    };
+                    out.push_str(&format!(" {attr}=\"{}\"", xhtml::escape_attr(&value)));
+                }
+                if void {
+                    out.push_str("/>");
+                    return;
+                }
+                out.push('>');
+                for tag in rest {
+                    out.push_str(&format!("<{tag}>"));
+                }
+            }
+            for child in node.children() {
+                write(child, styles, out);
+            }
+            for tag in wrappers.iter().rev() {
+                out.push_str(&format!("</{tag}>"));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Links in docs go through Google's redirects, `google.com/url?q=…`.
+fn unwrap_redirect(href: &str) -> String {
+    Url::parse(href)
+        .ok()
+        .filter(|url| {
+            url.host_str()
+                .is_some_and(|host| host == "www.google.com" || host == "google.com")
+                && url.path() == "/url"
+        })
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(name, _)| name == "q")
+                .map(|(_, target)| target.into_owned())
+        })
+        .unwrap_or_else(|| href.to_string())
+}
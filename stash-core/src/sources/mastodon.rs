@@ -0,0 +1,145 @@
+//! Mastodon threads (and those of servers with its API, like Pleroma's),
+//! read from the server's public API: the post, the posts it replies to,
+//! and the replies to it, of which the author's own are kept.
+
+use color_eyre::eyre::{Result, bail, eyre};
+use scraper::{ElementRef, Html};
+use serde_json::Value;
+use url::Url;
+
+use super::{Client, headline, str};
+use crate::{Article, xhtml};
+
+pub fn matches(url: &Url) -> bool {
+    status(url).is_some()
+}
+
+/// The post ID in `server/@user/ID` and `server/users/user/statuses/ID` URLs.
+fn status(url: &Url) -> Option<String> {
+    let segments: Vec<_> = url.path_segments()?.collect();
+    let id = match segments[..] {
+        [user, id] if user.len() > 1 && user.starts_with('@') => id,
+        ["users", _, "statuses", id] => id,
+        _ => return None,
+    };
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then(|| id.to_string())
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let id = status(url).ok_or_else(|| eyre!("Not a Mastodon post: {url}"))?;
+    let api = format!(
+        "{}://{}/api/v1/statuses/{id}",
+        url.scheme(),
+        url.authority()
+    );
+    let post = client.get_json(&api, &[])?;
+    if post["id"].is_null() {
+        bail!("Not a Mastodon post: {}", str(&post["error"]));
+    }
+    let context = client.get_json(&format!("{api}/context"), &[])?;
+    let author = str(&post["account"]["id"]);
+    let by_author = |post: &&Value| str(&post["account"]["id"]) == author;
+
+    // The author's posts leading up to this one, back to where
+    // they started the thread (or replied to someone else)...
+    let ancestors: Vec<_> = context["ancestors"].as_array().cloned().unwrap_or_default();
+    let start = ancestors
+        .iter()
+        .rposition(|post| !by_author(&post))
+        .map_or(0, |i| i + 1);
+    let mut thread: Vec<&Value> = ancestors[start..].iter().collect();
+    thread.push(&post);
+    // ...and the replies they made to those, in order.
+    for reply in context["descendants"].as_array().into_iter().flatten() {
+        let parent = str(&reply["in_reply_to_id"]);
+        if by_author(&reply) && thread.iter().any(|post| str(&post["id"]) == parent) {
+            thread.push(reply);
+        }
+    }
+
+    let first = thread[0];
+    let account = &first["account"];
+    let name = str(&account["display_name"]);
+    let handle = str(&account["acct"]);
+    let mut content = String::new();
+    for (i, post) in thread.iter().enumerate() {
+        if i > 0 {
+            content.push_str("<hr/>");
+        }
+        content.push_str(&render(post));
+    }
+    // Its first paragraph, without the markup.
+    let text = Html::parse_fragment(&str(&first["content"]))
+        .root_element()
+        .children()
+        .filter_map(ElementRef::wrap)
+        .map(|el| el.text().collect::<String>())
+        .next()
+        .unwrap_or_default();
+    Ok(Article {
+        title: headline(&str(&first["spoiler_text"]))
+            .or_else(|| headline(&text))
+            .unwrap_or_else(|| format!("Thread by {name}")),
+        authors: if name.is_empty() {
+            format!("@{handle}")
+        } else {
+            format!("{name} (@{handle})")
+        },
+        published_at: str(&first["created_at"]),
+        content,
+        site_name: url.host_str().unwrap_or_default().to_string(),
+        lang: str(&first["language"]),
+        keywords: first["tags"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|tag| str(&tag["name"]))
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// A post's content and media, after its content warning if it has one.
+fn render(post: &Value) -> String {
+    let mut html = String::new();
+    let warning = str(&post["spoiler_text"]);
+    if !warning.is_empty() {
+        html.push_str(&format!(
+            "<p><strong>Content warning: {}</strong></p>",
+            xhtml::escape_text(&warning)
+        ));
+    }
+    html.push_str(&str(&post["content"]));
+    for media in post["media_attachments"].as_array().into_iter().flatten() {
+        // Videos and GIFs get their preview image.
+        let src = match str(&media["type"]).as_str() {
+            "image" => str(&media["url"]),
+            _ => str(&media["preview_url"]),
+        };
+        if src.is_empty() {
+            continue;
+        }
+        let description = str(&media["description"]);
+        html.push_str(&format!(
+            "<figure><img src=\"{}\" alt=\"{}\"/>",
+            xhtml::escape_attr(&src),
+            xhtml::escape_attr(&description)
+        ));
+        if !description.is_empty() {
+            html.push_str(&format!(
+                "<figcaption>{}</figcaption>",
+                xhtml::escape_text(&description)
+            ));
+        }
+        html.push_str("</figure>");
+    }
+    let quoted = &post["quote"]["quoted_status"];
+    if !quoted.is_null() {
+        html.push_str(&format!(
+            "<blockquote><p><strong>{}</strong></p>{}</blockquote>",
+            xhtml::escape_text(&str(&quoted["account"]["display_name"])),
+            str(&quoted["content"]),
+        ));
+    }
+    html
+}
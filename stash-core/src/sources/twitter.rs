@@ -0,0 +1,267 @@
+//! Twitter/X threads. The site is an app that renders nothing without
+//! JavaScript, so a thread is put together from a Nitter instance's page
+//! for the linked tweet, if one's configured, or else tweet by tweet from
+//! Twitter's embeds, following replies back to the start of the thread.
+//! Either way it's the author's own tweets that are kept, as one article.
+
+use std::f64::consts::PI;
+
+use chrono::NaiveDateTime;
+use color_eyre::eyre::{Result, bail, eyre};
+use scraper::{ElementRef, Html};
+use serde_json::Value;
+use url::Url;
+
+use super::{Client, headline, str};
+use crate::{Article, selector, xhtml};
+
+/// Threads longer than this are cut off at their start.
+const MAX_TWEETS: usize = 100;
+
+struct Tweet {
+    name: String,
+    handle: String,
+    date: String,
+    /// The tweet's text and media, as HTML.
+    html: String,
+    /// Text only, for the title.
+    text: String,
+}
+
+pub fn matches(url: &Url) -> bool {
+    status(url).is_some()
+}
+
+/// The user and the tweet ID in `x.com/user/status/ID` (or `twitter.com`) URLs.
+fn status(url: &Url) -> Option<(String, String)> {
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let host = host.strip_prefix("mobile.").unwrap_or(host);
+    if !matches!(host, "x.com" | "twitter.com") {
+        return None;
+    }
+    let segments: Vec<_> = url.path_segments()?.collect();
+    match segments[..] {
+        [user, "status" | "statuses", id, ..] if id.chars().all(|c| c.is_ascii_digit()) => {
+            Some((user.to_string(), id.to_string()))
+        }
+        _ => None,
+    }
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let (user, id) = status(url).ok_or_else(|| eyre!("Not a tweet: {url}"))?;
+    let tweets = match &client.extractor.nitter {
+        Some(instance) => nitter(client, instance, &user, &id)?,
+        None => embeds(client, &id)?,
+    };
+    let Some(first) = tweets.first() else {
+        bail!("No tweets found");
+    };
+
+    let mut content = String::new();
+    for (i, tweet) in tweets.iter().enumerate() {
+        if i > 0 {
+            content.push_str("<hr/>");
+        }
+        content.push_str(&tweet.html);
+    }
+    Ok(Article {
+        title: headline(&first.text).unwrap_or_else(|| format!("Thread by {}", first.name)),
+        authors: format!("{} (@{})", first.name, first.handle),
+        published_at: first.date.clone(),
+        content,
+        site_name: "X (Twitter)".into(),
+        ..Default::default()
+    })
+}
+
+/// The thread on a Nitter instance's page for one of its tweets:
+/// the author's tweets leading up to it, it, and the author's replies to it.
+fn nitter(client: &Client, instance: &str, user: &str, id: &str) -> Result<Vec<Tweet>> {
+    let instance =
+        Url::parse(instance).map_err(|err| eyre!("`nitter` in config.toml isn't a URL: {err}"))?;
+    let page_url = instance.join(&format!("/{user}/status/{id}"))?;
+    let page = Html::parse_document(&client.get(page_url.as_str(), &[])?);
+    let item = selector(".main-thread .timeline-item").expect("valid selector");
+    let main = selector(".main-tweet .timeline-item").expect("valid selector");
+    let Some(author) = page
+        .select(&main)
+        .next()
+        .and_then(|tweet| text_of(tweet, ".username"))
+    else {
+        bail!("No tweet found on {page_url}");
+    };
+
+    let mut tweets: Vec<_> = page
+        .select(&item)
+        .filter(|tweet| text_of(*tweet, ".username").as_ref() == Some(&author))
+        .filter_map(|tweet| nitter_tweet(tweet, &instance))
+        .collect();
+    if tweets.len() > MAX_TWEETS {
+        tweets.drain(..tweets.len() - MAX_TWEETS);
+    }
+    Ok(tweets)
+}
+
+fn nitter_tweet(tweet: ElementRef, instance: &Url) -> Option<Tweet> {
+    let content = tweet.select(&selector(".tweet-content").ok()?).next()?;
+    // Nitter's own links are to its versions of Twitter's pages.
+    let mut html = format!(
+        "<p>{}</p>",
+        content
+            .inner_html()
+            .replace("href=\"/", "href=\"https://x.com/")
+            .replace('\n', "<br/>")
+    );
+    let images = selector(".attachments .still-image, .attachments video").ok()?;
+    for media in tweet.select(&images) {
+        let value = media.value();
+        let Some(src) = value.attr("href").or(value.attr("poster")) else {
+            continue;
+        };
+        if let Ok(src) = instance.join(src) {
+            html.push_str(&format!(
+                "<figure><img src=\"{}\" alt=\"\"/></figure>",
+                xhtml::escape_attr(src.as_str())
+            ));
+        }
+    }
+    if let Some(quote) = tweet.select(&selector(".quote").ok()?).next() {
+        html.push_str(&format!(
+            "<blockquote><p><strong>{}</strong></p><p>{}</p></blockquote>",
+            xhtml::escape_text(&text_of(quote, ".fullname").unwrap_or_default()),
+            xhtml::escape_text(&text_of(quote, ".quote-text").unwrap_or_default()),
+        ));
+    }
+    Some(Tweet {
+        name: text_of(tweet, ".fullname").unwrap_or_default(),
+        handle: text_of(tweet, ".username")?
+            .trim_start_matches('@')
+            .to_string(),
+        // e.g. "Dec 4, 2023 · 5:30 PM UTC"
+        date: tweet
+            .select(&selector(".tweet-date a").ok()?)
+            .next()
+            .and_then(|date| date.value().attr("title"))
+            .and_then(|date| NaiveDateTime::parse_from_str(date, "%b %d, %Y · %I:%M %p UTC").ok())
+            .map(|date| date.and_utc().to_rfc3339())
+            .unwrap_or_default(),
+        text: content.text().collect(),
+        html,
+    })
+}
+
+fn text_of(el: ElementRef, sel: &str) -> Option<String> {
+    let found = el.select(&selector(sel).ok()?).next()?;
+    Some(found.text().collect::<String>().trim().to_string())
+}
+
+/// The thread up to a tweet, from the JSON behind Twitter's embeds: the
+/// tweet, the one it replies to if that's by the same author, and so on.
+/// Later tweets in the thread aren't in the embeds, so they're missed.
+fn embeds(client: &Client, id: &str) -> Result<Vec<Tweet>> {
+    let mut tweets = vec![];
+    let mut next = Some(id.to_string());
+    while let Some(id) = next.take() {
+        let tweet = client.get_json(
+            &format!(
+                "https://cdn.syndication.twimg.com/tweet-result?id={id}&token={}",
+                token(&id)
+            ),
+            &[],
+        )?;
+        if tweet["__typename"] == "TweetTombstone" || tweet["user"].is_null() {
+            if tweets.is_empty() {
+                bail!("The tweet isn't available");
+            }
+            break;
+        }
+        let handle = str(&tweet["user"]["screen_name"]);
+        if tweets.len() < MAX_TWEETS && str(&tweet["in_reply_to_screen_name"]) == handle {
+            next = Some(str(&tweet["in_reply_to_status_id_str"])).filter(|id| !id.is_empty());
+        }
+        tweets.push(embedded_tweet(&tweet));
+    }
+    tweets.reverse();
+    Ok(tweets)
+}
+
+fn embedded_tweet(tweet: &Value) -> Tweet {
+    let mut text = tweet["text"].as_str().unwrap_or_default().to_string();
+    // Only the part of the text that's shown, without the mentions
+    // of who it replies to or the links to its own media.
+    if let Some([start, end]) = tweet["display_text_range"]
+        .as_array()
+        .map(|range| range.iter().filter_map(Value::as_u64).collect::<Vec<_>>())
+        .as_deref()
+    {
+        text = text
+            .chars()
+            .skip(*start as usize)
+            .take((end - start) as usize)
+            .collect();
+    }
+
+    let mut html = xhtml::escape_text(&text);
+    for link in tweet["entities"]["urls"].as_array().into_iter().flatten() {
+        let expanded = str(&link["expanded_url"]);
+        html = html.replace(
+            &str(&link["url"]),
+            &format!(
+                "<a href=\"{}\">{}</a>",
+                xhtml::escape_attr(&expanded),
+                xhtml::escape_text(&str(&link["display_url"]))
+            ),
+        );
+    }
+    let mut html = format!("<p>{}</p>", html.trim().replace('\n', "<br/>"));
+    for media in tweet["mediaDetails"].as_array().into_iter().flatten() {
+        html.push_str(&format!(
+            "<figure><img src=\"{}\" alt=\"{}\"/></figure>",
+            xhtml::escape_attr(&str(&media["media_url_https"])),
+            xhtml::escape_attr(&str(&media["ext_alt_text"])),
+        ));
+    }
+    let quoted = &tweet["quoted_tweet"];
+    if !quoted.is_null() {
+        html.push_str(&format!(
+            "<blockquote><p><strong>{}</strong></p><p>{}</p></blockquote>",
+            xhtml::escape_text(&str(&quoted["user"]["name"])),
+            xhtml::escape_text(&str(&quoted["text"])),
+        ));
+    }
+
+    Tweet {
+        name: str(&tweet["user"]["name"]),
+        handle: str(&tweet["user"]["screen_name"]),
+        date: str(&tweet["created_at"]),
+        html,
+        text,
+    }
+}
+
+/// The token the embeds' JSON is requested with,
+/// computed from the tweet ID the way Twitter's embed script does:
+/// `(id / 1e15 * π).toString(36)`, without zeros or the point.
+fn token(id: &str) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let n = id.parse::<f64>().unwrap_or_default() / 1e15 * PI;
+    let (mut whole, mut fraction) = (n.trunc() as u64, n.fract());
+    let mut token = vec![];
+    while whole > 0 {
+        token.push(DIGITS[(whole % 36) as usize]);
+        whole /= 36;
+    }
+    token.reverse();
+    for _ in 0..10 {
+        fraction *= 36.0;
+        token.push(DIGITS[fraction.trunc() as usize]);
+        fraction = fraction.fract();
+    }
+    token
+        .into_iter()
+        .filter(|c| *c != b'0')
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,154 @@
+//! Wikipedia articles, from the Parsoid HTML of Wikimedia's REST API
+//! rather than the page, which is cluttered with navigation and edit links.
+//! The sections, infobox (a table), and footnotes are kept as they are,
+//! with links to other articles made absolute and those to the article's
+//! own footnotes made to work within the book.
+
+use color_eyre::eyre::{Result, eyre};
+use scraper::{Html, Node, Selector};
+use url::Url;
+
+use super::{Client, detach};
+use crate::{Article, config::Wikipedia};
+
+/// Maintenance notices, navigation boxes, and the like,
+/// which are only of use on the site.
+const STRIP: &str = "style, link, .navbox, .navbox-styles, .vertical-navbox, .ambox, \
+    .metadata, .noprint, .mw-empty-elt, .sistersitebox, .portalbox, \
+    .authority-control, .mw-editsection, span[typeof='mw:FallbackId']";
+
+pub fn matches(url: &Url) -> bool {
+    page(url).is_some()
+}
+
+/// The wiki's host (e.g. `en.wikipedia.org`, for mobile pages too)
+/// and the article's title, as it is in `wiki/Title` URLs.
+fn page(url: &Url) -> Option<(String, String)> {
+    let host = url.host_str()?;
+    let lang = host.strip_suffix(".wikipedia.org")?;
+    let lang = lang.strip_suffix(".m").unwrap_or(lang);
+    if lang.is_empty() || lang.contains('.') {
+        return None;
+    }
+    let title = url.path().strip_prefix("/wiki/")?;
+    (!title.is_empty()).then(|| (format!("{lang}.wikipedia.org"), title.to_string()))
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let extractor = client.extractor;
+    let (host, title) = page(url).ok_or_else(|| eyre!("Not a Wikipedia article: {url}"))?;
+    let html = client.get(
+        &format!("https://{host}/api/rest_v1/page/html/{title}"),
+        &[("Accept", "text/html; charset=utf-8")],
+    )?;
+    let mut doc = Html::parse_document(&html);
+
+    let text_of = |doc: &Html, sel: &str| {
+        doc.select(&Selector::parse(sel).expect("Valid selector"))
+            .next()
+            .map(|el| el.text().collect::<String>().trim().to_string())
+            .unwrap_or_default()
+    };
+    let attr_of = |doc: &Html, sel: &str, attr: &str| {
+        doc.select(&Selector::parse(sel).expect("Valid selector"))
+            .next()
+            .and_then(|el| el.value().attr(attr))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let name = text_of(&doc, "head title");
+    let modified = attr_of(&doc, "meta[property='dc:modified']", "content");
+    let lang = attr_of(&doc, "html", "lang");
+
+    let mut strip = STRIP.to_string();
+    if !extractor.wikipedia.images {
+        strip.push_str(", figure, .gallery, span[typeof^='mw:File'], img");
+    }
+    detach(&mut doc, &strip);
+    rewrite(&mut doc, &host, &title, &extractor.wikipedia);
+
+    let body = doc
+        .select(&Selector::parse("body").expect("Valid selector"))
+        .next()
+        .ok_or_else(|| eyre!("The article's HTML has no body"))?;
+    Ok(Article {
+        title: name.replace('_', " "),
+        authors: "Wikipedia contributors".into(),
+        // The last edit, which is the version that's stashed.
+        published_at: modified,
+        content: body.inner_html(),
+        site_name: "Wikipedia".into(),
+        lang,
+        rights: "CC BY-SA 4.0".into(),
+        ..Default::default()
+    })
+}
+
+/// Point links and images at absolute URLs: links to other articles
+/// (`./Other_article`) at the wiki, links to this one's footnotes
+/// (`./This_article#cite_note-1`) at the footnotes in the book,
+/// and images at thumbnails of the configured width.
+fn rewrite(doc: &mut Html, host: &str, title: &str, settings: &Wikipedia) {
+    let sel = Selector::parse("a[href], img[src]").expect("Valid selector");
+    let ids: Vec<_> = doc.select(&sel).map(|el| el.id()).collect();
+    let own = format!("./{title}#");
+    for id in ids {
+        let Some(mut node) = doc.tree.get_mut(id) else {
+            continue;
+        };
+        let Node::Element(el) = node.value() else {
+            continue;
+        };
+        let file_width = el
+            .attr("data-file-width")
+            .and_then(|width| width.parse::<u32>().ok());
+        el.attrs.retain(|(name, _)| &*name.local != "srcset");
+        for (name, value) in el.attrs.iter_mut() {
+            let current: &str = value;
+            let rewritten = match &*name.local {
+                "href" if current.starts_with(&own) || is_note(current) => {
+                    format!("#{}", current.split_once('#').unwrap_or_default().1)
+                }
+                "href" if current.starts_with("./") => {
+                    format!("https://{host}/wiki/{}", &current[2..])
+                }
+                "href" | "src" if current.starts_with("//") => {
+                    let url = format!("https:{current}");
+                    if &*name.local == "src" {
+                        thumbnail(&url, settings.image_width, file_width)
+                    } else {
+                        url
+                    }
+                }
+                _ => continue,
+            };
+            *value = rewritten.as_str().into();
+        }
+    }
+}
+
+/// Whether a link is to a footnote or back from one, which are
+/// to the article's own title (however that's encoded).
+fn is_note(href: &str) -> bool {
+    href.starts_with("./")
+        && href
+            .split_once('#')
+            .is_some_and(|(_, id)| id.starts_with("cite_note") || id.starts_with("cite_ref"))
+}
+
+/// A thumbnail URL (`…/thumb/a/ab/File.jpg/220px-File.jpg`) for another
+/// width, if the original is wider than that.
+fn thumbnail(url: &str, width: u32, file_width: Option<u32>) -> String {
+    if file_width.is_some_and(|file_width| file_width <= width) || !url.contains("/thumb/") {
+        return url.to_string();
+    }
+    let Some((dir, file)) = url.rsplit_once('/') else {
+        return url.to_string();
+    };
+    match file.split_once("px-") {
+        Some((current, name)) if current.chars().all(|c| c.is_ascii_digit()) => {
+            format!("{dir}/{width}px-{name}")
+        }
+        _ => url.to_string(),
+    }
+}
@@ -0,0 +1,280 @@
+//! YouTube videos, as their transcripts: the captions (preferring those
+//! written by people to automatic ones) in paragraphs with timestamps
+//! linking back to the video, under the video's chapters if it has them.
+
+use color_eyre::eyre::{Result, bail, eyre};
+use serde_json::Value;
+use url::Url;
+
+use super::{Client, str};
+use crate::{Article, logging, xhtml};
+
+/// Skips the cookie consent page served in the EU.
+const CONSENT: &str = "CONSENT=YES+cb; SOCS=CAI";
+
+/// Paragraphs are broken at the end of a sentence once they're this long,
+/// in seconds, or regardless once they're twice that.
+const PARAGRAPH_LENGTH: u64 = 45;
+
+pub fn matches(url: &Url) -> bool {
+    video_id(url).is_some()
+}
+
+/// The video ID in `youtube.com/watch?v=…`, `youtu.be/…`,
+/// and `youtube.com/shorts/…` (or `live/` or `embed/`) URLs.
+fn video_id(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let host = host.strip_prefix("m.").unwrap_or(host);
+    let mut segments = url.path_segments()?;
+    let id = match host {
+        "youtu.be" => segments.next()?.to_string(),
+        "youtube.com" | "music.youtube.com" | "youtube-nocookie.com" => match segments.next()? {
+            "watch" => url
+                .query_pairs()
+                .find(|(name, _)| name == "v")
+                .map(|(_, id)| id.into_owned())?,
+            "shorts" | "live" | "embed" | "v" => segments.next()?.to_string(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    (id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+    .then_some(id)
+}
+
+pub fn fetch(url: &Url, client: &Client) -> Result<Article> {
+    let id = video_id(url).ok_or_else(|| eyre!("Not a YouTube video: {url}"))?;
+    let watch = format!("https://www.youtube.com/watch?v={id}");
+    let page = client.get(&format!("{watch}&hl=en"), &[("Cookie", CONSENT)])?;
+    let player = embedded_json(&page, "ytInitialPlayerResponse")
+        .ok_or_else(|| eyre!("No player data in the video's page"))?;
+    let details = &player["videoDetails"];
+    if details.is_null() {
+        let status = &player["playabilityStatus"];
+        bail!(
+            "The video isn't available: {}",
+            first(&[&status["reason"], &status["status"]])
+        );
+    }
+    let micro = &player["microformat"]["playerMicroformatRenderer"];
+
+    let mut chapters = embedded_json(&page, "ytInitialData")
+        .map(|data| {
+            let mut chapters = vec![];
+            find_chapters(&data, &mut chapters);
+            chapters
+        })
+        .unwrap_or_default();
+    let description = str(&details["shortDescription"]);
+    if chapters.is_empty() {
+        chapters = description_chapters(&description);
+    }
+    chapters.sort_by_key(|chapter| chapter.0);
+    chapters.dedup_by_key(|chapter| chapter.0);
+
+    let track = caption_track(&player);
+    let cues = match track {
+        Some(track) => transcript(client, &str(&track["baseUrl"]))?,
+        None => {
+            logging::warn("The video has no captions, so only its description is kept.");
+            vec![]
+        }
+    };
+
+    let length = str(&details["lengthSeconds"]).parse().unwrap_or(0);
+    let mut content = format!(
+        "<p><img src=\"https://i.ytimg.com/vi/{id}/hqdefault.jpg\" alt=\"\"/></p>\
+         <p><a href=\"{}\">Watch on YouTube</a> ({})</p>",
+        xhtml::escape_attr(&watch),
+        timestamp(length),
+    );
+    if !description.is_empty() {
+        content.push_str("<blockquote>");
+        for paragraph in description.split("\n\n") {
+            let lines: Vec<_> = paragraph.lines().map(xhtml::escape_text).collect();
+            content.push_str(&format!("<p>{}</p>", lines.join("<br/>")));
+        }
+        content.push_str("</blockquote>");
+    }
+    content.push_str(&render(&watch, &cues, &chapters));
+
+    Ok(Article {
+        title: str(&details["title"]),
+        authors: first(&[&details["author"], &micro["ownerChannelName"]]),
+        published_at: first(&[&micro["publishDate"], &micro["uploadDate"]]),
+        content,
+        site_name: "YouTube".into(),
+        lang: track
+            .map(|track| str(&track["languageCode"]))
+            .unwrap_or_default(),
+        keywords: details["keywords"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(str)
+            .take(10)
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// The first of the values that isn't empty.
+fn first(values: &[&Value]) -> String {
+    values
+        .iter()
+        .map(|value| str(value))
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+/// A JSON object assigned to a variable in one of the page's scripts,
+/// e.g. `var ytInitialData = {…};`.
+fn embedded_json(page: &str, name: &str) -> Option<Value> {
+    let start = page.find(&format!("{name} = "))? + name.len() + 3;
+    serde_json::Deserializer::from_str(&page[start..])
+        .into_iter::<Value>()
+        .next()?
+        .ok()
+}
+
+/// The captions to use: preferably not automatic ones.
+fn caption_track(player: &Value) -> Option<&Value> {
+    let tracks =
+        player["captions"]["playerCaptionsTracklistRenderer"]["captionTracks"].as_array()?;
+    tracks
+        .iter()
+        .find(|track| track["kind"] != "asr")
+        .or(tracks.first())
+}
+
+/// A caption: when it starts, in seconds, and its text.
+type Cue = (u64, String);
+
+fn transcript(client: &Client, base_url: &str) -> Result<Vec<Cue>> {
+    let captions = client.get_json(&format!("{base_url}&fmt=json3"), &[("Cookie", CONSENT)])?;
+    Ok(captions["events"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|event| {
+            let text: String = event["segs"]
+                .as_array()?
+                .iter()
+                .map(|seg| seg["utf8"].as_str().unwrap_or_default())
+                .collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let start = event["tStartMs"].as_u64()? / 1000;
+            (!text.is_empty()).then_some((start, text))
+        })
+        .collect())
+}
+
+/// A chapter: when it starts, in seconds, and its title.
+type Chapter = (u64, String);
+
+/// The chapters in the page's data, wherever they are in it.
+fn find_chapters(data: &Value, chapters: &mut Vec<Chapter>) {
+    match data {
+        Value::Object(object) => {
+            if let Some(chapter) = object.get("chapterRenderer") {
+                let title = str(&chapter["title"]["simpleText"]);
+                if let Some(start) = chapter["timeRangeStartMillis"].as_u64() {
+                    chapters.push((start / 1000, title));
+                }
+            }
+            for value in object.values() {
+                find_chapters(value, chapters);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                find_chapters(value, chapters);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Chapters listed in the description, as lines like `12:03 Some topic`.
+/// As on YouTube, they only count if the first is at `0:00`.
+fn description_chapters(description: &str) -> Vec<Chapter> {
+    let chapters: Vec<Chapter> = description
+        .lines()
+        .filter_map(|line| {
+            let (time, title) = line.trim().split_once(char::is_whitespace)?;
+            let time = time.trim_matches(|c| matches!(c, '(' | ')' | '[' | ']'));
+            let title = title.trim_start_matches(|c: char| {
+                c.is_whitespace() || matches!(c, '-' | '–' | '—' | ':' | '|')
+            });
+            Some((parse_timestamp(time)?, title.trim().to_string()))
+        })
+        .collect();
+    match chapters.first() {
+        Some((0, _)) if chapters.len() > 1 => chapters,
+        _ => vec![],
+    }
+}
+
+/// `1:02:03` or `2:03` in seconds.
+fn parse_timestamp(time: &str) -> Option<u64> {
+    let parts: Vec<u64> = time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    if !(2..=3).contains(&parts.len()) || parts[1..].iter().any(|part| *part >= 60) {
+        return None;
+    }
+    Some(parts.iter().fold(0, |total, part| total * 60 + part))
+}
+
+fn timestamp(secs: u64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+/// The transcript as paragraphs, each starting with a link to its
+/// moment in the video, under a heading per chapter.
+fn render(watch: &str, cues: &[Cue], chapters: &[Chapter]) -> String {
+    let link = |secs: u64| {
+        format!(
+            "<a href=\"{}&amp;t={secs}s\">{}</a>",
+            xhtml::escape_attr(watch),
+            timestamp(secs)
+        )
+    };
+    let mut html = String::new();
+    let mut chapters = chapters.iter().peekable();
+    let mut paragraph: Option<(u64, Vec<&str>)> = None;
+    let close = |html: &mut String, paragraph: &mut Option<(u64, Vec<&str>)>| {
+        if let Some((start, texts)) = paragraph.take() {
+            html.push_str(&format!(
+                "<p>{} {}</p>",
+                link(start),
+                xhtml::escape_text(&texts.join(" "))
+            ));
+        }
+    };
+    for (start, text) in cues {
+        if let Some((_, title)) = chapters.next_if(|(at, _)| at <= start) {
+            close(&mut html, &mut paragraph);
+            html.push_str(&format!("<h2>{}</h2>", xhtml::escape_text(title)));
+        }
+        let (para_start, texts) = paragraph.get_or_insert((*start, vec![]));
+        texts.push(text);
+        let elapsed = start.saturating_sub(*para_start);
+        let sentence_end = text.ends_with(['.', '?', '!', '。', '？', '！']);
+        if (elapsed >= PARAGRAPH_LENGTH && sentence_end) || elapsed >= 2 * PARAGRAPH_LENGTH {
+            close(&mut html, &mut paragraph);
+        }
+    }
+    close(&mut html, &mut paragraph);
+    html
+}
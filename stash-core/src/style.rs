@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Result, eyre};
+use epub_builder::{EpubBuilder, ZipLibrary};
+
+use crate::config::{Config, Font};
+
+/// Add the stylesheet, the font size, and any fonts to the EPUB.
+///
+/// Each font is packaged under `fonts/` and gets an `@font-face` rule
+/// prepended to the user's stylesheet, so the stylesheet can refer to
+/// it by its family name.
+pub fn apply(builder: &mut EpubBuilder<ZipLibrary>, config: &Config) -> Result<()> {
+    let mut css = String::new();
+    for font in &config.fonts {
+        let path = font.path();
+        let fname = path
+            .file_name()
+            .and_then(|fname| fname.to_str())
+            .ok_or_else(|| eyre!("Invalid font path: {}", path.display()))?;
+        let href = format!("fonts/{fname}");
+        builder.add_resource(&href, fs_err::File::open(&path)?, font_mime(&path)?)?;
+        css.push_str(&font_face(font, &href));
+    }
+
+    // Before the stylesheet, so it can still restyle the body.
+    if let Some(size) = &config.font_size {
+        css.push_str(&format!("body {{\n  font-size: {size};\n}}\n\n"));
+    }
+    if let Some(path) = config.stylesheet() {
+        css.push_str(&fs_err::read_to_string(path)?);
+    }
+    if !css.is_empty() {
+        builder.stylesheet(css.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn font_face(font: &Font, href: &str) -> String {
+    format!(
+        "@font-face {{\n  font-family: \"{}\";\n  font-weight: {};\n  font-style: {};\n  src: url(\"{href}\");\n}}\n\n",
+        font.family,
+        font.weight.as_deref().unwrap_or("normal"),
+        font.style.as_deref().unwrap_or("normal"),
+    )
+}
+
+fn font_mime(path: &Path) -> Result<&'static str> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match ext.as_deref() {
+        Some("ttf") => Ok("font/ttf"),
+        Some("otf") => Ok("font/otf"),
+        Some("woff") => Ok("font/woff"),
+        Some("woff2") => Ok("font/woff2"),
+        _ => Err(eyre!(
+            "Unsupported font type (expected ttf, otf, woff, or woff2): {}",
+            path.display()
+        )),
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use scraper::Html;
+use serde_json::{Value, json};
+
+use crate::{Article, config::Summary};
+
+/// Local models can be slow.
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Only send the start of very long articles,
+/// to stay within models' context windows.
+const MAX_CHARS: usize = 24_000;
+
+const DEFAULT_PROMPT: &str = "Summarize the following article in a short paragraph \
+    of two to four sentences, for someone deciding whether to read it. \
+    Reply with the summary only.";
+
+/// Generate a short abstract of an article with a model
+/// behind an OpenAI-compatible chat completions API.
+pub fn summarize(config: &Summary, article: &Article) -> Result<String> {
+    let text: String = Html::parse_fragment(&article.content)
+        .root_element()
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let text: String = text.chars().take(MAX_CHARS).collect();
+
+    let url = format!("{}/chat/completions", config.endpoint.trim_end_matches('/'));
+    let mut req = ureq::post(&url).timeout(TIMEOUT);
+    if let Some(key) = &config.api_key {
+        req = req.set("Authorization", &format!("Bearer {key}"));
+    }
+    let resp: Value = req
+        .send_json(json!({
+            "model": config.model,
+            "messages": [
+                {"role": "system", "content": config.prompt.as_deref().unwrap_or(DEFAULT_PROMPT)},
+                {"role": "user", "content": format!("{}\n\n{text}", article.title)},
+            ],
+        }))
+        .wrap_err("Summary request failed")?
+        .into_json()?;
+    let summary = resp["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or_else(|| eyre!("Unexpected summary response: {resp}"))?
+        .trim();
+    Ok(summary.to_string())
+}
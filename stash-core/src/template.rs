@@ -0,0 +1,28 @@
+/// Render a template by replacing `{name}` placeholders with their values.
+/// Placeholders without a value are left as-is.
+pub fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (end, *value))
+        });
+        match value {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,124 @@
+//! Download rate limits (`[bandwidth]` in config.toml), so that big
+//! batches don't saturate a metered or shared connection: one across
+//! every download, and lower ones for particular domains. They apply to
+//! pages and their assets as they're read, and are shared by everything
+//! downloading at once. How many downloads run at once is capped too
+//! (`fetches` under `[jobs]`), counting pages and assets alike.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::{Condvar, LazyLock, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use url::Url;
+
+use crate::config::{self, Bandwidth};
+
+/// The most read at once, so downloads are paced smoothly.
+const CHUNK: usize = 16 * 1024;
+
+static BANDWIDTH: RwLock<Option<Bandwidth>> = RwLock::new(None);
+
+/// When each limit allows reading again, by domain (`""` for the overall one).
+static SCHEDULE: LazyLock<Mutex<HashMap<String, Instant>>> = LazyLock::new(Mutex::default);
+
+/// Downloads under way, and how many are allowed at once.
+static DOWNLOADS: Mutex<(usize, usize)> = Mutex::new((0, usize::MAX));
+static DOWNLOAD_DONE: Condvar = Condvar::new();
+
+/// Set how many downloads can run at once, until it's set again.
+pub fn init_downloads(max: usize) {
+    DOWNLOADS.lock().expect("Lock").1 = max.max(1);
+    DOWNLOAD_DONE.notify_all();
+}
+
+/// Wait until there's room for another download, which it takes up
+/// until the returned `Download` is dropped. Those held at once by
+/// the same thread would wait on each other, so they mustn't be.
+pub fn download() -> Download {
+    let downloads = DOWNLOADS.lock().expect("Lock");
+    let mut downloads = DOWNLOAD_DONE
+        .wait_while(downloads, |(running, max)| *running >= *max)
+        .expect("Lock");
+    downloads.0 += 1;
+    Download(())
+}
+
+/// A download under way, see `download`.
+pub struct Download(());
+impl Drop for Download {
+    fn drop(&mut self) {
+        DOWNLOADS.lock().expect("Lock").0 -= 1;
+        DOWNLOAD_DONE.notify_one();
+    }
+}
+
+/// Set the rate limits, until they're set again (e.g. when the config's
+/// reloaded). Downloads already under way keep the limits they started with.
+pub fn init(bandwidth: Bandwidth) {
+    *BANDWIDTH.write().expect("Lock") = Some(bandwidth);
+}
+
+/// Limit reading a download from `url` to the rates that apply to it.
+pub fn reader<R: Read>(url: &str, inner: R) -> Throttled<R> {
+    let mut limits = vec![];
+    if let Some(bandwidth) = BANDWIDTH.read().expect("Lock").as_ref() {
+        if let Some(limit) = bandwidth.limit {
+            limits.push((String::new(), limit));
+        }
+        if let Some(host) = Url::parse(url).ok().as_ref().and_then(Url::host_str) {
+            limits.extend(
+                bandwidth
+                    .domains
+                    .iter()
+                    .filter(|(domain, _)| config::on_domain(host, domain))
+                    .map(|(domain, limit)| (domain.clone(), *limit)),
+            );
+        }
+    }
+    Throttled { inner, limits }
+}
+
+pub struct Throttled<R> {
+    inner: R,
+
+    /// The limits that apply, in kilobytes a second, by domain.
+    limits: Vec<(String, u64)>,
+}
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.limits.is_empty() {
+            return self.inner.read(buf);
+        }
+        let len = buf.len().min(CHUNK);
+        let read = self.inner.read(&mut buf[..len])?;
+        wait(&self.limits, read);
+        Ok(read)
+    }
+}
+
+/// Account for `read` bytes under each limit,
+/// and wait until the most behind of them allows reading more.
+fn wait(limits: &[(String, u64)], read: usize) {
+    let now = Instant::now();
+    let until = {
+        let mut schedule = SCHEDULE.lock().expect("Lock");
+        limits
+            .iter()
+            .map(|(domain, limit)| {
+                let next = schedule.entry(domain.clone()).or_insert(now);
+                let rate = (*limit).max(1) as f64 * 1024.0;
+                *next = (*next).max(now) + Duration::from_secs_f64(read as f64 / rate);
+                *next
+            })
+            .max()
+    };
+    if let Some(until) = until
+        && until > now
+    {
+        thread::sleep(until - now);
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use serde_json::{Value, json};
+
+use crate::{
+    Article,
+    config::{Translation, TranslationBackend},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const DEEPL_URL: &str = "https://api.deepl.com";
+const DEEPL_FREE_URL: &str = "https://api-free.deepl.com";
+
+/// Translate an article's title and content (keeping its markup)
+/// into a language, e.g. `en`, recording the language it was in.
+/// Articles already in the language are left alone.
+pub fn translate(config: &Translation, article: &mut Article, target: &str) -> Result<()> {
+    let primary = |lang: &str| {
+        lang.split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+    if primary(&article.lang) == primary(target) {
+        return Ok(());
+    }
+
+    let (content, detected) = config.translate(&article.content, target, true)?;
+    let (title, _) = config.translate(&article.title, target, false)?;
+    article.content = content;
+    article.title = title;
+    article.translated_from = detected.unwrap_or_else(|| article.lang.clone());
+    article.lang = target.to_string();
+    Ok(())
+}
+
+impl Translation {
+    /// Returns the translation and, if the backend said, the source language.
+    fn translate(&self, text: &str, target: &str, html: bool) -> Result<(String, Option<String>)> {
+        if text.is_empty() {
+            return Ok((String::new(), None));
+        }
+        match self.backend {
+            TranslationBackend::LibreTranslate => {
+                let Some(url) = &self.url else {
+                    bail!("LibreTranslate needs a `url` under `[translation]`.");
+                };
+                let url = format!("{}/translate", url.trim_end_matches('/'));
+                let resp: Value = ureq::post(&url)
+                    .timeout(TIMEOUT)
+                    .send_json(json!({
+                        "q": text,
+                        "source": "auto",
+                        "target": target,
+                        "format": if html { "html" } else { "text" },
+                        "api_key": self.api_key.as_deref().unwrap_or_default(),
+                    }))
+                    .wrap_err("Translation request failed")?
+                    .into_json()?;
+                let translated = resp["translatedText"]
+                    .as_str()
+                    .ok_or_else(|| eyre!("Unexpected translation response: {resp}"))?;
+                let detected = resp["detectedLanguage"]["language"].as_str();
+                Ok((translated.to_string(), detected.map(String::from)))
+            }
+            TranslationBackend::DeepL => {
+                let Some(key) = &self.api_key else {
+                    bail!("DeepL needs an `api_key` under `[translation]`.");
+                };
+                // Keys for the free API are marked with `:fx`.
+                let base = self.url.as_deref().unwrap_or(if key.ends_with(":fx") {
+                    DEEPL_FREE_URL
+                } else {
+                    DEEPL_URL
+                });
+                let mut body = json!({
+                    "text": [text],
+                    "target_lang": target.to_uppercase(),
+                });
+                if html {
+                    body["tag_handling"] = json!("html");
+                }
+                let resp: Value =
+                    ureq::post(&format!("{}/v2/translate", base.trim_end_matches('/')))
+                        .timeout(TIMEOUT)
+                        .set("Authorization", &format!("DeepL-Auth-Key {key}"))
+                        .send_json(body)
+                        .wrap_err("Translation request failed")?
+                        .into_json()?;
+                let translation = &resp["translations"][0];
+                let translated = translation["text"]
+                    .as_str()
+                    .ok_or_else(|| eyre!("Unexpected translation response: {resp}"))?;
+                let detected = translation["detected_source_language"]
+                    .as_str()
+                    .map(str::to_lowercase);
+                Ok((translated.to_string(), detected))
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use scraper::{Html, Node};
+
+/// Elements whose text is left exactly as it is.
+const VERBATIM_ELEMENTS: &[&str] = &["code", "kbd", "pre", "samp", "script", "style", "textarea"];
+
+/// Units that shouldn't be separated from the number before them.
+const UNITS: &[&str] = &[
+    "%", "°C", "°F", "km", "m", "cm", "mm", "kg", "g", "mg", "l", "ml", "mi", "ft", "lb", "lbs",
+    "oz", "kWh", "kW", "W", "V", "Hz", "kHz", "MHz", "GHz", "KB", "MB", "GB", "TB", "ms", "min",
+];
+
+const NBSP: char = '\u{a0}';
+const NARROW_NBSP: char = '\u{202f}';
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// A language's quotation marks: double open/close, single open/close.
+struct Quotes(char, char, char, char);
+
+fn quotes(lang: &str) -> Quotes {
+    match lang {
+        "de" => Quotes('„', '“', '‚', '‘'),
+        "fr" | "es" | "it" | "pt" => Quotes('«', '»', '“', '”'),
+        "ru" => Quotes('«', '»', '„', '“'),
+        "sv" | "fi" => Quotes('”', '”', '’', '’'),
+        _ => Quotes('“', '”', '‘', '’'),
+    }
+}
+
+/// Polish the text of an HTML fragment for a book: smart quotes,
+/// em/en dashes, ellipses, non-breaking spaces before units
+/// (and, for French, before `;:!?` and inside guillemets),
+/// without soft hyphens. Code and the like are left alone.
+///
+/// `lang` is a language tag like `en-GB`; only the language is used.
+pub fn polish(html: &str, lang: &str) -> String {
+    let lang = lang
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut doc = Html::parse_fragment(html);
+    let texts: Vec<_> = doc
+        .root_element()
+        .descendants()
+        .filter(|node| node.value().is_text())
+        .filter(|node| {
+            !node.ancestors().any(|ancestor| {
+                ancestor
+                    .value()
+                    .as_element()
+                    .is_some_and(|el| VERBATIM_ELEMENTS.contains(&el.name()))
+            })
+        })
+        .map(|node| node.id())
+        .collect();
+
+    let mut polisher = Polisher {
+        lang: &lang,
+        quotes: quotes(&lang),
+        prev: None,
+        single_open: false,
+    };
+    for id in texts {
+        if let Some(mut node) = doc.tree.get_mut(id)
+            && let Node::Text(text) = node.value()
+        {
+            let polished = polisher.text(text);
+            text.text = polished.into();
+        }
+    }
+    doc.root_element().inner_html()
+}
+
+struct Polisher<'a> {
+    lang: &'a str,
+    quotes: Quotes,
+
+    /// The last character of the previous text,
+    /// as quotes can open or close across elements.
+    prev: Option<char>,
+
+    /// Whether a single quote has been opened,
+    /// to tell a closing one from an apostrophe.
+    single_open: bool,
+}
+impl Polisher<'_> {
+    fn text(&mut self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().filter(|c| *c != SOFT_HYPHEN).collect();
+        let mut out = String::with_capacity(text.len());
+        let french = self.lang == "fr";
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            let prev = out.chars().last().or(self.prev);
+            let opening = prev.is_none_or(|p| {
+                p.is_whitespace()
+                    || matches!(p, '(' | '[' | '{' | '-' | '–' | '—' | '/')
+                    || p == self.quotes.0
+                    || p == self.quotes.2
+            });
+            match c {
+                '.' if chars[i..].starts_with(&['.', '.', '.']) => {
+                    out.push('…');
+                    i += 2;
+                }
+                '-' if next == Some('-') => {
+                    out.push('—');
+                    i += if chars.get(i + 2) == Some(&'-') { 2 } else { 1 };
+                }
+                '-' if prev.is_some_and(char::is_whitespace)
+                    && next.is_some_and(char::is_whitespace) =>
+                {
+                    out.push('–');
+                }
+                '"' if opening => {
+                    out.push(self.quotes.0);
+                    if french {
+                        out.push(NBSP);
+                        while chars.get(i + 1).is_some_and(|c| c.is_whitespace()) {
+                            i += 1;
+                        }
+                    }
+                }
+                '"' => {
+                    if french {
+                        trim_end_whitespace(&mut out);
+                        out.push(NBSP);
+                    }
+                    out.push(self.quotes.1);
+                }
+                '\'' if opening => {
+                    out.push(self.quotes.2);
+                    self.single_open = true;
+                }
+                '\'' if self.single_open && !next.is_some_and(char::is_alphanumeric) => {
+                    out.push(self.quotes.3);
+                    self.single_open = false;
+                }
+                // Apostrophes are always `’`, whatever the language's quotes.
+                '\'' => out.push('’'),
+                ' ' if prev.is_some_and(|p| p.is_ascii_digit())
+                    && UNITS.iter().any(|unit| unit_at(&chars[i + 1..], unit)) =>
+                {
+                    out.push(NBSP);
+                }
+                ' ' if french && matches!(next, Some(';' | ':' | '!' | '?')) => {
+                    out.push(NARROW_NBSP);
+                }
+                c => out.push(c),
+            }
+            i += 1;
+        }
+        if let Some(last) = out.chars().last() {
+            self.prev = Some(last);
+        }
+        out
+    }
+}
+
+/// Whether `chars` start with the unit as a whole word.
+fn unit_at(chars: &[char], unit: &str) -> bool {
+    let unit: Vec<char> = unit.chars().collect();
+    chars.starts_with(&unit) && chars.get(unit.len()).is_none_or(|c| !c.is_alphanumeric())
+}
+
+fn trim_end_whitespace(out: &mut String) {
+    let len = out.trim_end().len();
+    out.truncate(len);
+}
@@ -0,0 +1,172 @@
+use std::{path::Path, time::Duration};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    config::{Config, S3, WebDav},
+    logging,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Upload a newly written file to the configured remote targets,
+/// at its path relative to the output dir. Failures are warned about;
+/// returns whether there were any targets and they all worked.
+pub fn upload(config: &Config, path: &Path) -> bool {
+    let relative = path.strip_prefix(config.output_dir()).unwrap_or(path);
+    let mut uploaded = None;
+    let mut report = |target: &str, result: Result<()>| {
+        if let Err(err) = &result {
+            logging::warn(format!("Couldn't upload to {target}: {err:#}"));
+        }
+        uploaded = Some(uploaded.unwrap_or(true) && result.is_ok());
+    };
+    if let Some(webdav) = &config.webdav {
+        report("WebDAV", webdav_put(webdav, relative, path));
+    }
+    if let Some(s3) = &config.s3 {
+        report("S3", s3_put(s3, relative, path));
+    }
+    uploaded.unwrap_or(false)
+}
+
+fn webdav_put(config: &WebDav, relative: &Path, path: &Path) -> Result<()> {
+    let base = Url::parse(&config.url).wrap_err("Invalid WebDAV URL")?;
+    if base.cannot_be_a_base() {
+        bail!("Invalid WebDAV URL: {}", config.url);
+    }
+    let auth = format!(
+        "Basic {}",
+        BASE64_STANDARD.encode(format!("{}:{}", config.username, config.password))
+    );
+    let url_for = |parts: &[String], dir: bool| {
+        let mut url = base.clone();
+        {
+            let mut segments = url.path_segments_mut().expect("URL can be a base");
+            segments.pop_if_empty().extend(parts);
+            if dir {
+                segments.push("");
+            }
+        }
+        url
+    };
+
+    // Create any subdirectories (e.g. from the filename template);
+    // servers refuse to create ones that already exist, which is fine.
+    let parts: Vec<_> = relative
+        .iter()
+        .map(|part| part.to_string_lossy().to_string())
+        .collect();
+    for i in 1..parts.len() {
+        let url = url_for(&parts[..i], true);
+        let resp = ureq::request("MKCOL", url.as_str())
+            .timeout(TIMEOUT)
+            .set("Authorization", &auth)
+            .call();
+        match resp {
+            Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+            Err(err) => return Err(err).wrap_err("Couldn't create a folder"),
+        }
+    }
+
+    let url = url_for(&parts, false);
+    ureq::put(url.as_str())
+        .timeout(TIMEOUT)
+        .set("Authorization", &auth)
+        .set("Content-Type", "application/epub+zip")
+        .send_bytes(&fs_err::read(path)?)
+        .wrap_err("Upload failed")?;
+    Ok(())
+}
+
+/// Upload with a path-style URL (`{endpoint}/{bucket}/{key}`),
+/// which MinIO and friends expect, signed with AWS Signature Version 4.
+fn s3_put(config: &S3, relative: &Path, path: &Path) -> Result<()> {
+    let endpoint = Url::parse(&config.endpoint).wrap_err("Invalid S3 endpoint")?;
+    let Some(host) = endpoint.host_str() else {
+        bail!("Invalid S3 endpoint: {}", config.endpoint);
+    };
+    let host = match endpoint.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    };
+    let key = relative
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let key = format!("{}{key}", config.prefix);
+    let uri = std::iter::once(config.bucket.as_str())
+        .chain(key.split('/'))
+        .map(encode)
+        .fold(String::new(), |uri, part| uri + "/" + part.as_str());
+
+    let body = fs_err::read(path)?;
+    let payload_hash = hex::encode(Sha256::digest(&body));
+    let now = Utc::now();
+    let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), config.region);
+    let content_type = "application/epub+zip";
+
+    let signed_headers = "content-type;host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "PUT\n{uri}\n\ncontent-type:{content_type}\nhost:{host}\n\
+         x-amz-content-sha256:{payload_hash}\nx-amz-date:{timestamp}\n\n\
+         {signed_headers}\n{payload_hash}"
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request))
+    );
+    let signing_key = [
+        now.format("%Y%m%d").to_string().as_str(),
+        &config.region,
+        "s3",
+        "aws4_request",
+    ]
+    .iter()
+    .fold(
+        format!("AWS4{}", config.secret_key).into_bytes(),
+        |key, part| hmac(&key, part),
+    );
+    let signature = hex::encode(hmac(&signing_key, &string_to_sign));
+    let auth = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        config.access_key
+    );
+
+    let base = config.endpoint.trim_end_matches('/');
+    ureq::put(&format!("{base}{uri}"))
+        .timeout(TIMEOUT)
+        .set("Authorization", &auth)
+        .set("Content-Type", content_type)
+        .set("x-amz-content-sha256", &payload_hash)
+        .set("x-amz-date", &timestamp)
+        .send_bytes(&body)
+        .wrap_err("Upload failed")?;
+    Ok(())
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode everything but unreserved characters, as S3 does
+/// when checking signatures.
+fn encode(part: &str) -> String {
+    part.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
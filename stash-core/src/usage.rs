@@ -0,0 +1,102 @@
+//! Local usage statistics, to find the sites whose extraction needs work:
+//! how each domain's articles were extracted, and how often stashing them
+//! failed. Kept as `usage.json` in the data dir, and never sent anywhere.
+
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{Report, eyre::Result};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{Article, config, crypt, failure::Failure};
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct DomainUsage {
+    /// Articles stashed, by how they were extracted (see `Article::extraction`).
+    #[serde(default)]
+    pub stashed: BTreeMap<String, u32>,
+
+    /// Failures, by their class: `network`, `extraction`, `output`, or `other`.
+    #[serde(default)]
+    pub failed: BTreeMap<String, u32>,
+
+    pub last_used: DateTime<Utc>,
+}
+impl DomainUsage {
+    pub fn stashed_total(&self) -> u32 {
+        self.stashed.values().sum()
+    }
+
+    pub fn failed_total(&self) -> u32 {
+        self.failed.values().sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub struct Usage {
+    pub domains: BTreeMap<String, DomainUsage>,
+}
+impl Usage {
+    pub fn path() -> PathBuf {
+        config::data_dir().join("usage.json")
+    }
+
+    pub fn load() -> Result<Self> {
+        match crypt::read_to_string(&Self::path()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crypt::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Count a stashed article under its domain and extraction.
+    pub fn stashed(article: &Article) -> Result<()> {
+        let extraction = match article.extraction.as_str() {
+            "" => "unknown",
+            extraction => extraction,
+        };
+        Self::update(&article.url, |usage| {
+            *usage.stashed.entry(extraction.to_string()).or_default() += 1;
+        })
+    }
+
+    /// Count a URL that couldn't be stashed under its domain.
+    pub fn failed(url: &str, err: &Report) -> Result<()> {
+        let class = match Failure::of(err) {
+            Some(Failure::Network) => "network",
+            Some(Failure::Extraction) => "extraction",
+            Some(Failure::Output) => "output",
+            Some(Failure::Aborted) | None => "other",
+        };
+        Self::update(url, |usage| {
+            *usage.failed.entry(class.to_string()).or_default() += 1;
+        })
+    }
+
+    fn update(url: &str, count: impl FnOnce(&mut DomainUsage)) -> Result<()> {
+        // Newsletters from `stash mail` have no domain.
+        let Some(domain) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|domain| domain.to_string()))
+        else {
+            return Ok(());
+        };
+        let _lock = config::lock_data();
+        let mut usage = Self::load()?;
+        let entry = usage.domains.entry(domain).or_default();
+        count(entry);
+        entry.last_used = Utc::now();
+        usage.save()
+    }
+}
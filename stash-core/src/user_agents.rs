@@ -0,0 +1,68 @@
+//! Which of the user agents last worked for each domain, so that pages
+//! from sites that refuse the first are fetched with the one they accept
+//! first, rather than failing a request every time. Kept in
+//! `user-agents.json` in the cache dir.
+
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use url::Url;
+
+use crate::{USER_AGENTS, config, crypt, logging};
+
+/// The user agents by domain, once they've been read.
+static WORKED: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+fn path() -> PathBuf {
+    config::cache_dir().join("user-agents.json")
+}
+
+/// The user agents to try for a URL, in order:
+/// the one that last worked for its domain first.
+pub fn ordered(url: &str) -> Vec<&'static str> {
+    let mut agents = USER_AGENTS.to_vec();
+    let Some(domain) = domain(url) else {
+        return agents;
+    };
+    let mut worked = WORKED.lock().expect("Lock");
+    let last = worked.get_or_insert_with(load).get(&domain);
+    if let Some(i) = last.and_then(|last| agents.iter().position(|ua| ua == last)) {
+        let ua = agents.remove(i);
+        agents.insert(0, ua);
+    }
+    agents
+}
+
+/// Remember that a user agent worked for a URL's domain.
+/// Failures are only warned about, as the page's been fetched regardless.
+pub fn worked(url: &str, ua: &str) {
+    let Some(domain) = domain(url) else {
+        return;
+    };
+    let mut worked = WORKED.lock().expect("Lock");
+    let worked = worked.get_or_insert_with(load);
+    if worked.get(&domain).map(String::as_str) == Some(ua) {
+        return;
+    }
+    worked.insert(domain, ua.to_string());
+    let saved = serde_json::to_string_pretty(worked)
+        .map_err(std::io::Error::other)
+        .and_then(|json| {
+            fs_err::create_dir_all(config::cache_dir())?;
+            crypt::write(&path(), json)
+        });
+    if let Err(err) = saved {
+        logging::warn(format!("Couldn't remember the user agent for {url}: {err}"));
+    }
+}
+
+/// What's been remembered; nothing if it can't be read.
+fn load() -> HashMap<String, String> {
+    crypt::read_to_string(&path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn domain(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
+}
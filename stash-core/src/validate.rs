@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::Path,
+};
+
+use color_eyre::eyre::{Result, bail};
+use quick_xml::{Reader, XmlVersion, events::Event};
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::{config::Validation, logging};
+
+const MIMETYPE: &str = "application/epub+zip";
+
+/// Entities XML has without a DTD, which XHTML content documents can't rely on.
+const XML_ENTITIES: &[&str] = &["amp", "lt", "gt", "quot", "apos"];
+
+/// Validate a newly built book (at `path`, to be moved to `dest`) as
+/// configured: warning about any problems, or with `validate = "fail"`,
+/// failing so that it isn't kept.
+pub fn check(path: &Path, dest: &Path, mode: Validation) -> Result<()> {
+    if mode == Validation::Off {
+        return Ok(());
+    }
+    let problems = validate(path)?;
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let report = problems.join("\n  ");
+    if mode == Validation::Fail {
+        bail!("The EPUB failed validation:\n  {report}");
+    }
+    logging::warn(format!(
+        "{} failed validation, some readers may reject it:\n  {report}",
+        dest.display()
+    ));
+    Ok(())
+}
+
+/// Check an EPUB's structure: that `mimetype` comes first (and uncompressed),
+/// that the package document's manifest and spine agree with each other and
+/// the files in the book, and that its content documents are well-formed.
+pub fn validate(path: &Path) -> Result<Vec<String>> {
+    let mut zip = ZipArchive::new(fs_err::File::open(path)?)?;
+    let mut problems = vec![];
+
+    match zip.by_index(0) {
+        Ok(mut first) if first.name() == "mimetype" => {
+            if first.compression() != CompressionMethod::Stored {
+                problems.push("`mimetype` is compressed".to_string());
+            }
+            let mut mimetype = String::new();
+            first.read_to_string(&mut mimetype)?;
+            if mimetype != MIMETYPE {
+                problems.push(format!("`mimetype` is `{mimetype}`, not `{MIMETYPE}`"));
+            }
+        }
+        _ => problems.push("`mimetype` isn't the first file".to_string()),
+    }
+
+    let Some(container) = document(&mut zip, "META-INF/container.xml", &mut problems) else {
+        return Ok(problems);
+    };
+    let Some(opf_path) = container
+        .iter()
+        .find(|(name, _)| name == "rootfile")
+        .and_then(|(_, attrs)| attrs.get("full-path"))
+    else {
+        problems.push("META-INF/container.xml: no `rootfile`".to_string());
+        return Ok(problems);
+    };
+    let Some(opf) = document(&mut zip, opf_path, &mut problems) else {
+        return Ok(problems);
+    };
+
+    // Manifest hrefs are relative to the package document.
+    let dir = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut manifest = HashMap::new();
+    for (_, attrs) in opf.iter().filter(|(name, _)| name == "item") {
+        let (Some(id), Some(href)) = (attrs.get("id"), attrs.get("href")) else {
+            problems.push(format!("{opf_path}: a manifest item without an id or href"));
+            continue;
+        };
+        let href = href.split('#').next().unwrap_or_default();
+        let file = if dir.is_empty() {
+            href.to_string()
+        } else {
+            format!("{dir}/{href}")
+        };
+        let media_type = attrs.get("media-type").cloned().unwrap_or_default();
+        if manifest.insert(id.clone(), (file, media_type)).is_some() {
+            problems.push(format!(
+                "{opf_path}: manifest id `{id}` is used more than once"
+            ));
+        }
+    }
+
+    let spine: Vec<_> = opf
+        .iter()
+        .filter(|(name, _)| name == "itemref")
+        .filter_map(|(_, attrs)| attrs.get("idref"))
+        .collect();
+    if spine.is_empty() {
+        problems.push(format!("{opf_path}: the spine is empty"));
+    }
+    for idref in spine {
+        if !manifest.contains_key(idref) {
+            problems.push(format!(
+                "{opf_path}: spine item `{idref}` isn't in the manifest"
+            ));
+        }
+    }
+
+    let files: HashSet<String> = zip.file_names().map(str::to_string).collect();
+    let mut items: Vec<_> = manifest.values().collect();
+    items.sort();
+    for (file, media_type) in items {
+        if !files.contains(file) {
+            problems.push(format!("{opf_path}: manifest item `{file}` is missing"));
+        } else if media_type == "application/xhtml+xml" || media_type == "application/x-dtbncx+xml"
+        {
+            document(&mut zip, file, &mut problems);
+        }
+    }
+    Ok(problems)
+}
+
+/// Read and parse an XML file in the book, noting if it's missing or malformed.
+fn document(
+    zip: &mut ZipArchive<fs_err::File>,
+    name: &str,
+    problems: &mut Vec<String>,
+) -> Option<Vec<Element>> {
+    let mut xml = String::new();
+    match zip.by_name(name) {
+        Ok(mut file) => {
+            if let Err(err) = file.read_to_string(&mut xml) {
+                problems.push(format!("{name}: {err}"));
+                return None;
+            }
+        }
+        Err(_) => {
+            problems.push(format!("{name} is missing"));
+            return None;
+        }
+    }
+    parse(&xml)
+        .inspect_err(|err| problems.push(format!("{name}: not well-formed: {err}")))
+        .ok()
+}
+
+/// An element's local name and attributes.
+type Element = (String, HashMap<String, String>);
+
+/// Parse an XML document into its elements, checking that it's well-formed.
+fn parse(xml: &str) -> Result<Vec<Element>, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().check_end_names = true;
+    let mut elements = vec![];
+    let mut depth = 0;
+    let mut roots = 0;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|err| format!("{err} (at byte {})", reader.error_position()))?;
+        let start = match &event {
+            Event::Start(start) | Event::Empty(start) => start,
+            Event::End(_) => {
+                depth -= 1;
+                continue;
+            }
+            Event::GeneralRef(entity) => {
+                let name: &str = entity.as_ref();
+                let known = if entity.is_char_ref() {
+                    matches!(entity.resolve_char_ref(), Ok(Some(_)))
+                } else {
+                    XML_ENTITIES.contains(&name)
+                };
+                if !known {
+                    return Err(format!("undefined entity `&{name};`"));
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if depth == 0 {
+            roots += 1;
+        }
+        if matches!(event, Event::Start(_)) {
+            depth += 1;
+        }
+        let mut attrs = HashMap::new();
+        for attr in start.attributes() {
+            let attr = attr.map_err(|err| err.to_string())?;
+            let value = attr
+                .normalized_value(XmlVersion::Implicit1_0)
+                .map_err(|err| err.to_string())?;
+            let name = attr.key.local_name();
+            attrs.insert(AsRef::<str>::as_ref(&name).to_string(), value.to_string());
+        }
+        let name = start.local_name();
+        elements.push((AsRef::<str>::as_ref(&name).to_string(), attrs));
+    }
+    if depth > 0 {
+        return Err("unclosed elements at the end".to_string());
+    }
+    if roots != 1 {
+        return Err(format!("{roots} root elements instead of one"));
+    }
+    Ok(elements)
+}
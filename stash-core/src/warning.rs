@@ -0,0 +1,41 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+/// Something that may have gone wrong extracting an article, though it
+/// was extracted. These are kept with the article (`Article::warnings`)
+/// to be shown together: in the preview, or in one `warnings` event
+/// when there's none.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// A site config's selector for a field (e.g. `authors`)
+    /// matched nothing, so it's left empty.
+    MissingElement {
+        field: &'static str,
+        selector: String,
+    },
+
+    /// The publication date couldn't be parsed, so it's kept as it was.
+    UnparsableDate { date: String },
+
+    /// The chosen extraction failed, so another was used.
+    Fallback { method: String, error: String },
+
+    /// The body is suspiciously short or long (see `[length]`).
+    Length { problem: String },
+}
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingElement { field, selector } => {
+                write!(f, "Nothing matched the {field} selector `{selector}`")
+            }
+            Self::UnparsableDate { date } => write!(f, "Couldn't parse the date `{date}`"),
+            Self::Fallback { method, error } => {
+                write!(f, "{method} extraction failed: {error}")
+            }
+            Self::Length { problem } => write!(f, "{problem}"),
+        }
+    }
+}
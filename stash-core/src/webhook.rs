@@ -0,0 +1,89 @@
+use std::{path::Path, time::Duration};
+
+use serde::Serialize;
+
+use crate::{USER_AGENTS, config::Webhook, logging, template};
+
+/// Give up on a webhook that takes longer than this,
+/// rather than holding up the next article.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What became of a stashed URL.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+
+    /// An output file already existed and was left alone.
+    Skipped,
+    Failed,
+}
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Report<'a> {
+    pub status: Status,
+    pub url: &'a str,
+
+    /// Empty if the article couldn't be extracted.
+    pub title: &'a str,
+    pub path: Option<&'a Path>,
+    pub error: Option<String>,
+}
+
+/// POST a report to the webhook.
+///
+/// With a `payload` template, its `{status}`, `{url}`, `{title}`,
+/// `{path}`, and `{error}` placeholders are filled in, escaped
+/// for use inside JSON strings; otherwise the report is sent as JSON.
+/// Problems with the webhook are only warned about.
+pub fn notify(webhook: &Webhook, report: &Report) {
+    if webhook.failures_only && report.status != Status::Failed {
+        return;
+    }
+    let body = match &webhook.payload {
+        Some(payload) => {
+            let path = report
+                .path
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            let vars = [
+                ("status", report.status.as_str()),
+                ("url", report.url),
+                ("title", report.title),
+                ("path", &path),
+                ("error", report.error.as_deref().unwrap_or_default()),
+            ]
+            .map(|(name, value)| (name, json_escape(value)));
+            let vars: Vec<_> = vars
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            template::render(payload, &vars)
+        }
+        None => serde_json::to_string(report).expect("Report is serializable"),
+    };
+
+    let resp = ureq::post(&webhook.url)
+        .timeout(TIMEOUT)
+        .set("User-Agent", USER_AGENTS[0])
+        .set("Content-Type", "application/json")
+        .send_string(&body);
+    if let Err(err) = resp {
+        logging::warn(format!("Webhook failed: {err}"));
+    }
+}
+
+/// Escape a value for use inside a JSON string.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("Strings are serializable");
+    quoted[1..quoted.len() - 1].to_string()
+}
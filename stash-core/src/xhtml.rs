@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Html, Node, node::Element};
+
+const HTML_NS: &str = "http://www.w3.org/1999/xhtml";
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+
+/// Elements that never have content, and so are self-closed.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// A section heading found in the content.
+pub struct Heading {
+    /// 2 for `<h2>`, 3 for `<h3>`.
+    pub level: u8,
+    pub id: String,
+    pub title: String,
+}
+
+/// Wrap an HTML fragment in a complete XHTML document
+/// which uses the EPUB's stylesheet.
+///
+/// EPUB content documents have to be well-formed XML,
+/// which extracted HTML usually isn't (unclosed `<br>`s etc.),
+/// so the fragment is re-serialized as XHTML.
+///
+/// The content is marked up as a chapter of the book's body matter
+/// (with `epub:type`), for reading systems' navigation and assistive tech.
+///
+/// This also returns the `<h2>` and `<h3>` headings, for navigation,
+/// giving them ids where they don't already have one.
+pub fn document(title: &str, body: &str) -> (String, Vec<Heading>) {
+    typed_document(title, body, "bodymatter", "chapter")
+}
+
+/// Like `document`, but for the book's colophon.
+pub fn colophon(title: &str, body: &str) -> String {
+    typed_document(title, body, "backmatter", "colophon").0
+}
+
+/// Like `document`, but for an appendix.
+pub fn appendix(title: &str, body: &str) -> String {
+    typed_document(title, body, "backmatter", "appendix").0
+}
+
+fn typed_document(title: &str, body: &str, matter: &str, section: &str) -> (String, Vec<Heading>) {
+    let mut writer = Writer::default();
+    writer.fragment(body);
+    let doc = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="{HTML_NS}" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{}</title>
+  <link rel="stylesheet" type="text/css" href="stylesheet.css"/>
+</head>
+<body epub:type="{matter}">
+<section epub:type="{section}">
+{}
+</section>
+</body>
+</html>
+"#,
+        escape_text(title),
+        writer.out
+    );
+    (doc, writer.headings)
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    headings: Vec<Heading>,
+
+    /// The ids in the fragment, so headings aren't given one of those.
+    ids: HashSet<String>,
+}
+impl Writer {
+    fn fragment(&mut self, html: &str) {
+        let html = Html::parse_fragment(html);
+        self.ids = html
+            .root_element()
+            .descendants()
+            .filter_map(|node| node.value().as_element()?.id().map(str::to_string))
+            .collect();
+        for child in html.root_element().children() {
+            self.node(child);
+        }
+    }
+
+    fn node(&mut self, node: NodeRef<Node>) {
+        match node.value() {
+            Node::Text(text) => self.out.push_str(&escape_text(text)),
+            Node::Comment(comment) => {
+                // `--` isn't allowed inside XML comments.
+                self.out.push_str("<!--");
+                self.out.push_str(&comment.replace("--", "- -"));
+                self.out.push_str("-->");
+            }
+            Node::Element(el) => {
+                let name = el.name();
+                self.out.push('<');
+                self.out.push_str(name);
+
+                // Foreign elements need their namespace declared
+                // where they stop being nested in the same namespace.
+                let ns = &*el.name.ns;
+                let parent_ns = node.parent().and_then(|parent| {
+                    parent.value().as_element().map(|el| el.name.ns.to_string())
+                });
+                if ns != HTML_NS && parent_ns.as_deref() != Some(ns) {
+                    self.out.push_str(&format!(r#" xmlns="{ns}""#));
+                    if ns == SVG_NS {
+                        self.out.push_str(&format!(r#" xmlns:xlink="{XLINK_NS}""#));
+                    }
+                }
+
+                if ns == HTML_NS && matches!(name, "h2" | "h3") {
+                    self.heading(node, el);
+                }
+
+                for (attr, value) in &el.attrs {
+                    let local = &*attr.local;
+                    if !is_xml_name(local) || local == "xmlns" || local.starts_with("xmlns:") {
+                        continue;
+                    }
+                    self.out.push(' ');
+                    if &*attr.ns == XLINK_NS {
+                        self.out.push_str("xlink:");
+                    }
+                    self.out.push_str(local);
+                    self.out.push_str("=\"");
+                    self.out.push_str(&escape_attr(value));
+                    self.out.push('"');
+                }
+
+                let is_void = ns == HTML_NS && VOID_ELEMENTS.contains(&name);
+                if is_void || (ns != HTML_NS && !node.has_children()) {
+                    self.out.push_str("/>");
+                    return;
+                }
+                self.out.push('>');
+                for child in node.children() {
+                    self.node(child);
+                }
+                self.out.push_str("</");
+                self.out.push_str(name);
+                self.out.push('>');
+            }
+            // Doctypes and processing instructions
+            // have no place inside a document body.
+            _ => {
+                for child in node.children() {
+                    self.node(child);
+                }
+            }
+        }
+    }
+
+    /// Record a heading, writing out an id attribute for it if it lacks one.
+    fn heading(&mut self, node: NodeRef<Node>, el: &Element) {
+        let title = node
+            .descendants()
+            .filter_map(|node| node.value().as_text().map(|text| &**text))
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if title.is_empty() {
+            return;
+        }
+        let id = match el.id() {
+            Some(id) => id.to_string(),
+            None => {
+                let id = (self.headings.len() + 1..)
+                    .map(|n| format!("section-{n}"))
+                    .find(|id| !self.ids.contains(id))
+                    .expect("An unused id");
+                self.ids.insert(id.clone());
+                self.out.push_str(&format!(r#" id="{id}""#));
+                id
+            }
+        };
+        let level = if el.name() == "h2" { 2 } else { 3 };
+        self.headings.push(Heading { level, id, title });
+    }
+}
+
+/// Split an HTML fragment into parts of at most `max` bytes where it
+/// can be, for readers that struggle with large content documents:
+/// between its sections (at `<h1>`–`<h3>`s), or for a section too large
+/// by itself, between its elements. Wrappers around the whole fragment
+/// (e.g. a `<div>` of it all) are left out, to split within them.
+pub fn split(html: &str, max: usize) -> Vec<String> {
+    if max == 0 || html.len() <= max {
+        return vec![html.to_string()];
+    }
+    let doc = Html::parse_fragment(html);
+    let mut root = doc.root_element();
+    loop {
+        let mut elements = root.children().filter(|child| match child.value() {
+            Node::Text(text) => !text.trim().is_empty(),
+            _ => true,
+        });
+        match (elements.next().and_then(ElementRef::wrap), elements.next()) {
+            (Some(only), None)
+                if matches!(only.value().name(), "div" | "article" | "section" | "main") =>
+            {
+                root = only;
+            }
+            _ => break,
+        }
+    }
+
+    let mut sections: Vec<Vec<String>> = vec![vec![]];
+    for child in root.children() {
+        let html = match child.value() {
+            Node::Text(text) => escape_text(text),
+            Node::Element(_) => ElementRef::wrap(child)
+                .map(|el| el.html())
+                .unwrap_or_default(),
+            _ => continue,
+        };
+        let is_heading = child
+            .value()
+            .as_element()
+            .is_some_and(|el| matches!(el.name(), "h1" | "h2" | "h3"));
+        if is_heading && sections.last().is_some_and(|section| !section.is_empty()) {
+            sections.push(vec![]);
+        }
+        sections.last_mut().expect("A section").push(html);
+    }
+
+    let mut parts = vec![String::new()];
+    for section in sections {
+        let len: usize = section.iter().map(String::len).sum();
+        let part = parts.last().expect("A part");
+        if !part.trim().is_empty() && part.len() + len > max {
+            parts.push(String::new());
+        }
+        for node in section {
+            let part = parts.last_mut().expect("A part");
+            if !part.trim().is_empty() && part.len() + node.len() > max {
+                parts.push(String::new());
+            }
+            parts.last_mut().expect("A part").push_str(&node);
+        }
+    }
+    parts
+}
+
+/// Whether a name is usable as an XML attribute name.
+/// This is stricter than XML itself, but HTML attributes
+/// that fail it (e.g. `@click`) aren't useful in an EPUB anyway.
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// Escape text for use in XML, also dropping
+/// control characters which XML doesn't allow at all.
+pub fn escape_text(s: &str) -> String {
+    s.replace(
+        |c: char| c.is_control() && !matches!(c, '\t' | '\n' | '\r'),
+        "",
+    )
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
+
+pub fn escape_attr(s: &str) -> String {
+    escape_text(s).replace('"', "&quot;")
+}